@@ -0,0 +1,97 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Sample;
+use gameboy::host::AudioSink;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Cap on the number of buffered stereo frames, so audio never lags far behind video.
+const MAX_BUFFERED_FRAMES: usize = 8192;
+
+/// Plays the emulator's audio through the default cpal output device.
+pub struct AudioOutput {
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    speed: f64,
+    _stream: cpal::Stream,
+}
+
+impl AudioOutput {
+    pub fn new() -> Self {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .expect("no audio output device available");
+        let mut supported_configs_range = device
+            .supported_output_configs()
+            .expect("error while querying configs");
+        let supported_config = supported_configs_range
+            .next()
+            .expect("no supported config?!")
+            .with_max_sample_rate();
+        let err_fn = |err| eprintln!("an error occurred on the output audio stream: {}", err);
+        let sample_rate = supported_config.sample_rate().0;
+        let channels = supported_config.channels() as usize;
+        let config = supported_config.into();
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let stream_buffer = buffer.clone();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut buffer = stream_buffer.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let (left, right) = match (buffer.pop_front(), buffer.pop_front()) {
+                            (Some(left), Some(right)) => (left, right),
+                            _ => (Sample::EQUILIBRIUM, Sample::EQUILIBRIUM),
+                        };
+                        if channels == 1 {
+                            frame[0] = (left + right) / 2.0;
+                        } else {
+                            for (i, sample) in frame.iter_mut().enumerate() {
+                                *sample = match i {
+                                    0 => left,
+                                    1 => right,
+                                    _ => Sample::EQUILIBRIUM,
+                                };
+                            }
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            )
+            .unwrap();
+        stream.play().unwrap();
+        Self {
+            buffer,
+            sample_rate,
+            speed: 1.0,
+            _stream: stream,
+        }
+    }
+
+    /// Sets the emulation speed. The emulator is asked for proportionally fewer samples per
+    /// emulated second, so playback at the device rate keeps audio and video in sync.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// Number of stereo frames waiting to be played.
+    pub fn buffered_frames(&self) -> usize {
+        self.buffer.lock().unwrap().len() / 2
+    }
+}
+
+impl AudioSink for AudioOutput {
+    fn sample_rate(&self) -> u32 {
+        (self.sample_rate as f64 / self.speed) as u32
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+        let overflow = buffer.len().saturating_sub(2 * MAX_BUFFERED_FRAMES);
+        buffer.drain(..overflow);
+    }
+}
@@ -0,0 +1,109 @@
+use gameboy::capture::{numbered_path, save_png, AudioDump, GifRecorder, VideoDump};
+use gameboy::host::{AudioSink, VideoSink};
+use log::{error, info};
+use std::path::Path;
+
+/// The GIF being recorded and the raw video dump, fed every presented frame.
+pub struct VideoCapture {
+    /// Size of the frames, see `GameBoy::screen_size`.
+    size: (usize, usize),
+    gif: Option<GifRecorder>,
+    dump: Option<VideoDump>,
+}
+
+impl VideoCapture {
+    pub fn new(size: (usize, usize)) -> Self {
+        Self {
+            size,
+            gif: None,
+            dump: None,
+        }
+    }
+
+    pub fn set_dump(&mut self, dump: VideoDump) {
+        self.dump = Some(dump);
+    }
+
+    pub fn start_gif(&mut self, path: &Path) -> Result<(), String> {
+        self.gif = Some(GifRecorder::create(path, self.size)?);
+        Ok(())
+    }
+
+    /// Starts recording a GIF next to the ROM, or stops and saves the one being recorded.
+    pub fn toggle_gif(&mut self, rom_path: &Path) {
+        if let Some(gif) = self.gif.take() {
+            match gif.finish() {
+                Ok(()) => info!("Stopped recording GIF"),
+                Err(e) => error!("{}", e),
+            }
+            return;
+        }
+        let path = numbered_path(rom_path, "gif");
+        match self.start_gif(&path) {
+            Ok(()) => info!("Recording GIF to {}", path.display()),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    pub fn finish(self) {
+        if let Some(Err(e)) = self.gif.map(GifRecorder::finish) {
+            error!("{}", e);
+        }
+        if let Some(Err(e)) = self.dump.map(VideoDump::finish) {
+            error!("{}", e);
+        }
+    }
+}
+
+impl VideoSink for VideoCapture {
+    fn present_frame(&mut self, frame: &[u32]) {
+        if let Some(gif) = self.gif.as_mut() {
+            if let Err(e) = gif.add_frame(frame) {
+                error!("{}", e);
+                self.gif = None;
+            }
+        }
+        if let Some(dump) = self.dump.as_mut() {
+            dump.present_frame(frame);
+        }
+    }
+}
+
+/// The audio dump, if any. Pair it with the sink that sets the sample rate.
+#[derive(Default)]
+pub struct AudioCapture {
+    dump: Option<AudioDump>,
+}
+
+impl AudioCapture {
+    pub fn set_dump(&mut self, dump: AudioDump) {
+        self.dump = Some(dump);
+    }
+
+    pub fn finish(self) {
+        if let Some(Err(e)) = self.dump.map(AudioDump::finish) {
+            error!("{}", e);
+        }
+    }
+}
+
+impl AudioSink for AudioCapture {
+    fn sample_rate(&self) -> u32 {
+        self.dump.as_ref().map_or(48_000, AudioDump::sample_rate)
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        if let Some(dump) = self.dump.as_mut() {
+            dump.push_samples(samples);
+        }
+    }
+}
+
+/// Saves a screenshot next to the ROM.
+pub fn take_screenshot(rom_path: &Path, frame: &[u32], size: (usize, usize)) {
+    let path = numbered_path(rom_path, "png");
+    match save_png(&path, frame, size) {
+        Ok(()) => info!("Saved screenshot to {}", path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
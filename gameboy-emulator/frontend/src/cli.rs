@@ -0,0 +1,134 @@
+use crate::pacing::SyncMode;
+use clap::Parser;
+use gameboy::ppu::DmgPalette;
+use gameboy::serial::LinkSpec;
+use log::LevelFilter;
+use minifb::Scale;
+use std::path::PathBuf;
+
+/// Game Boy and Game Boy Color emulator.
+#[derive(Parser)]
+#[command(version)]
+pub struct Args {
+    /// Cartridge ROM, optionally in a zip archive or gzipped.
+    pub rom: PathBuf,
+
+    /// Boot ROM to run before the cartridge. Without one, emulation starts at the
+    /// cartridge entry point with the registers the boot ROM leaves behind.
+    #[arg(long)]
+    pub boot_rom: Option<PathBuf>,
+
+    /// Run cartridges with a bad logo or header checksum, which hardware refuses.
+    #[arg(long)]
+    pub ignore_header: bool,
+
+    /// Run as a Super Game Boy: games get its colours and, if they have one, their border.
+    /// CGB games run in DMG mode.
+    #[arg(long)]
+    pub sgb: bool,
+
+    /// Window scale: 1, 2, 4, 8, 16 or 32.
+    #[arg(long, default_value = "8", value_parser = parse_scale)]
+    pub scale: Scale,
+
+    /// Colours of DMG games: grey, green or pocket.
+    #[arg(long, default_value = "grey", value_parser = parse_palette)]
+    pub palette: DmgPalette,
+
+    /// Run this many frames without a window or audio, then exit.
+    #[arg(long)]
+    pub frames: Option<u32>,
+
+    /// Save a PNG of the last frame on exit.
+    #[arg(long)]
+    pub screenshot: Option<PathBuf>,
+
+    /// Record an animated GIF from the start until exit.
+    #[arg(long)]
+    pub gif: Option<PathBuf>,
+
+    /// Write every frame as raw RGB24 to this file, or to stdout if it is '-'.
+    #[arg(long)]
+    pub dump_video: Option<PathBuf>,
+
+    /// Write the audio as 16-bit stereo to this file: WAV if it ends in .wav, raw
+    /// little-endian PCM otherwise, or to stdout if it is '-'.
+    #[arg(long)]
+    pub dump_audio: Option<PathBuf>,
+
+    /// Sample rate of --dump-audio without a window. With one, the audio device's rate is
+    /// used.
+    #[arg(long, default_value_t = 48_000)]
+    pub sample_rate: u32,
+
+    /// Link cable: none, loopback, stdout (for test ROMs that print their results),
+    /// listen:<addr> to wait for another instance, or connect:<addr>.
+    #[arg(long, default_value = "none", value_parser = LinkSpec::parse)]
+    pub link: LinkSpec,
+
+    /// Frame pacing: audio, which follows the audio device, or timer.
+    #[arg(long, default_value = "audio", value_parser = parse_sync)]
+    pub sync: SyncMode,
+
+    /// Memory for rewind snapshots, in MiB.
+    #[arg(long, default_value_t = 64)]
+    pub rewind_mb: usize,
+
+    /// Button mapping file, created with the defaults if it doesn't exist. Without one,
+    /// gameboy/keymap.txt in the user's config directory is used.
+    #[arg(long)]
+    pub keymap: Option<PathBuf>,
+
+    /// Replay an input movie, checking that emulation matches the recording.
+    #[arg(long, conflicts_with = "record_movie")]
+    pub play_movie: Option<PathBuf>,
+
+    /// Record an input movie, written on exit.
+    #[arg(long)]
+    pub record_movie: Option<PathBuf>,
+
+    /// Start the recording from this save state slot instead of power-on.
+    #[arg(long, requires = "record_movie", value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub movie_slot: Option<u8>,
+
+    /// Write an execution trace in the Gameboy Doctor format.
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// Make LY read as $90 in the trace, like the Gameboy Doctor reference logs.
+    #[arg(long, requires = "trace")]
+    pub trace_stub_ly: bool,
+
+    /// Start in the debugger prompt.
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Lua script to run alongside the game. It registers callbacks with emu.on_frame,
+    /// emu.on_exec and emu.on_write that can read and write memory and press buttons.
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+
+    /// off, error, warn, info, debug or trace.
+    #[arg(long, default_value = "info")]
+    pub log_level: LevelFilter,
+}
+
+fn parse_scale(s: &str) -> Result<Scale, String> {
+    match s {
+        "1" => Ok(Scale::X1),
+        "2" => Ok(Scale::X2),
+        "4" => Ok(Scale::X4),
+        "8" => Ok(Scale::X8),
+        "16" => Ok(Scale::X16),
+        "32" => Ok(Scale::X32),
+        _ => Err("must be 1, 2, 4, 8, 16 or 32".to_string()),
+    }
+}
+
+fn parse_palette(s: &str) -> Result<DmgPalette, String> {
+    DmgPalette::from_name(s).ok_or_else(|| "must be grey, green or pocket".to_string())
+}
+
+fn parse_sync(s: &str) -> Result<SyncMode, String> {
+    SyncMode::from_spec(s).ok_or_else(|| "must be audio or timer".to_string())
+}
@@ -0,0 +1,68 @@
+use gameboy::memory::Memory;
+use gameboy::vram_viewer::{self, DebugView};
+use log::error;
+use minifb::{Key, Scale, Window, WindowOptions};
+
+type Render = fn(&Memory) -> DebugView;
+
+/// The debug views and the keys that open and close them.
+const VIEWS: [(Key, &str, Render); 4] = [
+    (Key::F1, "Tile data", vram_viewer::tile_data),
+    (Key::F2, "BG maps", vram_viewer::bg_maps),
+    (Key::F3, "OAM", vram_viewer::oam),
+    (Key::F4, "Palettes", vram_viewer::palettes),
+];
+
+/// Windows showing the video state next to the game, redrawn every frame while open.
+pub struct DebugWindows {
+    windows: [Option<Window>; VIEWS.len()],
+}
+
+impl DebugWindows {
+    pub fn new() -> Self {
+        Self {
+            windows: Default::default(),
+        }
+    }
+
+    /// Opens or closes the view toggled by `key`. Returns whether `key` is one of them.
+    pub fn handle_key(&mut self, key: Key, mem: &Memory) -> bool {
+        let Some(i) = VIEWS.iter().position(|(k, _, _)| *k == key) else {
+            return false;
+        };
+        if self.windows[i].take().is_none() {
+            let (_, title, render) = VIEWS[i];
+            let view = render(mem);
+            match Window::new(
+                title,
+                view.width,
+                view.height,
+                WindowOptions {
+                    scale: Scale::X2,
+                    ..WindowOptions::default()
+                },
+            ) {
+                Ok(window) => self.windows[i] = Some(window),
+                Err(e) => error!("Unable to open {} window: {}", title, e),
+            }
+        }
+        true
+    }
+
+    /// Redraws the open views and drops the ones that were closed.
+    pub fn update(&mut self, mem: &Memory) {
+        for (slot, (_, _, render)) in self.windows.iter_mut().zip(VIEWS) {
+            let Some(window) = slot else {
+                continue;
+            };
+            if !window.is_open() {
+                *slot = None;
+                continue;
+            }
+            let view = render(mem);
+            window
+                .update_with_buffer(&view.pixels, view.width, view.height)
+                .unwrap();
+        }
+    }
+}
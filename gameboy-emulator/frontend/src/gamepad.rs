@@ -0,0 +1,65 @@
+#[cfg(target_os = "linux")]
+use log::info;
+
+/// The first gamepad found through evdev. Its state is polled once per frame and reported
+/// with the input names used in the keymap.
+#[cfg(target_os = "linux")]
+pub struct Gamepad {
+    device: evdev::Device,
+}
+
+#[cfg(target_os = "linux")]
+impl Gamepad {
+    /// Opens the first device with gamepad buttons that the user can read.
+    pub fn open() -> Option<Self> {
+        let (path, device) = evdev::enumerate().find(|(_, device)| {
+            device
+                .supported_keys()
+                .is_some_and(|keys| keys.contains(evdev::Key::BTN_SOUTH))
+        })?;
+        info!(
+            "Using gamepad {} ({})",
+            device.name().unwrap_or("unnamed"),
+            path.display()
+        );
+        Some(Self { device })
+    }
+
+    /// Adds the held buttons, and the axes pushed more than halfway towards either end,
+    /// to `held`.
+    pub fn held_inputs(&self, held: &mut Vec<String>) -> std::io::Result<()> {
+        for key in self.device.get_key_state()?.iter() {
+            held.push(format!("pad:{:?}", key));
+        }
+        let Some(axes) = self.device.supported_absolute_axes() else {
+            return Ok(());
+        };
+        let state = self.device.get_abs_state()?;
+        for axis in axes.iter() {
+            let info = state[axis.0 as usize];
+            let center = (info.minimum + info.maximum) / 2;
+            let threshold = (info.maximum - info.minimum) / 4;
+            if info.value < center - threshold {
+                held.push(format!("pad:{:?}-", axis));
+            } else if info.value > center + threshold {
+                held.push(format!("pad:{:?}+", axis));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gamepads are only supported through evdev on Linux.
+#[cfg(not(target_os = "linux"))]
+pub struct Gamepad;
+
+#[cfg(not(target_os = "linux"))]
+impl Gamepad {
+    pub fn open() -> Option<Self> {
+        None
+    }
+
+    pub fn held_inputs(&self, _: &mut Vec<String>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
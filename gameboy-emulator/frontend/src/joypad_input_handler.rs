@@ -0,0 +1,75 @@
+use crate::gamepad::Gamepad;
+use crate::keymap::Keymap;
+use gameboy::host::InputSource;
+use gameboy::joypad::JoyPad;
+use log::error;
+use minifb::{InputCallback, Key};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Keeps track of the keys held down in the window, by their keymap names.
+pub struct JoypadInputHandler {
+    held_keys: Arc<Mutex<HashSet<String>>>,
+}
+
+impl JoypadInputHandler {
+    pub fn new(held_keys: Arc<Mutex<HashSet<String>>>) -> Self {
+        Self { held_keys }
+    }
+}
+
+impl InputCallback for JoypadInputHandler {
+    fn add_char(&mut self, _: u32) {}
+
+    fn set_key_state(&mut self, key: Key, state: bool) {
+        let mut held_keys = self.held_keys.lock().unwrap();
+        let name = format!("{:?}", key);
+        if state {
+            held_keys.insert(name);
+        } else {
+            held_keys.remove(&name);
+        }
+    }
+}
+
+/// Feeds the keys collected by the window's input callback and the gamepad state through
+/// the keymap into the emulator.
+pub struct SharedJoyPadInput {
+    held_keys: Arc<Mutex<HashSet<String>>>,
+    keymap: Keymap,
+    gamepad: Option<Gamepad>,
+    frame: u32,
+}
+
+impl SharedJoyPadInput {
+    pub fn new(
+        held_keys: Arc<Mutex<HashSet<String>>>,
+        keymap: Keymap,
+        gamepad: Option<Gamepad>,
+    ) -> Self {
+        Self {
+            held_keys,
+            keymap,
+            gamepad,
+            frame: 0,
+        }
+    }
+}
+
+impl InputSource for SharedJoyPadInput {
+    fn poll(&mut self, joy_pad: &mut JoyPad) {
+        let mut held = self.held_keys.lock().unwrap().clone();
+        if let Some(gamepad) = self.gamepad.as_ref() {
+            let mut pad_inputs = vec![];
+            match gamepad.held_inputs(&mut pad_inputs) {
+                Ok(()) => held.extend(pad_inputs),
+                Err(e) => {
+                    error!("Lost the gamepad: {}", e);
+                    self.gamepad = None;
+                }
+            }
+        }
+        self.keymap.apply(&held, self.frame, joy_pad);
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
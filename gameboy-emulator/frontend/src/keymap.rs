@@ -0,0 +1,202 @@
+use gameboy::joypad::JoyPad;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Written to the keymap path if there is no file yet, so it can be edited from there.
+pub const DEFAULT_KEYMAP: &str = "\
+# <button> = <input> [<input> ...]
+#
+# Buttons: a, b, start, select, up, down, left, right, and turbo_a and turbo_b, which
+# press and release A or B every few frames while held.
+#
+# Inputs are keyboard keys by their minifb name (W, Up, Enter, LeftShift, Key1, ...) or
+# gamepad inputs prefixed with `pad:`, either evdev button names (pad:BTN_SOUTH) or
+# an axis direction (pad:ABS_X-, pad:ABS_HAT0Y+).
+a = J pad:BTN_EAST
+b = I pad:BTN_SOUTH
+start = N pad:BTN_START
+select = M pad:BTN_SELECT
+up = W Up pad:BTN_DPAD_UP pad:ABS_HAT0Y- pad:ABS_Y-
+down = S Down pad:BTN_DPAD_DOWN pad:ABS_HAT0Y+ pad:ABS_Y+
+left = A Left pad:BTN_DPAD_LEFT pad:ABS_HAT0X- pad:ABS_X-
+right = D Right pad:BTN_DPAD_RIGHT pad:ABS_HAT0X+ pad:ABS_X+
+turbo_a = K pad:BTN_WEST
+turbo_b = O pad:BTN_NORTH
+";
+
+/// Frames a turbo button stays pressed, and then released, about 15 presses a second.
+const TURBO_HALF_PERIOD: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Button {
+    A,
+    B,
+    Start,
+    Select,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    fn state(self, joy_pad: &mut JoyPad) -> &mut bool {
+        match self {
+            Button::A => &mut joy_pad.a,
+            Button::B => &mut joy_pad.b,
+            Button::Start => &mut joy_pad.start,
+            Button::Select => &mut joy_pad.select,
+            Button::Up => &mut joy_pad.up,
+            Button::Down => &mut joy_pad.down,
+            Button::Left => &mut joy_pad.left,
+            Button::Right => &mut joy_pad.right,
+        }
+    }
+}
+
+struct Binding {
+    button: Button,
+    turbo: bool,
+    inputs: Vec<String>,
+}
+
+/// Maps keyboard keys and gamepad inputs to buttons. Any number of inputs can be bound
+/// to a button, and an input can press several buttons.
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut bindings = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, inputs) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected '<button> = <inputs>'", i + 1))?;
+            let name = name.trim();
+            let (turbo, base) = match name.strip_prefix("turbo_") {
+                Some(base) => (true, base),
+                None => (false, name),
+            };
+            let button = match base {
+                "a" => Button::A,
+                "b" => Button::B,
+                "start" if !turbo => Button::Start,
+                "select" if !turbo => Button::Select,
+                "up" if !turbo => Button::Up,
+                "down" if !turbo => Button::Down,
+                "left" if !turbo => Button::Left,
+                "right" if !turbo => Button::Right,
+                _ => return Err(format!("line {}: unknown button '{}'", i + 1, name)),
+            };
+            bindings.push(Binding {
+                button,
+                turbo,
+                inputs: inputs.split_whitespace().map(String::from).collect(),
+            });
+        }
+        Ok(Self { bindings })
+    }
+
+    /// Where the keymap lives without `--keymap`: `gameboy/keymap.txt` in the user's
+    /// config directory, or the working directory if there is none.
+    pub fn default_path() -> PathBuf {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        match config_dir {
+            Some(dir) => dir.join("gameboy").join("keymap.txt"),
+            None => PathBuf::from("keymap.txt"),
+        }
+    }
+
+    /// Reads the keymap at `path`, creating it with the default bindings if it doesn't
+    /// exist.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(error)?;
+            }
+            fs::write(path, DEFAULT_KEYMAP).map_err(error)?;
+        }
+        let text = fs::read_to_string(path).map_err(error)?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Sets the buttons from the inputs held in frame `frame`, which drives the turbo
+    /// buttons.
+    pub fn apply(&self, held: &HashSet<String>, frame: u32, joy_pad: &mut JoyPad) {
+        joy_pad.reset();
+        let turbo_on = (frame / TURBO_HALF_PERIOD).is_multiple_of(2);
+        for binding in &self.bindings {
+            if binding.inputs.iter().any(|input| held.contains(input))
+                && (!binding.turbo || turbo_on)
+            {
+                *binding.button.state(joy_pad) = true;
+            }
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::parse(DEFAULT_KEYMAP).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(inputs: &[&str]) -> HashSet<String> {
+        inputs.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn inputs_press_their_buttons() {
+        let keymap = Keymap::parse("a = J pad:BTN_EAST # comment\n\nup = W Up\nb = J").unwrap();
+        let mut joy_pad = JoyPad::new();
+        keymap.apply(&held(&["J"]), 0, &mut joy_pad);
+        assert!(joy_pad.a && joy_pad.b && !joy_pad.up);
+        keymap.apply(&held(&["pad:BTN_EAST", "Up"]), 0, &mut joy_pad);
+        assert!(joy_pad.a && !joy_pad.b && joy_pad.up);
+    }
+
+    #[test]
+    fn turbo_buttons_alternate() {
+        let keymap = Keymap::parse("turbo_a = K").unwrap();
+        let mut joy_pad = JoyPad::new();
+        let pressed: Vec<bool> = (0..2 * TURBO_HALF_PERIOD)
+            .map(|frame| {
+                keymap.apply(&held(&["K"]), frame, &mut joy_pad);
+                joy_pad.a
+            })
+            .collect();
+        assert_eq!(pressed, [true, true, false, false]);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert_eq!(
+            Keymap::parse("a = J\nturbo_start = N").err().unwrap(),
+            "line 2: unknown button 'turbo_start'"
+        );
+        assert!(Keymap::parse("a J").is_err());
+    }
+
+    #[test]
+    fn missing_keymap_is_created_with_defaults() {
+        let dir = env::temp_dir().join(format!("gameboy-keymap-{}", std::process::id()));
+        let path = dir.join("config").join("keymap.txt");
+        Keymap::load(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), DEFAULT_KEYMAP);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+use crate::audio_output::AudioOutput;
+use crate::capture::{take_screenshot, AudioCapture, VideoCapture};
+use crate::cli::Args;
+use crate::debug_windows::DebugWindows;
+use crate::gamepad::Gamepad;
+use crate::joypad_input_handler::{JoypadInputHandler, SharedJoyPadInput};
+use crate::keymap::Keymap;
+use crate::pacing::FramePacer;
+use crate::screen::Screen;
+use clap::Parser;
+use gameboy::capture::{save_png, AudioDump, VideoDump};
+use gameboy::cheats::Cheats;
+use gameboy::debugger::{run_repl, Debugger, ReplExit};
+use gameboy::host::{AudioSink, InputSource, NullHost, VideoSink};
+use gameboy::movie::{Movie, MoviePlayer, MovieRecorder};
+use gameboy::rewind::RewindBuffer;
+use gameboy::trace::TraceLog;
+use gameboy::GameBoy;
+use log::{error, info};
+use minifb::Key;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+mod audio_output;
+mod capture;
+mod cli;
+mod debug_windows;
+mod gamepad;
+mod joypad_input_handler;
+mod keymap;
+mod pacing;
+mod screen;
+mod script;
+
+const SAVE_STATE_SLOT_KEYS: [Key; 10] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+];
+const SAVE_STATE_SAVE_KEY: Key = Key::F5;
+const SAVE_STATE_LOAD_KEY: Key = Key::F8;
+const PAUSE_KEY: Key = Key::P;
+/// Runs at fast-forward speed while held.
+const FAST_FORWARD_KEY: Key = Key::Tab;
+const SLOW_MOTION_KEY: Key = Key::G;
+/// Stops emulation and opens the debugger prompt on stdin.
+const DEBUGGER_KEY: Key = Key::F12;
+/// Turns all cheats off and on again.
+const CHEATS_KEY: Key = Key::F7;
+/// Saves a PNG next to the ROM.
+const SCREENSHOT_KEY: Key = Key::F9;
+/// Starts and stops recording a GIF next to the ROM.
+const GIF_KEY: Key = Key::F10;
+/// Steps gameplay backwards while held.
+const REWIND_KEY: Key = Key::Backspace;
+/// Frames between rewind snapshots, which is also how many frames every frame of
+/// rewinding goes back.
+const REWIND_INTERVAL: u32 = 2;
+
+/// An input movie being recorded or replayed.
+enum MovieMode {
+    Recording(PathBuf, MovieRecorder),
+    Playing(MoviePlayer),
+}
+
+/// Runs a frame, recording its input or taking the input from the movie being replayed.
+/// Live input takes over once the replay has finished or diverged.
+fn run_frame(
+    gb: &mut GameBoy,
+    movie: &mut Option<MovieMode>,
+    video: &mut dyn VideoSink,
+    audio: &mut dyn AudioSink,
+    input: &mut dyn InputSource,
+) {
+    match movie {
+        Some(MovieMode::Recording(_, recorder)) => recorder.run_frame(gb, video, audio, input),
+        Some(MovieMode::Playing(player)) => {
+            if let Err(e) = player.run_frame(gb, video, audio) {
+                error!("{}", e);
+                *movie = None;
+            } else if player.is_finished() {
+                info!("Movie replay finished");
+                *movie = None;
+            }
+        }
+        None => gb.run_frame(video, audio, input),
+    }
+}
+
+fn handle_hotkeys(
+    gb: &mut GameBoy,
+    screen: &Screen,
+    pacer: &mut FramePacer,
+    save_state_slot: &mut u8,
+    debug_windows: &mut DebugWindows,
+    video_capture: &mut VideoCapture,
+    rom_path: &Path,
+) {
+    pacer.fast_forward = screen.is_key_down(FAST_FORWARD_KEY);
+    for key in screen.keys_pressed() {
+        if debug_windows.handle_key(key, &gb.mem) {
+            continue;
+        }
+        if let Some(slot) = SAVE_STATE_SLOT_KEYS.iter().position(|k| *k == key) {
+            *save_state_slot = slot as u8;
+            info!("Selected save state slot {}", slot);
+        } else if key == SAVE_STATE_SAVE_KEY {
+            match gb.save_state(*save_state_slot) {
+                Ok(()) => info!("Saved state to slot {}", save_state_slot),
+                Err(e) => error!("Unable to save state: {}", e),
+            }
+        } else if key == SAVE_STATE_LOAD_KEY {
+            match gb.load_state(*save_state_slot) {
+                Ok(()) => info!("Loaded state from slot {}", save_state_slot),
+                Err(e) => error!("Unable to load state: {}", e),
+            }
+        } else if key == PAUSE_KEY {
+            pacer.paused = !pacer.paused;
+            info!("{}", if pacer.paused { "Paused" } else { "Resumed" });
+        } else if key == SLOW_MOTION_KEY {
+            pacer.slow_motion = !pacer.slow_motion;
+            info!(
+                "Slow motion {}",
+                if pacer.slow_motion { "on" } else { "off" }
+            );
+        } else if key == CHEATS_KEY {
+            let cheats = &mut gb.mem.cheats;
+            cheats.active = !cheats.active;
+            info!("Cheats {}", if cheats.active { "on" } else { "off" });
+        } else if key == SCREENSHOT_KEY {
+            take_screenshot(rom_path, gb.screen(), gb.screen_size());
+        } else if key == GIF_KEY {
+            video_capture.toggle_gif(rom_path);
+        } else if key == DEBUGGER_KEY {
+            gb.debugger
+                .get_or_insert_with(Debugger::new)
+                .break_now("Interrupted");
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    env_logger::Builder::new()
+        .filter_level(args.log_level)
+        .init();
+    let mut gb = GameBoy::from_cartridge(
+        &args.rom,
+        args.boot_rom.as_deref(),
+        !args.ignore_header,
+        args.sgb,
+    )
+    .unwrap_or_else(|e| exit_with_error(&e));
+    gb.mem.dmg_palette = args.palette;
+    match load_cheats(&args.rom.with_extension("cht")) {
+        Ok(cheats) => gb.mem.cheats = cheats,
+        Err(e) => error!("Unable to load cheats: {}", e),
+    }
+    let link = args
+        .link
+        .open()
+        .unwrap_or_else(|e| exit_with_error(&format!("Unable to set up link cable: {}", e)));
+    gb.serial.set_link(link);
+    if let Some(path) = &args.trace {
+        let file = File::create(path)
+            .unwrap_or_else(|e| exit_with_error(&format!("Unable to create trace file: {}", e)));
+        gb.trace = Some(TraceLog::new(
+            Box::new(BufWriter::new(file)),
+            args.trace_stub_ly,
+        ));
+    }
+    if args.debug {
+        let mut debugger = Debugger::new();
+        debugger.break_now("Debugger attached");
+        gb.debugger = Some(debugger);
+    }
+    if let Some(path) = &args.script {
+        script::load(path, &mut gb).unwrap_or_else(|e| exit_with_error(&e));
+    }
+
+    let mut movie = if let Some(path) = &args.play_movie {
+        let movie = Movie::load(path).unwrap_or_else(|e| exit_with_error(&e));
+        let player = MoviePlayer::start(movie, &mut gb).unwrap_or_else(|e| exit_with_error(&e));
+        Some(MovieMode::Playing(player))
+    } else if let Some(path) = &args.record_movie {
+        let recorder = match args.movie_slot {
+            Some(slot) => {
+                gb.load_state(slot).unwrap_or_else(|e| exit_with_error(&e));
+                MovieRecorder::from_current_state(&gb)
+            }
+            None => MovieRecorder::from_power_on(&gb),
+        };
+        Some(MovieMode::Recording(path.clone(), recorder))
+    } else {
+        None
+    };
+    if let Some(frames) = args.frames {
+        let (mut video_capture, mut audio_capture) =
+            start_capture(&args, gb.screen_size(), args.sample_rate)
+                .unwrap_or_else(|e| exit_with_error(&e));
+        for _ in 0..frames {
+            if gb.debugger_stopped()
+                && !matches!(
+                    run_repl(&mut gb, &mut io::stdin().lock(), &mut io::stdout()),
+                    Ok(ReplExit::Resume)
+                )
+            {
+                break;
+            }
+            run_frame(
+                &mut gb,
+                &mut movie,
+                &mut video_capture,
+                &mut audio_capture,
+                &mut NullHost,
+            );
+        }
+        shut_down(&mut gb, movie, &args, video_capture, audio_capture);
+        return;
+    }
+
+    let keymap = match &args.keymap {
+        Some(path) => Keymap::load(path).unwrap_or_else(|e| exit_with_error(&e)),
+        None => Keymap::load(&Keymap::default_path()).unwrap_or_else(|e| {
+            error!("Unable to load keymap: {}", e);
+            Keymap::default()
+        }),
+    };
+
+    let held_keys = Arc::new(Mutex::new(HashSet::new()));
+    let mut screen = Screen::new(
+        args.scale,
+        gb.screen_size(),
+        JoypadInputHandler::new(held_keys.clone()),
+    );
+    let mut audio = AudioOutput::new();
+    let (mut video_capture, mut audio_capture) =
+        start_capture(&args, gb.screen_size(), audio.sample_rate())
+            .unwrap_or_else(|e| exit_with_error(&e));
+    let mut input = SharedJoyPadInput::new(held_keys, keymap, Gamepad::open());
+    let mut pacer = FramePacer::new(args.sync);
+    let mut save_state_slot = 0;
+    let mut rewind = RewindBuffer::new(args.rewind_mb << 20, REWIND_INTERVAL);
+    // Tile data, BG map, OAM and palette viewers, toggled with F1-F4.
+    let mut debug_windows = DebugWindows::new();
+    while screen.is_open() {
+        if gb.debugger_stopped() {
+            match run_repl(&mut gb, &mut io::stdin().lock(), &mut io::stdout()) {
+                Ok(ReplExit::Resume) => {}
+                Ok(ReplExit::Quit) => break,
+                Err(e) => {
+                    error!("Debugger I/O failed: {}", e);
+                    break;
+                }
+            }
+        }
+        pacer.wait(&audio);
+        // Rewinding would desync a movie, so it is only available without one.
+        if screen.is_key_down(REWIND_KEY) && movie.is_none() {
+            if let Err(e) = rewind.rewind(&mut gb) {
+                error!("Unable to rewind: {}", e);
+                rewind.clear();
+            }
+            screen.present_frame(gb.screen());
+        } else if pacer.paused {
+            screen.present_frame(gb.screen());
+        } else {
+            audio.set_speed(pacer.speed());
+            run_frame(
+                &mut gb,
+                &mut movie,
+                &mut (&mut screen, &mut video_capture),
+                &mut (&mut audio, &mut audio_capture),
+                &mut input,
+            );
+            rewind.after_frame(&gb);
+            // minifb has no force feedback, so the motor state is only logged.
+            if let Some(rumble) = gb.mem.take_rumble_event() {
+                info!("Rumble {}", if rumble { "on" } else { "off" });
+            }
+        }
+        debug_windows.update(&gb.mem);
+        handle_hotkeys(
+            &mut gb,
+            &screen,
+            &mut pacer,
+            &mut save_state_slot,
+            &mut debug_windows,
+            &mut video_capture,
+            &args.rom,
+        );
+    }
+    // The summary would end up in a dump written to stdout.
+    let stdout_dump = [&args.dump_video, &args.dump_audio]
+        .iter()
+        .any(|path| path.as_deref() == Some(Path::new("-")));
+    shut_down(&mut gb, movie, &args, video_capture, audio_capture);
+    if !stdout_dump {
+        gb.print_debug_summary();
+    }
+}
+
+/// Loads the cheat file at `path`. Cartridges without one have no cheats.
+fn load_cheats(path: &Path) -> Result<Cheats, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => {
+            info!("Loading cheats from {}", path.display());
+            Cheats::parse(&text)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Cheats::new()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+/// Opens the GIF and dumps requested on the command line, for frames of `size`.
+/// `sample_rate` is the rate the audio is produced at.
+fn start_capture(
+    args: &Args,
+    size: (usize, usize),
+    sample_rate: u32,
+) -> Result<(VideoCapture, AudioCapture), String> {
+    let mut video = VideoCapture::new(size);
+    let mut audio = AudioCapture::default();
+    if let Some(path) = &args.gif {
+        video.start_gif(path)?;
+    }
+    if let Some(path) = &args.dump_video {
+        video.set_dump(VideoDump::create(path)?);
+    }
+    if let Some(path) = &args.dump_audio {
+        audio.set_dump(AudioDump::create(path, sample_rate)?);
+    }
+    Ok((video, audio))
+}
+
+fn shut_down(
+    gb: &mut GameBoy,
+    movie: Option<MovieMode>,
+    args: &Args,
+    video_capture: VideoCapture,
+    audio_capture: AudioCapture,
+) {
+    gb.flush_save_file();
+    video_capture.finish();
+    audio_capture.finish();
+    if let Some(path) = &args.screenshot {
+        if let Err(e) = save_png(path, gb.screen(), gb.screen_size()) {
+            error!("{}", e);
+        }
+    }
+    if let Some(MovieMode::Recording(path, recorder)) = movie {
+        match recorder.finish().save(&path) {
+            Ok(()) => info!("Saved movie to {}", path.display()),
+            Err(e) => error!("Unable to save movie: {}", e),
+        }
+    }
+}
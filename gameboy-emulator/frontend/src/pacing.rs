@@ -0,0 +1,86 @@
+use crate::audio_output::AudioOutput;
+use gameboy::gameboy::{DOTS_PER_FRAME, DOT_FREQ};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Frames per second of the DMG, about 59.73 Hz.
+pub const FRAME_RATE: f64 = DOT_FREQ as f64 / DOTS_PER_FRAME as f64;
+pub const FAST_FORWARD_SPEED: f64 = 4.0;
+pub const SLOW_MOTION_SPEED: f64 = 0.5;
+/// When syncing to audio, the next frame is emulated once fewer than this many stereo
+/// frames are left in the output buffer, about 40 ms at 48 kHz.
+const AUDIO_BUFFER_TARGET_FRAMES: usize = 2048;
+/// If the timer falls further behind than this, it stops trying to catch up.
+const MAX_TIMER_LAG: Duration = Duration::from_millis(100);
+const AUDIO_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Emulate whenever the audio output is about to run dry.
+    Audio,
+    /// Emulate at a fixed 59.73 Hz, independent of the audio device.
+    Timer,
+}
+
+impl SyncMode {
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        match spec {
+            "audio" => Some(SyncMode::Audio),
+            "timer" => Some(SyncMode::Timer),
+            _ => None,
+        }
+    }
+}
+
+/// Decides when the next frame is emulated. The speed is applied by scaling the audio
+/// sample rate reported to the emulator, so audio stays in sync at every speed.
+pub struct FramePacer {
+    mode: SyncMode,
+    next_frame: Instant,
+    pub paused: bool,
+    pub fast_forward: bool,
+    pub slow_motion: bool,
+}
+
+impl FramePacer {
+    pub fn new(mode: SyncMode) -> Self {
+        Self {
+            mode,
+            next_frame: Instant::now(),
+            paused: false,
+            fast_forward: false,
+            slow_motion: false,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        if self.fast_forward {
+            FAST_FORWARD_SPEED
+        } else if self.slow_motion {
+            SLOW_MOTION_SPEED
+        } else {
+            1.0
+        }
+    }
+
+    /// Blocks until the next frame is due. While paused, this waits one frame period so
+    /// the window keeps being serviced.
+    pub fn wait(&mut self, audio: &AudioOutput) {
+        if self.mode == SyncMode::Audio && !self.paused {
+            while audio.buffered_frames() > AUDIO_BUFFER_TARGET_FRAMES {
+                sleep(AUDIO_POLL_INTERVAL);
+            }
+            self.next_frame = Instant::now();
+            return;
+        }
+
+        let speed = if self.paused { 1.0 } else { self.speed() };
+        self.next_frame += Duration::from_secs_f64(1.0 / (FRAME_RATE * speed));
+        let now = Instant::now();
+        if self.next_frame > now {
+            sleep(self.next_frame - now);
+        } else if now - self.next_frame > MAX_TIMER_LAG {
+            self.next_frame = now;
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use gameboy::host::VideoSink;
+use minifb::{InputCallback, Key, KeyRepeat, Scale, Window, WindowOptions};
+
+pub struct Screen {
+    window: Window,
+    width: usize,
+    height: usize,
+}
+
+impl Screen {
+    /// Opens a window for frames of `size`, see `GameBoy::screen_size`.
+    pub fn new(
+        scale: Scale,
+        (width, height): (usize, usize),
+        input_callback: impl InputCallback + 'static,
+    ) -> Self {
+        let mut window = Window::new(
+            "Pixel Grid - ESC to exit",
+            width,
+            height,
+            WindowOptions {
+                scale,
+                ..WindowOptions::default()
+            },
+        )
+        .unwrap();
+        window.set_input_callback(Box::new(input_callback));
+        Self {
+            window,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    pub fn is_key_down(&self, key: Key) -> bool {
+        self.window.is_key_down(key)
+    }
+
+    pub fn keys_pressed(&self) -> Vec<Key> {
+        self.window.get_keys_pressed(KeyRepeat::No)
+    }
+}
+
+impl VideoSink for Screen {
+    fn present_frame(&mut self, frame: &[u32]) {
+        if self.is_open() {
+            self.window
+                .update_with_buffer(frame, self.width, self.height)
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Lua scripts, for bots and tooling. A script registers callbacks on the `emu` table:
+//!
+//! ```lua
+//! emu.on_frame(function(gb) ... end)                    -- after every frame
+//! emu.on_exec(0x0150, function(gb) ... end)             -- when PC reaches an address
+//! emu.on_write(0xC000, 0xC0FF, function(gb, addr, value) ... end)
+//! ```
+//!
+//! Callbacks receive the machine as `gb`, with `gb.read(addr)`, `gb.write(addr, value)`,
+//! `gb.pc()`, and `gb.press(button)` and `gb.release(button)` to hold buttons down for the
+//! game: a, b, select, start, right, left, up or down.
+
+use gameboy::hooks::Hooks;
+use gameboy::GameBoy;
+use log::error;
+use mlua::{Function, IntoLuaMulti, Lua, RegistryKey, Scope, Table, Value};
+use std::cell::RefCell;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::rc::Rc;
+
+const BUTTONS: [&str; 8] = ["a", "b", "select", "start", "right", "left", "up", "down"];
+
+/// A callback registered by the script that isn't installed as a hook yet.
+enum Registration {
+    Frame(RegistryKey),
+    Exec(u16, RegistryKey),
+    Write(RangeInclusive<u16>, RegistryKey),
+}
+
+type Pending = Rc<RefCell<Vec<Registration>>>;
+
+/// Runs the script at `path` and installs the callbacks it registers as hooks of `gb`.
+pub fn load(path: &Path, gb: &mut GameBoy) -> Result<(), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read script {}: {}", path.display(), e))?;
+    let lua = Rc::new(Lua::new());
+    let pending = Pending::default();
+    create_api(&lua, &pending).map_err(|e| e.to_string())?;
+    lua.load(source)
+        .set_name(path.display().to_string())
+        .exec()
+        .map_err(|e| format!("Script error: {}", e))?;
+    install(&lua, &pending, &mut gb.hooks);
+    Ok(())
+}
+
+fn create_api(lua: &Lua, pending: &Pending) -> mlua::Result<()> {
+    let emu = lua.create_table()?;
+    let p = pending.clone();
+    let on_frame = lua.create_function(move |lua, f: Function| {
+        let key = lua.create_registry_value(f)?;
+        p.borrow_mut().push(Registration::Frame(key));
+        Ok(())
+    })?;
+    emu.set("on_frame", on_frame)?;
+    let p = pending.clone();
+    let on_exec = lua.create_function(move |lua, (addr, f): (u16, Function)| {
+        let key = lua.create_registry_value(f)?;
+        p.borrow_mut().push(Registration::Exec(addr, key));
+        Ok(())
+    })?;
+    emu.set("on_exec", on_exec)?;
+    let p = pending.clone();
+    let on_write = lua.create_function(move |lua, (start, end, f): (u16, u16, Function)| {
+        let key = lua.create_registry_value(f)?;
+        p.borrow_mut().push(Registration::Write(start..=end, key));
+        Ok(())
+    })?;
+    emu.set("on_write", on_write)?;
+    lua.globals().set("emu", emu)
+}
+
+/// Turns the pending registrations into hooks.
+fn install(lua: &Rc<Lua>, pending: &Pending, hooks: &mut Hooks) {
+    for registration in pending.borrow_mut().drain(..) {
+        let (lua, pending) = (lua.clone(), pending.clone());
+        match registration {
+            Registration::Frame(key) => {
+                hooks.on_frame(move |gb| call(&lua, &pending, &key, gb, ()));
+            }
+            Registration::Exec(addr, key) => {
+                hooks.on_exec(addr, move |gb| call(&lua, &pending, &key, gb, ()));
+            }
+            Registration::Write(range, key) => {
+                hooks.on_write(range, move |gb, addr, value| {
+                    call(&lua, &pending, &key, gb, (addr, value))
+                });
+            }
+        }
+    }
+}
+
+/// Calls a registered function with the machine and `args`. Errors are logged and don't
+/// stop emulation.
+fn call(
+    lua: &Rc<Lua>,
+    pending: &Pending,
+    key: &RegistryKey,
+    gb: &mut GameBoy,
+    args: impl for<'lua> IntoLuaMulti<'lua>,
+) {
+    let machine = RefCell::new(&mut *gb);
+    let result = lua.scope(|scope| {
+        let f: Function = lua.registry_value(key)?;
+        let mut args = args.into_lua_multi(lua)?;
+        args.push_front(Value::Table(create_machine(lua, scope, &machine)?));
+        f.call::<_, ()>(args)
+    });
+    if let Err(e) = result {
+        error!("Script error: {}", e);
+    }
+    install(lua, pending, &mut gb.hooks);
+}
+
+/// The `gb` table, whose functions work on the machine for the duration of the callback.
+fn create_machine<'lua, 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    machine: &'scope RefCell<&mut GameBoy>,
+) -> mlua::Result<Table<'lua>> {
+    let gb = lua.create_table()?;
+    gb.set(
+        "read",
+        scope.create_function(|_, addr: u16| Ok(machine.borrow().mem[addr]))?,
+    )?;
+    gb.set(
+        "write",
+        scope.create_function(|_, (addr, value): (u16, u8)| {
+            machine.borrow_mut().mem[addr] = value;
+            Ok(())
+        })?,
+    )?;
+    gb.set(
+        "pc",
+        scope.create_function(|_, ()| Ok(machine.borrow().cpu.reg.pc))?,
+    )?;
+    gb.set(
+        "press",
+        scope.create_function(|_, button: String| {
+            machine.borrow_mut().injected_buttons |= button_bit(&button)?;
+            Ok(())
+        })?,
+    )?;
+    gb.set(
+        "release",
+        scope.create_function(|_, button: String| {
+            machine.borrow_mut().injected_buttons &= !button_bit(&button)?;
+            Ok(())
+        })?,
+    )?;
+    Ok(gb)
+}
+
+/// The bit of a button in a `JoyPad::buttons` mask.
+fn button_bit(name: &str) -> mlua::Result<u8> {
+    match BUTTONS.iter().position(|b| *b == name) {
+        Some(i) => Ok(1 << i),
+        None => Err(mlua::Error::RuntimeError(format!(
+            "unknown button '{}', expected one of {}",
+            name,
+            BUTTONS.join(", ")
+        ))),
+    }
+}
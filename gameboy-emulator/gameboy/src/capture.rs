@@ -0,0 +1,278 @@
+use crate::gameboy::{DOTS_PER_FRAME, DOT_FREQ};
+use crate::host::{AudioSink, VideoSink};
+use gif::{Encoder, Frame, Repeat};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Browsers play GIF frames shorter than this (in 1/100 s) far too slowly, so frames are
+/// dropped to keep every delay at least this long.
+const GIF_MIN_DELAY: u64 = 2;
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+fn rgb_bytes(frame: &[u32]) -> Vec<u8> {
+    frame
+        .iter()
+        .flat_map(|px| [(px >> 16) as u8, (px >> 8) as u8, *px as u8])
+        .collect()
+}
+
+/// Opens `path` for writing, or stdout if it is `-`.
+fn create_output(path: &Path) -> Result<Box<dyn Write>, String> {
+    if path == Path::new("-") {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    let file =
+        File::create(path).map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// Saves a frame of the given size, see `GameBoy::screen_size`, as a PNG.
+pub fn save_png(path: &Path, frame: &[u32], (width, height): (usize, usize)) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer
+        .write_image_data(&rgb_bytes(frame))
+        .map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())
+}
+
+/// Returns `<rom>-<n>.<extension>` for the lowest `n` that isn't taken yet.
+pub fn numbered_path(rom_path: &Path, extension: &str) -> PathBuf {
+    let stem = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| rom_path.with_file_name(format!("{}-{}.{}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Records frames into an animated GIF. Runs of identical frames become one GIF frame,
+/// and frames are timed by emulated time, so the GIF plays at the speed of the hardware.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    /// The frame waiting to be written, held back until it is known how long it shows.
+    pending: Option<Vec<u32>>,
+    /// Emulated frames recorded so far.
+    frames: u64,
+    /// Time of the start of the pending frame, in 1/100 s.
+    pending_start: u64,
+}
+
+impl GifRecorder {
+    /// Records frames of the given size, see `GameBoy::screen_size`.
+    pub fn create(path: &Path, (width, height): (usize, usize)) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+        let (width, height) = (width as u16, height as u16);
+        let mut encoder =
+            Encoder::new(BufWriter::new(file), width, height, &[]).map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            pending: None,
+            frames: 0,
+            pending_start: 0,
+        })
+    }
+
+    fn centiseconds(frames: u64) -> u64 {
+        frames * DOTS_PER_FRAME as u64 * 100 / DOT_FREQ as u64
+    }
+
+    pub fn add_frame(&mut self, frame: &[u32]) -> Result<(), String> {
+        let now = Self::centiseconds(self.frames);
+        self.frames += 1;
+        match &mut self.pending {
+            Some(pending) if pending == frame => {}
+            // Shown too briefly, replace it with the new frame.
+            Some(pending) if now - self.pending_start < GIF_MIN_DELAY => {
+                pending.copy_from_slice(frame)
+            }
+            _ => {
+                self.write_pending(now)?;
+                self.pending = Some(frame.to_vec());
+                self.pending_start = now;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_pending(&mut self, end: u64) -> Result<(), String> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(());
+        };
+        let mut frame = self.encode_frame(&pending);
+        frame.delay = (end - self.pending_start).clamp(GIF_MIN_DELAY, u16::MAX as u64) as u16;
+        self.encoder
+            .write_frame(&frame)
+            .map_err(|e| format!("Unable to write GIF: {}", e))
+    }
+
+    /// Uses the frame's own colours as its palette. A frame can only have more than 256
+    /// when a CGB game changes palettes mid-frame or an SGB border is colourful, and is
+    /// quantized then.
+    fn encode_frame(&self, pixels: &[u32]) -> Frame<'static> {
+        let mut palette = HashMap::new();
+        let indices: Option<Vec<u8>> = pixels
+            .iter()
+            .map(|px| {
+                let next = palette.len();
+                let index = *palette.entry(*px).or_insert(next);
+                (index < 256).then_some(index as u8)
+            })
+            .collect();
+        let Some(indices) = indices else {
+            return Frame::from_rgb_speed(
+                self.width,
+                self.height,
+                &rgb_bytes(pixels),
+                GIF_QUANTIZE_SPEED,
+            );
+        };
+        let mut colors = vec![0; palette.len()];
+        for (px, index) in palette {
+            colors[index] = px;
+        }
+        Frame::from_palette_pixels(self.width, self.height, indices, rgb_bytes(&colors), None)
+    }
+
+    /// Writes the last frame and the end of the GIF.
+    pub fn finish(mut self) -> Result<(), String> {
+        let end = Self::centiseconds(self.frames);
+        self.write_pending(end)?;
+        self.encoder
+            .into_inner()
+            .map_err(|e| e.to_string())?
+            .flush()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Writes every frame as raw 8-bit RGB without any header, for piping into an encoder such
+/// as `ffmpeg -f rawvideo -pix_fmt rgb24 -video_size 160x144 -framerate 59.7275 -i -`.
+/// Frames are 256x224 with the Super Game Boy border.
+pub struct VideoDump {
+    out: Box<dyn Write>,
+    error: Option<String>,
+}
+
+impl VideoDump {
+    /// Writes to `path`, or to stdout if it is `-`.
+    pub fn create(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            out: create_output(path)?,
+            error: None,
+        })
+    }
+
+    /// Flushes the output and reports the first write that failed.
+    pub fn finish(mut self) -> Result<(), String> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl VideoSink for VideoDump {
+    fn present_frame(&mut self, frame: &[u32]) {
+        if self.error.is_none() {
+            if let Err(e) = self.out.write_all(&rgb_bytes(frame)) {
+                self.error = Some(format!("Unable to write video: {}", e));
+            }
+        }
+    }
+}
+
+enum AudioOutput {
+    Raw(Box<dyn Write>),
+    Wav(WavWriter<BufWriter<File>>),
+}
+
+/// Writes the audio as signed 16-bit little-endian stereo, into a WAV file if the path
+/// ends in `.wav` and raw otherwise. The audio is produced at `sample_rate` as long as the
+/// sink it is paired with asks for that rate; speeding up emulation makes it skip ahead.
+pub struct AudioDump {
+    out: AudioOutput,
+    sample_rate: u32,
+    error: Option<String>,
+}
+
+impl AudioDump {
+    /// Writes to `path`, or raw to stdout if it is `-`.
+    pub fn create(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        let is_wav = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+        let out = if is_wav {
+            let spec = WavSpec {
+                channels: 2,
+                sample_rate,
+                bits_per_sample: 16,
+                sample_format: SampleFormat::Int,
+            };
+            let writer = WavWriter::create(path, spec)
+                .map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+            AudioOutput::Wav(writer)
+        } else {
+            AudioOutput::Raw(create_output(path)?)
+        };
+        Ok(Self {
+            out,
+            sample_rate,
+            error: None,
+        })
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut samples = samples
+            .iter()
+            .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        match &mut self.out {
+            AudioOutput::Raw(out) => {
+                let bytes: Vec<u8> = samples.flat_map(i16::to_le_bytes).collect();
+                out.write_all(&bytes).map_err(|e| e.to_string())
+            }
+            AudioOutput::Wav(writer) => samples
+                .try_for_each(|s| writer.write_sample(s))
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Completes the output and reports the first write that failed.
+    pub fn finish(self) -> Result<(), String> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        match self.out {
+            AudioOutput::Raw(mut out) => out.flush().map_err(|e| e.to_string()),
+            AudioOutput::Wav(writer) => writer.finalize().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl AudioSink for AudioDump {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        if self.error.is_none() {
+            if let Err(e) = self.write_samples(samples) {
+                self.error = Some(format!("Unable to write audio: {}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::memory::{rom_bank_count, MemoryBankController, ROMOnly, MBC1, MBC2, MBC3, MBC5};
+use log::warn;
+
+const MBC1M_ROM_SIZE: usize = 0x10_0000;
+/// Offset of the second game on an MBC1M multicart, which repeats the header.
+const MBC1M_GAME_OFFSET: usize = 0x4_0000;
+/// The header ends at 0x14F, the entry point is right before it.
+const HEADER_END: usize = 0x150;
+/// The logo at 0x104, which the boot ROM compares before it starts the cartridge.
+const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    pub logo: [u8; 0x30],
+    pub title: [u8; 0x10],
+    pub manufacturer: [u8; 0x4],
+    pub cgb: u8,
+    pub licensee: [u8; 0x2],
+    pub sgb: u8,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub destination_code: u8,
+    pub licensee_old: u8,
+    pub rom_version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: [u8; 2],
+}
+
+impl CartridgeHeader {
+    pub fn read(rom: &[u8]) -> Result<Self, String> {
+        if rom.len() < HEADER_END {
+            return Err(format!(
+                "ROM is {} bytes, too small to contain a cartridge header",
+                rom.len()
+            ));
+        }
+        Ok(Self {
+            logo: rom[0x104..=0x133].try_into().unwrap(),
+            title: rom[0x134..=0x143].try_into().unwrap(),
+            manufacturer: rom[0x13F..=0x142].try_into().unwrap(),
+            cgb: rom[0x143],
+            licensee: rom[0x144..=0x145].try_into().unwrap(),
+            sgb: rom[0x146],
+            cartridge_type: rom[0x147],
+            rom_size: rom[0x148],
+            ram_size: rom[0x149],
+            destination_code: rom[0x14A],
+            licensee_old: rom[0x14B],
+            rom_version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: rom[0x14E..=0x14F].try_into().unwrap(),
+        })
+    }
+
+    /// Checks the logo and header checksum, which the boot ROM verifies and locks up on
+    /// if they are wrong. A wrong global checksum is only a warning, as nothing checks it
+    /// on hardware.
+    pub fn validate(&self, rom: &[u8]) -> Result<(), String> {
+        if self.logo != NINTENDO_LOGO {
+            return Err("Cartridge header has no valid Nintendo logo".to_string());
+        }
+        let rom_size = 0x8000usize << self.rom_size.min(8);
+        if rom.len() < rom_size {
+            return Err(format!(
+                "ROM is {} bytes, but the header declares {} bytes",
+                rom.len(),
+                rom_size
+            ));
+        }
+        let header_checksum = rom[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1));
+        if header_checksum != self.header_checksum {
+            return Err(format!(
+                "Header checksum is {:02X}, but the header contains {:02X}",
+                header_checksum, self.header_checksum
+            ));
+        }
+        let global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !(0x14E..=0x14F).contains(i))
+            .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16));
+        let expected = u16::from_be_bytes(self.global_checksum);
+        if global_checksum != expected {
+            warn!(
+                "Global checksum is {:04X}, but the header contains {:04X}",
+                global_checksum, expected
+            );
+        }
+        Ok(())
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        matches!(self.cartridge_type, 0x0F | 0x10)
+    }
+
+    pub fn ram_size_bytes(&self) -> usize {
+        if self.has_mbc2() {
+            return 0x200;
+        }
+        match self.ram_size {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        }
+    }
+
+    /// CGB-enhanced and CGB-only cartridges both run in CGB mode.
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb & 0x80 != 0
+    }
+
+    /// Whether the cartridge uses Super Game Boy functions. The SGB ignores the commands
+    /// of cartridges that don't.
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb == 0x03 && self.licensee_old == 0x33
+    }
+
+    pub fn has_mbc2(&self) -> bool {
+        matches!(self.cartridge_type, 0x05 | 0x06)
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        matches!(self.cartridge_type, 0x1C..=0x1E)
+    }
+
+    pub fn memory_bank_controller(&self, rom: &[u8]) -> Result<MemoryBankController, String> {
+        match self.cartridge_type {
+            0x00 => Ok(MemoryBankController::ROMOnly(ROMOnly::new())),
+            0x01..=0x03 => Ok(MemoryBankController::MBC1(MBC1::new(
+                rom_bank_count(rom),
+                self.ram_size,
+                Self::is_mbc1_multicart(rom),
+            ))),
+            0x05 | 0x06 => Ok(MemoryBankController::MBC2(MBC2::new())),
+            0x0F..=0x13 => Ok(MemoryBankController::MBC3(MBC3::new())),
+            0x19..=0x1E => Ok(MemoryBankController::MBC5(MBC5::new(self.has_rumble()))),
+            _ => Err("Cartridge type's MemoryBankController not implemented".to_string()),
+        }
+    }
+
+    /// MBC1M multicarts declare a plain 1 MiB MBC1 cartridge. They are told apart by the
+    /// Nintendo logo of the second game's header at bank 0x10.
+    fn is_mbc1_multicart(rom: &[u8]) -> bool {
+        rom.len() == MBC1M_ROM_SIZE
+            && rom[MBC1M_GAME_OFFSET + 0x104..MBC1M_GAME_OFFSET + 0x134] == rom[0x104..0x134]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoy;
+    use std::{env, fs, process};
+
+    /// A 32 KiB ROM with the logo and a header of zeros, whose checksum is 0 - 25 = E7.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x14D] = 0xE7;
+        rom
+    }
+
+    fn validate(rom: &[u8]) -> Result<(), String> {
+        CartridgeHeader::read(rom)?.validate(rom)
+    }
+
+    #[test]
+    fn valid_header_passes() {
+        assert_eq!(validate(&rom()), Ok(()));
+        let mut rom = rom();
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        // E7 minus the title's bytes, 320.
+        rom[0x14D] = 0xA7;
+        assert_eq!(validate(&rom), Ok(()));
+    }
+
+    #[test]
+    fn wrong_header_checksum_fails() {
+        let mut rom = rom();
+        rom[0x147] = 0x01;
+        assert_eq!(
+            validate(&rom),
+            Err("Header checksum is E6, but the header contains E7".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_logo_fails() {
+        let mut rom = rom();
+        rom[0x110] ^= 0xFF;
+        assert!(validate(&rom).is_err());
+    }
+
+    #[test]
+    fn rom_smaller_than_declared_fails() {
+        let mut rom = rom();
+        rom[0x148] = 0x01;
+        rom[0x14D] = 0xE6;
+        assert_eq!(
+            validate(&rom),
+            Err("ROM is 32768 bytes, but the header declares 65536 bytes".to_string())
+        );
+        assert!(CartridgeHeader::read(&rom[..0x14F]).is_err());
+    }
+
+    #[test]
+    fn truncated_rom_with_corrupt_size_loads_unchecked() {
+        // Two and a half banks of an MBC1 cartridge whose ROM size byte is garbage.
+        let mut rom: Vec<u8> = (0..3).flat_map(|b| [b as u8; 0x4000]).collect();
+        rom.truncate(0xA000);
+        rom[0x147] = 0x01;
+        rom[0x148] = 0xFF;
+        let path = env::temp_dir().join(format!("truncated-{}.gb", process::id()));
+        fs::write(&path, &rom).unwrap();
+        let gb = GameBoy::from_cartridge(&path, None, false, false);
+        fs::remove_file(&path).unwrap();
+        let mut gb = gb.unwrap();
+        for (bank, expected) in [(2, 0x02), (3, 0xFF), (5, 0x01), (0x1F, 0xFF)] {
+            gb.mem[0x2000] = bank;
+            assert_eq!(gb.mem[0x4000], expected, "bank {bank:02x}");
+        }
+        gb.mem[0x2000] = 0x02;
+        assert_eq!(gb.mem[0x6000], 0xFF);
+    }
+}
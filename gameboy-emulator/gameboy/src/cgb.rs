@@ -0,0 +1,249 @@
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+
+const PALETTE_RAM_SIZE: usize = 0x40;
+const PALETTE_INDEX_MASK: u8 = 0b0011_1111;
+const PALETTE_AUTO_INCREMENT: u8 = 1 << 7;
+const HDMA_BLOCK_SIZE: u16 = 0x10;
+const HDMA_HBLANK_MODE: u8 = 1 << 7;
+/// HDMA5 reads as this while no transfer is active.
+const HDMA_IDLE: u8 = 0xFF;
+
+/// Converts a BGR555 colour as stored in palette RAM to `0x00RRGGBB`.
+pub fn rgb555_to_rgb888(color: u16) -> u32 {
+    let channel = |shift: u16| {
+        let c = ((color >> shift) & 0x1F) as u32;
+        (c << 3) | (c >> 2)
+    };
+    channel(0) << 16 | channel(5) << 8 | channel(10)
+}
+
+/// Eight palettes of four BGR555 colours, accessed through an index register (BCPS/OCPS)
+/// and a data register (BCPD/OCPD).
+pub struct ColorPalettes {
+    /// BCPS/OCPS: bits 0-5 select a byte of palette RAM, bit 7 enables auto-increment.
+    pub spec: u8,
+    ram: [u8; PALETTE_RAM_SIZE],
+}
+
+impl ColorPalettes {
+    pub fn new() -> Self {
+        Self {
+            spec: 0,
+            ram: [0xFF; PALETTE_RAM_SIZE],
+        }
+    }
+
+    pub fn data(&self) -> &u8 {
+        &self.ram[(self.spec & PALETTE_INDEX_MASK) as usize]
+    }
+
+    pub fn data_mut(&mut self) -> &mut u8 {
+        &mut self.ram[(self.spec & PALETTE_INDEX_MASK) as usize]
+    }
+
+    /// Advances the index after a write to the data register if auto-increment is on.
+    pub fn after_data_write(&mut self) {
+        if self.spec & PALETTE_AUTO_INCREMENT != 0 {
+            self.spec = PALETTE_AUTO_INCREMENT | (self.spec.wrapping_add(1) & PALETTE_INDEX_MASK);
+        }
+    }
+
+    /// BGR555 value of colour `color` (0-3) in palette `palette` (0-7).
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let i = (palette as usize & 0b111) * 8 + (color as usize & 0b11) * 2;
+        u16::from_le_bytes([self.ram[i], self.ram[i + 1]]) & 0x7FFF
+    }
+}
+
+impl Default for ColorPalettes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// VRAM DMA controlled by HDMA1-HDMA5. General purpose transfers copy everything at once,
+/// HBlank transfers copy one 16 byte block at the start of every HBlank.
+pub struct Hdma {
+    /// HDMA1-HDMA5 as last written by the CPU.
+    pub regs: [u8; 5],
+    /// HDMA5 as read by the CPU: the remaining blocks minus one, or 0xFF when idle.
+    pub status: u8,
+    src: u16,
+    dst: u16,
+    blocks_left: u16,
+    hblank_active: bool,
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Self {
+            regs: [0xFF; 5],
+            status: HDMA_IDLE,
+            src: 0,
+            dst: 0,
+            blocks_left: 0,
+            hblank_active: false,
+        }
+    }
+
+    /// Handles a write to HDMA5. Returns the number of blocks to copy right away.
+    pub fn start(&mut self) -> u16 {
+        let control = self.regs[4];
+        if self.hblank_active && control & HDMA_HBLANK_MODE == 0 {
+            // Writing bit 7 = 0 during an HBlank transfer cancels it.
+            self.hblank_active = false;
+            self.status |= HDMA_HBLANK_MODE;
+            return 0;
+        }
+        self.src = u16::from_be_bytes([self.regs[0], self.regs[1]]) & 0xFFF0;
+        self.dst = 0x8000 | (u16::from_be_bytes([self.regs[2], self.regs[3]]) & 0x1FF0);
+        self.blocks_left = (control & !HDMA_HBLANK_MODE) as u16 + 1;
+        if control & HDMA_HBLANK_MODE != 0 {
+            self.hblank_active = true;
+            self.status = control & !HDMA_HBLANK_MODE;
+            0
+        } else {
+            self.blocks_left
+        }
+    }
+
+    /// Called when the PPU enters HBlank. Returns the number of blocks to copy.
+    pub fn hblank(&self) -> u16 {
+        if self.hblank_active {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Advances past the next block and returns its source and destination address.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.src, self.dst);
+        self.src = self.src.wrapping_add(HDMA_BLOCK_SIZE);
+        // The destination wraps within VRAM.
+        self.dst = 0x8000 | (self.dst.wrapping_add(HDMA_BLOCK_SIZE) & 0x1FF0);
+        self.blocks_left -= 1;
+        if self.blocks_left == 0 {
+            self.hblank_active = false;
+            self.status = HDMA_IDLE;
+        } else {
+            self.status = (self.blocks_left - 1) as u8;
+        }
+        block
+    }
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for ColorPalettes {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.spec);
+        w.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.spec = r.read_u8()?;
+        r.read_into(&mut self.ram)
+    }
+}
+
+impl Snapshot for Hdma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.regs);
+        w.write_u8(self.status);
+        w.write_u16(self.src);
+        w.write_u16(self.dst);
+        w.write_u16(self.blocks_left);
+        w.write_bool(self.hblank_active);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.regs)?;
+        self.status = r.read_u8()?;
+        self.src = r.read_u16()?;
+        self.dst = r.read_u16()?;
+        self.blocks_left = r.read_u16()?;
+        self.hblank_active = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An HDMA set up to copy from $C123 to $9FF5, ignoring the low four bits of both.
+    fn hdma(control: u8) -> Hdma {
+        let mut hdma = Hdma::new();
+        hdma.regs = [0xC1, 0x23, 0x9F, 0xF5, control];
+        hdma
+    }
+
+    #[test]
+    fn general_purpose_transfer_copies_at_once() {
+        let mut hdma = hdma(0x01);
+        assert_eq!(hdma.start(), 2);
+        assert_eq!(hdma.next_block(), (0xC120, 0x9FF0));
+        assert_eq!(hdma.status, 0x00);
+        // The destination wraps to the start of VRAM.
+        assert_eq!(hdma.next_block(), (0xC130, 0x8000));
+        assert_eq!(hdma.status, HDMA_IDLE);
+    }
+
+    #[test]
+    fn hblank_transfer_copies_a_block_per_hblank() {
+        let mut hdma = hdma(0x82);
+        assert_eq!(hdma.start(), 0);
+        assert_eq!(hdma.status, 0x02);
+        for status in [0x01, 0x00, HDMA_IDLE] {
+            assert_eq!(hdma.hblank(), 1);
+            hdma.next_block();
+            assert_eq!(hdma.status, status);
+        }
+        assert_eq!(hdma.hblank(), 0);
+    }
+
+    #[test]
+    fn hblank_transfer_is_cancelled_by_clearing_bit_7() {
+        let mut hdma = hdma(0x82);
+        hdma.start();
+        hdma.next_block();
+        hdma.regs[4] = 0x00;
+        assert_eq!(hdma.start(), 0);
+        assert_eq!(hdma.hblank(), 0);
+        // The remaining blocks stay readable, with bit 7 set.
+        assert_eq!(hdma.status, 0x81);
+    }
+
+    #[test]
+    fn palette_index_auto_increments_and_wraps() {
+        let mut palettes = ColorPalettes::new();
+        palettes.spec = PALETTE_AUTO_INCREMENT | 0x3E;
+        *palettes.data_mut() = 0x1F;
+        palettes.after_data_write();
+        assert_eq!(palettes.spec, PALETTE_AUTO_INCREMENT | 0x3F);
+        *palettes.data_mut() = 0x7C;
+        palettes.after_data_write();
+        assert_eq!(palettes.spec, PALETTE_AUTO_INCREMENT);
+        assert_eq!(palettes.color(7, 3), 0x7C1F);
+
+        palettes.spec = 0x05;
+        palettes.after_data_write();
+        assert_eq!(palettes.spec, 0x05);
+    }
+
+    #[test]
+    fn rgb555_expands_to_rgb888() {
+        assert_eq!(rgb555_to_rgb888(0x0000), 0x000000);
+        assert_eq!(rgb555_to_rgb888(0x7FFF), 0xFFFFFF);
+        // Red is in the low bits.
+        assert_eq!(rgb555_to_rgb888(0x001F), 0xFF0000);
+        assert_eq!(rgb555_to_rgb888(0x03E0), 0x00FF00);
+        assert_eq!(rgb555_to_rgb888(0x7C00), 0x0000FF);
+        assert_eq!(rgb555_to_rgb888(0x0010), 0x840000);
+    }
+}
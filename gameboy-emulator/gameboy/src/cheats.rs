@@ -0,0 +1,295 @@
+use std::fmt;
+
+/// GameShark codes with this type write through the memory map, to whichever banks are
+/// selected. Types 0x90-0x97 write to that WRAM bank instead.
+const GAMESHARK_CURRENT_BANK: u8 = 0x01;
+const GAMESHARK_WRAM_BANK: u8 = 0x90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Replaces the byte the CPU reads from ROM at `addr`, in any bank. With a compare
+    /// value only if the original byte matches it, which picks out one bank.
+    GameGenie {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes `value` to `addr` once per frame, at the start of VBlank.
+    GameShark { kind: u8, addr: u16, value: u8 },
+}
+
+impl Cheat {
+    /// Parses a Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`, or an 8 digit GameShark code.
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let digits: Vec<u8> = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("Invalid cheat code '{}'", code))?;
+        let dashes = code.chars().filter(|c| *c == '-').count();
+        match (digits.len(), dashes) {
+            (6, 1) | (9, 2) => {
+                let d = |i: usize| digits[i] as u16;
+                let value = (d(0) << 4 | d(1)) as u8;
+                let addr = (d(5) ^ 0xF) << 12 | d(2) << 8 | d(3) << 4 | d(4);
+                if addr >= 0x8000 {
+                    return Err(format!("Game Genie code '{}' is not for ROM", code));
+                }
+                // The compare value is scrambled across the 7th and 9th digit, the 8th
+                // digit isn't used.
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Cheat::GameGenie {
+                    addr,
+                    value,
+                    compare,
+                })
+            }
+            (8, 0) => {
+                let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+                let kind = byte(0);
+                if kind != GAMESHARK_CURRENT_BANK && kind & 0xF8 != GAMESHARK_WRAM_BANK {
+                    return Err(format!("Unsupported GameShark code type {:02X}", kind));
+                }
+                Ok(Cheat::GameShark {
+                    kind,
+                    value: byte(2),
+                    // The address is stored little-endian.
+                    addr: u16::from_le_bytes([byte(4), byte(6)]),
+                })
+            }
+            _ => Err(format!("Invalid cheat code '{}'", code)),
+        }
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cheat::GameGenie {
+                addr,
+                value,
+                compare: Some(compare),
+            } => write!(f, "ROM {:04X} = {:02X} if {:02X}", addr, value, compare),
+            Cheat::GameGenie { addr, value, .. } => write!(f, "ROM {:04X} = {:02X}", addr, value),
+            Cheat::GameShark { kind, addr, value } if *kind & 0xF8 == GAMESHARK_WRAM_BANK => {
+                write!(f, "WRAM{} {:04X} = {:02X}", kind & 0x07, addr, value)
+            }
+            Cheat::GameShark { addr, value, .. } => write!(f, "RAM {:04X} = {:02X}", addr, value),
+        }
+    }
+}
+
+pub struct CheatCode {
+    pub code: String,
+    pub name: String,
+    pub cheat: Cheat,
+    pub enabled: bool,
+}
+
+/// The cheats of the loaded cartridge. `parse` reads them from text with one code per line,
+/// optionally followed by a name. A code prefixed with `!` is loaded disabled, and lines
+/// starting with `#` are comments.
+pub struct Cheats {
+    codes: Vec<CheatCode>,
+    /// Turns all cheats off without forgetting which ones are enabled.
+    pub active: bool,
+    /// Enabled Game Genie codes, looked up on every ROM read.
+    rom_patches: Vec<(u16, u8, Option<u8>)>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self {
+            codes: Vec::new(),
+            active: true,
+            rom_patches: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (code, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let (code, enabled) = match code.strip_prefix('!') {
+                Some(code) => (code, false),
+                None => (code, true),
+            };
+            cheats
+                .add(code, name.trim(), enabled)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(cheats)
+    }
+
+    pub fn add(&mut self, code: &str, name: &str, enabled: bool) -> Result<(), String> {
+        let cheat = Cheat::parse(code)?;
+        self.codes.push(CheatCode {
+            code: code.to_uppercase(),
+            name: name.to_string(),
+            cheat,
+            enabled,
+        });
+        self.update_rom_patches();
+        Ok(())
+    }
+
+    pub fn codes(&self) -> &[CheatCode] {
+        &self.codes
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        let code = self
+            .codes
+            .get_mut(index)
+            .ok_or_else(|| format!("No cheat {}", index))?;
+        code.enabled = enabled;
+        self.update_rom_patches();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.codes.len() {
+            return Err(format!("No cheat {}", index));
+        }
+        self.codes.remove(index);
+        self.update_rom_patches();
+        Ok(())
+    }
+
+    fn update_rom_patches(&mut self) {
+        self.rom_patches = self
+            .codes
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|c| match c.cheat {
+                Cheat::GameGenie {
+                    addr,
+                    value,
+                    compare,
+                } => Some((addr, value, compare)),
+                Cheat::GameShark { .. } => None,
+            })
+            .collect();
+    }
+
+    /// Returns the byte a ROM read of `addr` sees, given the byte in ROM.
+    pub fn patch_rom<'a>(&'a self, addr: u16, original: &'a u8) -> &'a u8 {
+        if !self.active {
+            return original;
+        }
+        self.rom_patches
+            .iter()
+            .find(|(a, _, compare)| *a == addr && compare.is_none_or(|c| c == *original))
+            .map_or(original, |(_, value, _)| value)
+    }
+
+    /// The GameShark writes to make this frame, as the WRAM bank if the code selects one,
+    /// address and value.
+    pub fn ram_writes(&self) -> Vec<(Option<u8>, u16, u8)> {
+        if !self.active {
+            return Vec::new();
+        }
+        self.codes
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|c| match c.cheat {
+                Cheat::GameShark { kind, addr, value } => {
+                    let bank = (kind & 0xF8 == GAMESHARK_WRAM_BANK).then_some(kind & 0x07);
+                    Some((bank, addr, value))
+                }
+                Cheat::GameGenie { .. } => None,
+            })
+            .collect()
+    }
+}
+
+impl Default for Cheats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_codes_decode() {
+        assert_eq!(
+            Cheat::parse("01a-2be"),
+            Ok(Cheat::GameGenie {
+                addr: 0x1A2B,
+                value: 0x01,
+                compare: None,
+            })
+        );
+        // The compare digits C and D unscramble to (CD ror 2) ^ BA.
+        assert_eq!(
+            Cheat::parse("01A-2BE-C4D"),
+            Ok(Cheat::GameGenie {
+                addr: 0x1A2B,
+                value: 0x01,
+                compare: Some(0xC9),
+            })
+        );
+        assert!(Cheat::parse("01A-2B3").is_err(), "address is not in ROM");
+        assert!(Cheat::parse("01A2BE").is_err());
+        assert!(Cheat::parse("01A-2BG").is_err());
+    }
+
+    #[test]
+    fn gameshark_codes_decode() {
+        assert_eq!(
+            Cheat::parse("01FF34C1"),
+            Ok(Cheat::GameShark {
+                kind: 0x01,
+                addr: 0xC134,
+                value: 0xFF,
+            })
+        );
+        assert_eq!(
+            Cheat::parse("91630AD0").unwrap().to_string(),
+            "WRAM1 D00A = 63"
+        );
+        assert!(Cheat::parse("A1FF34C1").is_err());
+    }
+
+    #[test]
+    fn rom_patches_follow_enabled_codes() {
+        let mut cheats = Cheats::parse(
+            "# comment\n\
+             01A-2BE-C4D  Only bank C9\n\
+             !05B-00E\n",
+        )
+        .unwrap();
+        assert_eq!(cheats.codes()[0].name, "Only bank C9");
+        assert_eq!(*cheats.patch_rom(0x1A2B, &0xC9), 0x01);
+        assert_eq!(*cheats.patch_rom(0x1A2B, &0xC8), 0xC8);
+        assert_eq!(*cheats.patch_rom(0x1B00, &0x00), 0x00);
+        cheats.set_enabled(1, true).unwrap();
+        assert_eq!(*cheats.patch_rom(0x1B00, &0x00), 0x05);
+        cheats.active = false;
+        assert_eq!(*cheats.patch_rom(0x1B00, &0x00), 0x00);
+    }
+
+    #[test]
+    fn ram_writes_select_wram_banks() {
+        let cheats = Cheats::parse("01FF34C1\n91630AD0\n!01000000").unwrap();
+        assert_eq!(
+            cheats.ram_writes(),
+            [(None, 0xC134, 0xFF), (Some(1), 0xD00A, 0x63)]
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let error = Cheats::parse("01FF34C1\nXYZ").err().unwrap();
+        assert_eq!(error, "line 2: Invalid cheat code 'XYZ'");
+    }
+}
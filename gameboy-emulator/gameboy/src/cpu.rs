@@ -0,0 +1,1037 @@
+use crate::addrreg::AddrReg;
+use crate::bus::Bus;
+use crate::dataloc::DataLoc;
+use crate::instruction::Instruction;
+use crate::reg::Reg;
+use crate::register::Registers;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use log::{debug, info, warn};
+#[cfg(feature = "cpu-debug")]
+use std::collections::VecDeque;
+
+const MSB_MASK: u8 = 0b1000_0000;
+const LSB_MASK: u8 = 0b0000_0001;
+const INTERRUPT_HANDLERS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+/// Executes the instruction of one opcode, fetching its operands. Returns the extra
+/// M-cycles a conditional instruction took.
+type Handler = fn(&mut CPU, &mut Bus) -> u32;
+
+/// Sixteen consecutive entries of `DISPATCH`, starting at `$row * 16`.
+macro_rules! handler_row {
+    ($row:literal) => {
+        [
+            execute_opcode::<{ $row * 16 }>,
+            execute_opcode::<{ $row * 16 + 0x1 }>,
+            execute_opcode::<{ $row * 16 + 0x2 }>,
+            execute_opcode::<{ $row * 16 + 0x3 }>,
+            execute_opcode::<{ $row * 16 + 0x4 }>,
+            execute_opcode::<{ $row * 16 + 0x5 }>,
+            execute_opcode::<{ $row * 16 + 0x6 }>,
+            execute_opcode::<{ $row * 16 + 0x7 }>,
+            execute_opcode::<{ $row * 16 + 0x8 }>,
+            execute_opcode::<{ $row * 16 + 0x9 }>,
+            execute_opcode::<{ $row * 16 + 0xA }>,
+            execute_opcode::<{ $row * 16 + 0xB }>,
+            execute_opcode::<{ $row * 16 + 0xC }>,
+            execute_opcode::<{ $row * 16 + 0xD }>,
+            execute_opcode::<{ $row * 16 + 0xE }>,
+            execute_opcode::<{ $row * 16 + 0xF }>,
+        ]
+    };
+}
+
+/// Handlers for the 256 opcodes, followed by the 256 opcodes after the 0xCB prefix.
+static DISPATCH: [Handler; 512] = flatten_rows(&[
+    handler_row!(0x00),
+    handler_row!(0x01),
+    handler_row!(0x02),
+    handler_row!(0x03),
+    handler_row!(0x04),
+    handler_row!(0x05),
+    handler_row!(0x06),
+    handler_row!(0x07),
+    handler_row!(0x08),
+    handler_row!(0x09),
+    handler_row!(0x0A),
+    handler_row!(0x0B),
+    handler_row!(0x0C),
+    handler_row!(0x0D),
+    handler_row!(0x0E),
+    handler_row!(0x0F),
+    handler_row!(0x10),
+    handler_row!(0x11),
+    handler_row!(0x12),
+    handler_row!(0x13),
+    handler_row!(0x14),
+    handler_row!(0x15),
+    handler_row!(0x16),
+    handler_row!(0x17),
+    handler_row!(0x18),
+    handler_row!(0x19),
+    handler_row!(0x1A),
+    handler_row!(0x1B),
+    handler_row!(0x1C),
+    handler_row!(0x1D),
+    handler_row!(0x1E),
+    handler_row!(0x1F),
+]);
+
+const fn flatten_rows(rows: &[[Handler; 16]; 32]) -> [Handler; 512] {
+    let mut table = [rows[0][0]; 512];
+    let mut i = 0;
+    while i < table.len() {
+        table[i] = rows[i / 16][i % 16];
+        i += 1;
+    }
+    table
+}
+
+/// The handler for entry `INDEX` of `DISPATCH`. With the opcode known at compile time,
+/// decoding and the dispatch on the instruction in `CPU::execute` fold away, leaving only
+/// the code for this one instruction. The 0xCB prefix fetches the next byte and
+/// dispatches to the second half of the table.
+fn execute_opcode<const INDEX: u16>(cpu: &mut CPU, bus: &mut Bus) -> u32 {
+    let opcode = INDEX as u8;
+    if INDEX == 0xCB {
+        let cb_opcode = cpu.next_byte(bus);
+        return DISPATCH[0x100 | cb_opcode as usize](cpu, bus);
+    }
+    let instruction = if INDEX < 0x100 {
+        Instruction::decode(opcode, || cpu.next_byte(bus))
+    } else {
+        Instruction::decode(0xCB, || opcode)
+    };
+    match instruction {
+        Some(instruction) => cpu.execute(instruction, bus),
+        None => {
+            cpu.lock(opcode);
+            0
+        }
+    }
+}
+
+#[cfg(feature = "cpu-debug")]
+struct ExecLog {
+    pc: u16,
+    byte: u8,
+    instruction: Instruction,
+    registers: Registers,
+    timer_regs: [u8; 4],
+}
+
+pub struct CPU {
+    pub reg: Registers,
+    last: Instruction,
+    /// Opcode fetched by the last `run_cycle`, `None` if it didn't execute anything.
+    last_opcode: Option<u8>,
+    /// Master interrupt enable (IME) flag
+    ime: bool,
+    ie_delay: i8,
+    halted: bool,
+    /// An illegal opcode hung the CPU. Only a reset gets it going again.
+    locked: bool,
+    /// The last 1000 instructions, printed when the CPU crashes.
+    #[cfg(feature = "cpu-debug")]
+    dbg_exec_log: VecDeque<ExecLog>,
+    #[cfg(feature = "cpu-debug")]
+    instructions_out_of_interrupt: usize,
+    /// Executions of every opcode, indexed like `DISPATCH`.
+    #[cfg(feature = "cpu-debug")]
+    pub instructions_count: [usize; 2 * 256],
+}
+
+impl CPU {
+    pub fn new() -> Self {
+        Self {
+            reg: Registers::new(),
+            last: Instruction::NOP,
+            last_opcode: None,
+            ime: false,
+            ie_delay: -1,
+            halted: false,
+            locked: false,
+            #[cfg(feature = "cpu-debug")]
+            dbg_exec_log: VecDeque::new(),
+            #[cfg(feature = "cpu-debug")]
+            instructions_out_of_interrupt: 0,
+            #[cfg(feature = "cpu-debug")]
+            instructions_count: [0; 2 * 256],
+        }
+    }
+
+    /// The most recently executed instruction.
+    pub fn last_instruction(&self) -> &Instruction {
+        &self.last
+    }
+
+    /// Opcode of the instruction the last `run_cycle` executed, or `None` if the CPU was
+    /// halted or locked up. CB-prefixed instructions give 0xCB. Unlike the byte at PC
+    /// before a step, this is never the instruction an interrupt dispatch jumped over.
+    pub fn last_opcode(&self) -> Option<u8> {
+        self.last_opcode
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Hangs the CPU on an illegal opcode, as the hardware does. Interrupts can't wake it.
+    fn lock(&mut self, opcode: u8) {
+        warn!(
+            "Illegal opcode {:02x} at {:04x}, CPU locked up",
+            opcode,
+            self.reg.pc.wrapping_sub(1)
+        );
+        self.print_exec_log();
+        self.locked = true;
+    }
+
+    pub fn check_interrupts(&mut self, bus: &mut Bus) {
+        if self.locked {
+            return;
+        }
+        let ie = bus.mem[0xFFFF];
+        let if_ = bus.mem[0xFF0F];
+
+        if ie & if_ > 0 {
+            self.halted = false;
+        }
+
+        if !self.ime {
+            return;
+        }
+
+        for b in 0..5 {
+            if (if_ >> b) & 1 != 0 && (ie >> b) & 1 != 0 {
+                bus.mem[0xFF0F] &= !(1 << b);
+                if self.ime {
+                    // Two wait cycles, pushing PC and jumping take 5 M-cycles.
+                    self.ime = false;
+                    bus.tick();
+                    bus.tick();
+                    self.call(INTERRUPT_HANDLERS[b], bus);
+                    bus.tick();
+                }
+                return;
+            }
+        }
+    }
+
+    fn next_byte(&mut self, bus: &mut Bus) -> u8 {
+        if !matches!(self.reg.pc, 0..0x7FFF | 0xFF80..=0xFFFE) {
+            // self.cpu_crash("PC escaped valid code".to_string())
+        }
+        let byte = bus.read(self.reg.pc);
+        self.reg.pc += 1;
+        byte
+    }
+
+    pub fn run_cycle(&mut self, bus: &mut Bus) -> u32 {
+        if self.halted || self.locked {
+            self.last_opcode = None;
+            bus.tick();
+            return 1;
+        }
+        let start_m_cycles = bus.m_cycles();
+        #[cfg(feature = "cpu-debug")]
+        let pc = self.reg.pc;
+
+        let opcode = self.next_byte(bus);
+        self.last_opcode = Some(opcode);
+        let conditional_extra_cycles = DISPATCH[opcode as usize](self, bus);
+
+        if self.ie_delay == 0 {
+            self.ime = true;
+            debug!("IME enabled");
+        }
+        if self.ie_delay >= 0 {
+            self.ie_delay -= 1;
+        }
+
+        let m_cycles = bus.m_cycles() - start_m_cycles;
+        debug_assert!(
+            self.locked || m_cycles == self.last.machine_cycles() as u32 + conditional_extra_cycles,
+            "M-cycles of {}",
+            self.last
+        );
+
+        #[cfg(feature = "cpu-debug")]
+        self.record_debug_info(pc, opcode, bus);
+        m_cycles
+    }
+
+    /// Counts the instruction and adds it to the exec log.
+    #[cfg(feature = "cpu-debug")]
+    fn record_debug_info(&mut self, pc: u16, opcode: u8, bus: &Bus) {
+        self.instructions_count[opcode as usize] += 1;
+        if opcode == 0xCB {
+            self.instructions_count[0x100 | bus.mem[pc.wrapping_add(1)] as usize] += 1;
+        }
+        let entry = ExecLog {
+            pc,
+            byte: opcode,
+            instruction: self.last.clone(),
+            registers: self.reg.clone(),
+            timer_regs: [
+                bus.mem[0xFF26],
+                bus.mem[0xFF11],
+                bus.mem[0xFF14],
+                bus.mem[0xFF12],
+            ],
+        };
+        self.dbg_exec_log.push_front(entry);
+        if self.dbg_exec_log.len() > 1000 {
+            let _ = self.dbg_exec_log.pop_back();
+        }
+        if self.ime {
+            self.instructions_out_of_interrupt += 1;
+        }
+    }
+
+    /// Executes a decoded instruction whose operands have been fetched. Returns the extra
+    /// M-cycles a conditional instruction took because its condition held. Always inlined
+    /// into the dispatch table handlers, where the instruction is a constant and the
+    /// matches below fold away.
+    #[inline(always)]
+    fn execute(&mut self, instruction: Instruction, bus: &mut Bus) -> u32 {
+        let mut conditional_extra_cycles = 0u32;
+        match instruction.clone() {
+            Instruction::INC(l) => {
+                let (old, res) = match l {
+                    DataLoc::Reg(r) => {
+                        let old = self.reg.get(r);
+                        let res = old.wrapping_add(1);
+                        self.reg.set(r, res);
+                        (old, res)
+                    }
+                    DataLoc::AddrReg(AddrReg::HL) => {
+                        let addr = self.reg.get_pair(AddrReg::HL);
+                        let old = bus.read(addr);
+                        let res = old.wrapping_add(1);
+                        bus.write(addr, res);
+                        (old, res)
+                    }
+                    _ => self.cpu_crash("Not in instruction set.".to_string()),
+                };
+                self.reg.set_flag(7, res == 0);
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, old & 0x0F == 0x0F);
+            }
+            Instruction::INC16(r) => {
+                bus.tick();
+                self.reg.set_pair(r, self.reg.get_pair(r).wrapping_add(1));
+            }
+            Instruction::DEC(l) => {
+                let (_, res) = match l {
+                    DataLoc::Reg(r) => {
+                        let old = self.reg.get(r);
+                        let res = old.wrapping_sub(1);
+                        self.reg.set(r, res);
+                        (old, res)
+                    }
+                    DataLoc::AddrReg(AddrReg::HL) => {
+                        let addr = self.reg.get_pair(AddrReg::HL);
+                        let old = bus.read(addr);
+                        let res = old.wrapping_sub(1);
+                        bus.write(addr, res);
+                        (old, res)
+                    }
+                    _ => self.cpu_crash("Not in instruction set.".to_string()),
+                };
+                self.reg.set_flag(7, res == 0);
+                self.reg.set_flag(6, true);
+                self.reg.set_flag(5, res & 0x0F == 0x0F);
+            }
+            Instruction::DEC16(r) => {
+                bus.tick();
+                self.reg.set_pair(r, self.reg.get_pair(r).wrapping_sub(1));
+            }
+            Instruction::JP1(addr) => {
+                bus.tick();
+                self.reg.pc = addr;
+            }
+            Instruction::JP2(c, addr) => {
+                if self.reg.eval_condition(c) {
+                    conditional_extra_cycles = 1;
+                    bus.tick();
+                    self.reg.pc = addr;
+                }
+            }
+            Instruction::JP3 => {
+                self.reg.pc = self.reg.get_pair(AddrReg::HL);
+            }
+            Instruction::JR4(addr) => {
+                bus.tick();
+                self.reg.pc = self.reg.pc.wrapping_add_signed(addr as i16);
+            }
+            Instruction::JR5(c, addr) => {
+                if self.reg.eval_condition(c) {
+                    conditional_extra_cycles = 1;
+                    bus.tick();
+                    self.reg.pc = self.reg.pc.wrapping_add_signed(addr as i16);
+                }
+            }
+            Instruction::LD(a, b) => {
+                self.ld_8_bit(a, b, bus);
+            }
+            Instruction::LD5 => {
+                self.ld_8_bit(
+                    DataLoc::Reg(Reg::A),
+                    DataLoc::Addr(0xFF00 | self.reg.c as u16),
+                    bus,
+                );
+            }
+            Instruction::LD6 => {
+                self.ld_8_bit(
+                    DataLoc::Addr(0xFF00 | self.reg.c as u16),
+                    DataLoc::Reg(Reg::A),
+                    bus,
+                );
+            }
+            Instruction::LDH1(o) => {
+                bus.write(0xFF00 | o as u16, self.reg.a);
+            }
+            Instruction::LDH2(o) => {
+                self.reg.a = bus.read(0xFF00 | o as u16);
+            }
+            Instruction::LDI(a, b) => {
+                self.ld_8_bit(a, b, bus);
+                self.reg
+                    .set_pair(AddrReg::HL, self.reg.get_pair(AddrReg::HL).wrapping_add(1));
+            }
+            Instruction::LDD(a, b) => {
+                self.ld_8_bit(a, b, bus);
+                self.reg
+                    .set_pair(AddrReg::HL, self.reg.get_pair(AddrReg::HL).wrapping_sub(1));
+            }
+            Instruction::LD16(reg, v) => {
+                self.reg.set_pair(reg, v);
+            }
+            Instruction::LDSPHL => {
+                bus.tick();
+                self.reg
+                    .set_pair(AddrReg::SP, self.reg.get_pair(AddrReg::HL));
+            }
+            Instruction::LDHL(offset) => {
+                let hi = (self.reg.sp >> 8) as u8;
+                let lo = (self.reg.sp & 0x00FF) as u8;
+                let s = ((offset as u8) & MSB_MASK) != 0;
+
+                let (r_lo, c) = lo.overflowing_add(offset as u8);
+                let (_, h) = (lo << 4).overflowing_add((offset as u8) << 4);
+
+                let r_hi = if c && !s {
+                    hi.wrapping_add(1)
+                } else if s && !c {
+                    hi.wrapping_sub(1)
+                } else {
+                    hi
+                };
+                let r = ((r_hi as u16) << 8) | (r_lo as u16);
+                bus.tick();
+                self.reg.set_pair(AddrReg::HL, r);
+                self.reg.set_flag(7, false);
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, h);
+                self.reg.set_flag(4, c);
+            }
+            Instruction::LDnn(r) => {
+                bus.write(r, (self.reg.sp & 0x00FF) as u8);
+                bus.write(r.wrapping_add(1), (self.reg.sp >> 8) as u8);
+            }
+            Instruction::PUSH(r) => {
+                bus.tick();
+                self.push(self.reg.get_pair(r), bus);
+            }
+            Instruction::POP(r) => {
+                let val = self.pop(bus);
+                self.reg.set_pair(r, val);
+            }
+            Instruction::ADD(l) => {
+                self.reg.a = self.add_set_flags(l, false, bus);
+            }
+            Instruction::ADC(l) => {
+                self.reg.a = self.add_set_flags(l, true, bus);
+            }
+            Instruction::ADD16(r) => {
+                let hl = self.reg.get_pair(AddrReg::HL);
+                let rhs = self.reg.get_pair(r);
+                let (_, h) = (hl << 4).overflowing_add(rhs << 4);
+                let (r, c) = hl.overflowing_add(rhs);
+                bus.tick();
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, h);
+                self.reg.set_flag(4, c);
+                self.reg.set_pair(AddrReg::HL, r);
+            }
+            Instruction::ADD16n(n) => {
+                let hi = (self.reg.sp >> 8) as u8;
+                let lo = (self.reg.sp & 0x00FF) as u8;
+                let s = ((n as u8) & MSB_MASK) != 0;
+
+                let (r_lo, c) = lo.overflowing_add(n as u8);
+                let (_, h) = (lo << 4).overflowing_add((n as u8) << 4);
+
+                let r_hi = if c && !s {
+                    hi.wrapping_add(1)
+                } else if s && !c {
+                    hi.wrapping_sub(1)
+                } else {
+                    hi
+                };
+                let r = ((r_hi as u16) << 8) | (r_lo as u16);
+                bus.tick();
+                bus.tick();
+                self.reg.set_flag(7, false);
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, h);
+                self.reg.set_flag(4, c);
+                self.reg.set_pair(AddrReg::SP, r);
+            }
+            Instruction::SUB(l) => {
+                self.reg.a = self.sub_set_flags(l, false, bus);
+            }
+            Instruction::SBC(l) => {
+                self.reg.a = self.sub_set_flags(l, true, bus);
+            }
+            Instruction::AND(l) => {
+                self.reg.a = self.and_set_flags(l, bus);
+            }
+            Instruction::OR(l) => {
+                self.reg.a = self.or_set_flags(l, bus);
+            }
+            Instruction::XOR(l) => {
+                self.reg.a = self.xor_set_flags(l, bus);
+            }
+            Instruction::SWAP(r) => {
+                self.apply_to_7_bit_reg(Self::swap, r, bus);
+            }
+            Instruction::CP(l) => {
+                let _ = self.sub_set_flags(l, false, bus);
+            }
+            Instruction::CALL(addr) => {
+                bus.tick();
+                self.call(addr, bus);
+            }
+            Instruction::CALLc(cond, addr) => {
+                if self.reg.eval_condition(cond) {
+                    conditional_extra_cycles = 3;
+                    bus.tick();
+                    self.call(addr, bus);
+                }
+            }
+            Instruction::RET => {
+                self.ret(bus);
+            }
+            Instruction::RETc(cond) => {
+                // Checking the condition takes a cycle of its own.
+                bus.tick();
+                if self.reg.eval_condition(cond) {
+                    conditional_extra_cycles = 3;
+                    self.ret(bus);
+                }
+            }
+            Instruction::RETI => {
+                self.ret(bus);
+                self.ime = true;
+                #[cfg(feature = "cpu-debug")]
+                {
+                    self.instructions_out_of_interrupt = 0;
+                }
+                debug!("IME enabled");
+                // self.cpu_crash("test".to_string());
+            }
+            Instruction::BIT(b, n) => {
+                if b > 7 {
+                    self.cpu_crash(format!("Invalid RES bit: {b}"));
+                }
+                let mask = (1 << b) as u8;
+                let z = match n {
+                    DataLoc::Reg(r) => self.reg.get(r) & mask,
+                    DataLoc::AddrReg(AddrReg::HL) => {
+                        let addr = self.reg.get_pair(AddrReg::HL);
+                        bus.read(addr) & mask
+                    }
+                    _ => self.cpu_crash("Not in instruction set.".to_string()),
+                };
+                self.reg.set_flag(7, z == 0);
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, true);
+            }
+            Instruction::RES(b, n) => {
+                if b > 7 {
+                    self.cpu_crash(format!("Invalid RES bit: {b}"));
+                }
+                let mask = !((1 << b) as u8);
+                match n {
+                    DataLoc::Reg(r) => self.reg.set(r, self.reg.get(r) & mask),
+                    DataLoc::AddrReg(AddrReg::HL) => {
+                        let addr = self.reg.get_pair(AddrReg::HL);
+                        let v = bus.read(addr);
+                        bus.write(addr, v & mask);
+                    }
+                    _ => self.cpu_crash("Not in instruction set.".to_string()),
+                }
+            }
+            Instruction::SET(b, n) => {
+                if b > 7 {
+                    panic!("Invalid SET bit: {b}");
+                }
+                let mask = (1 << b) as u8;
+                match n {
+                    DataLoc::Reg(r) => self.reg.set(r, self.reg.get(r) | mask),
+                    DataLoc::AddrReg(AddrReg::HL) => {
+                        let addr = self.reg.get_pair(AddrReg::HL);
+                        let v = bus.read(addr);
+                        bus.write(addr, v | mask);
+                    }
+                    _ => self.cpu_crash("Not in instruction set.".to_string()),
+                }
+            }
+            Instruction::DAA => {
+                // http://www.z80.info/z80syntx.htm#DAA
+                let c = self.reg.get_flag(4);
+                let h = self.reg.get_flag(5);
+                let n = self.reg.get_flag(6);
+                let a = self.reg.a;
+                let lo = a & 0xF;
+
+                let mut offset = 0u8;
+                let mut c_after = false;
+                if (!n && lo > 0x09) || h {
+                    offset |= 0x06;
+                }
+
+                if (!n && a > 0x99) || c {
+                    offset |= 0x60;
+                    c_after = true;
+                }
+
+                self.reg.a = if !n {
+                    a.wrapping_add(offset)
+                } else {
+                    a.wrapping_sub(offset)
+                };
+
+                self.reg.set_flag(7, self.reg.a == 0);
+                self.reg.set_flag(5, false);
+                self.reg.set_flag(4, c_after);
+            }
+            Instruction::CPL => {
+                self.reg.a = !self.reg.a;
+                self.reg.set_flag(6, true);
+                self.reg.set_flag(5, true);
+            }
+            Instruction::CCF => {
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, false);
+                self.reg.set_flag(4, !self.reg.get_flag(4));
+            }
+            Instruction::SCF => {
+                self.reg.set_flag(6, false);
+                self.reg.set_flag(5, false);
+                self.reg.set_flag(4, true);
+            }
+            Instruction::NOP => {}
+            Instruction::HALT => {
+                if !self.ime && bus.mem[0xFF0F] & bus.mem[0xFFFF] != 0 {
+                    self.cpu_crash("HALT BUG".to_string());
+                }
+                self.halted = true;
+            }
+            Instruction::STOP => {
+                let button_pressed = bus.mem[0xFF00] & 0x0F == 0;
+                let interrupt_pending = bus.mem[0xFF0F] & bus.mem[0xFFFF] > 0;
+                let speed_key_requested = bus.mem.speed_switch_armed();
+                if button_pressed {
+                    if interrupt_pending {
+                        // 1 byte OP code
+                    } else {
+                        // self.next_byte(mem);
+                        self.halted = true;
+                    }
+                } else {
+                    if speed_key_requested {
+                        if interrupt_pending && !self.ime {
+                            self.cpu_crash("CPU glitch".to_string());
+                        }
+                        // The CPU pauses for 2050 M-cycles while the clock settles, which
+                        // is not emulated.
+                        bus.mem[0xFF04] = 0x00;
+                        bus.mem.switch_speed();
+                        info!("Speed change");
+                    } else {
+                        if interrupt_pending {
+                            bus.mem[0xFF04] = 0x00;
+                            info!("STOP MODE");
+                        } else {
+                            bus.mem[0xFF04] = 0x00;
+                            // self.next_byte(mem);
+                            info!("STOP MODE");
+                        }
+                    }
+                }
+            }
+            Instruction::DI => {
+                debug!("IME disabled");
+                self.ime = false
+            }
+            Instruction::EI => self.ie_delay = 1,
+            Instruction::RLCA => {
+                self.reg.a = Self::rotate_left_into_carry(self.reg.a, false, &mut self.reg);
+            }
+            Instruction::RLA => {
+                self.reg.a = Self::rotate_left_through_carry(self.reg.a, false, &mut self.reg);
+            }
+            Instruction::RRCA => {
+                self.reg.a = Self::rotate_right_into_carry(self.reg.a, false, &mut self.reg);
+            }
+            Instruction::RRA => {
+                self.reg.a = Self::rotate_right_through_carry(self.reg.a, false, &mut self.reg);
+            }
+            Instruction::RLC(r) => {
+                self.apply_to_7_bit_reg(Self::rotate_left_into_carry, r, bus);
+            }
+            Instruction::RL(r) => {
+                self.apply_to_7_bit_reg(Self::rotate_left_through_carry, r, bus);
+            }
+            Instruction::RRC(r) => {
+                self.apply_to_7_bit_reg(Self::rotate_right_into_carry, r, bus);
+            }
+            Instruction::RR(r) => {
+                self.apply_to_7_bit_reg(Self::rotate_right_through_carry, r, bus);
+            }
+            Instruction::SLA(r) => {
+                self.apply_to_7_bit_reg(Self::shift_left_into_carry, r, bus);
+            }
+            Instruction::SRA(r) => {
+                self.apply_to_7_bit_reg(Self::shift_right_into_carry_keep_msb, r, bus);
+            }
+            Instruction::SRL(r) => {
+                self.apply_to_7_bit_reg(Self::shift_right_into_carry, r, bus);
+            }
+            Instruction::RST(proc) => {
+                if proc == 0x38 {
+                    // self.cpu_crash("HIT RST 0x38".to_string());
+                }
+                let curr = self.reg.pc; // PC was incremented, decrement to get current
+                bus.tick();
+                self.push(curr, bus);
+                self.reg.pc = 0x0000 | proc as u16;
+            }
+        }
+        self.last = instruction;
+        conditional_extra_cycles
+    }
+
+    fn apply_to_7_bit_reg<F>(&mut self, f: F, r: DataLoc, bus: &mut Bus)
+    where
+        F: Fn(u8, bool, &mut Registers) -> u8,
+    {
+        let n = match r {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+        let n_new = f(n, true, &mut self.reg);
+        match r {
+            DataLoc::Reg(r) => self.reg.set(r, n_new),
+            DataLoc::AddrReg(AddrReg::HL) => bus.write(self.reg.get_pair(AddrReg::HL), n_new),
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+    }
+
+    fn swap(n: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let msn = n >> 4;
+        let lsn = n & 0xF;
+        let n_new = (lsn << 4) | msn;
+        reg.set_flag(7, n_new == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, false);
+        n_new
+    }
+
+    fn rotate_left_into_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = a_old.rotate_left(1);
+        // TODO gameboy manual says to set Z, z80 manual says not to. Not setting it gets
+        //  test further with the same amount of instructions
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & MSB_MASK) != 0);
+        a
+    }
+
+    fn rotate_left_through_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = (a_old << 1) | reg.get_flag(4) as u8;
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & MSB_MASK) != 0);
+        a
+    }
+
+    fn rotate_right_into_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = a_old.rotate_right(1);
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & LSB_MASK) != 0);
+        a
+    }
+
+    fn rotate_right_through_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = (a_old >> 1) | ((reg.get_flag(4) as u8) << 7);
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & LSB_MASK) != 0);
+        a
+    }
+
+    fn shift_left_into_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = a_old << 1;
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & MSB_MASK) != 0);
+        a
+    }
+
+    fn shift_right_into_carry(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = a_old >> 1;
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & LSB_MASK) != 0);
+        a
+    }
+
+    fn shift_right_into_carry_keep_msb(a_old: u8, set_z: bool, reg: &mut Registers) -> u8 {
+        let a = (a_old >> 1) | (a_old & MSB_MASK);
+        reg.set_flag(7, a == 0 && set_z);
+        reg.set_flag(6, false);
+        reg.set_flag(5, false);
+        reg.set_flag(4, (a_old & LSB_MASK) != 0);
+        a
+    }
+
+    fn cpu_crash(&mut self, message: String) -> ! {
+        self.print_exec_log();
+        panic!("{}", message);
+    }
+
+    /// Prints the exec log, which is only kept with the `cpu-debug` feature.
+    pub fn print_exec_log(&mut self) {
+        #[cfg(feature = "cpu-debug")]
+        while let Some(entry) = self.dbg_exec_log.pop_back() {
+            debug!(
+                "Executed {:04x} {:02x} {:<30} {:x?} {:02x?}",
+                entry.pc,
+                entry.byte,
+                format!("{:x?}", entry.instruction),
+                entry.registers,
+                entry.timer_regs,
+            );
+        }
+    }
+
+    fn push(&mut self, val: u16, bus: &mut Bus) {
+        let ls = (val & 0xFF) as u8;
+        let ms = (val >> 8) as u8;
+        // mem[self.reg.sp] = ls;
+        // mem[self.reg.sp - 1] = ms;
+        bus.write(self.reg.sp.wrapping_sub(1), ms);
+        bus.write(self.reg.sp.wrapping_sub(2), ls);
+        self.reg.sp = self.reg.sp.wrapping_sub(2);
+    }
+
+    fn pop(&mut self, bus: &mut Bus) -> u16 {
+        // let ls = mem[self.reg.sp + 2];
+        // let ms = mem[self.reg.sp + 1];
+        let ls = bus.read(self.reg.sp);
+        let ms = bus.read(self.reg.sp.wrapping_add(1));
+        self.reg.sp = self.reg.sp.wrapping_add(2);
+        ((ms as u16) << 8) | (ls as u16)
+    }
+
+    fn call(&mut self, addr: u16, bus: &mut Bus) {
+        let pc_next = self.reg.pc; // Program Counter is already at next instruction.
+        self.push(pc_next, bus);
+        self.reg.pc = addr;
+    }
+
+    fn ret(&mut self, bus: &mut Bus) {
+        self.reg.pc = self.pop(bus);
+        bus.tick();
+    }
+
+    fn add_set_flags(&mut self, l: DataLoc, add_carry: bool, bus: &mut Bus) -> u8 {
+        let n = match l {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            DataLoc::Value(v) => v,
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        } as u16;
+        let a = self.reg.a as u16;
+        let c_extra = add_carry as u16 * self.reg.get_flag(4) as u16;
+        let r = a + n + c_extra;
+        let h = (a & 0x0F) + (n & 0x0F) + c_extra > 0x0F;
+        let c = (r >> 8) != 0;
+        self.reg.set_flag(7, (r as u8) == 0);
+        self.reg.set_flag(6, false);
+        self.reg.set_flag(5, h);
+        self.reg.set_flag(4, c);
+        r as u8
+    }
+
+    fn sub_set_flags(&mut self, l: DataLoc, add_carry: bool, bus: &mut Bus) -> u8 {
+        let n = match l {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            DataLoc::Value(v) => v,
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+        let a = self.reg.a;
+        let c_extra = add_carry as u8 & self.reg.get_flag(4) as u8;
+
+        let (r1, c1) = a.overflowing_sub(n);
+        let (r, c2) = r1.overflowing_sub(c_extra);
+        let c = c1 || c2;
+
+        let (r1, h1) = (a << 4).overflowing_sub(n << 4);
+        let (_, h2) = r1.overflowing_sub(c_extra << 4);
+        let h = h1 || h2;
+
+        self.reg.set_flag(7, r == 0);
+        self.reg.set_flag(6, true);
+        self.reg.set_flag(5, h);
+        self.reg.set_flag(4, c);
+        r & 0x00FF
+    }
+
+    fn and_set_flags(&mut self, l: DataLoc, bus: &mut Bus) -> u8 {
+        let n = match l {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            DataLoc::Value(v) => v,
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+        let r = self.reg.a & n;
+        self.reg.set_flag(7, r == 0);
+        self.reg.set_flag(6, false);
+        self.reg.set_flag(5, true);
+        self.reg.set_flag(4, false);
+        r
+    }
+
+    fn or_set_flags(&mut self, l: DataLoc, bus: &mut Bus) -> u8 {
+        let n = match l {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            DataLoc::Value(v) => v,
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+        let r = self.reg.a | n;
+        self.reg.set_flag(7, r == 0);
+        self.reg.set_flag(6, false);
+        self.reg.set_flag(5, false);
+        self.reg.set_flag(4, false);
+        r
+    }
+
+    fn xor_set_flags(&mut self, l: DataLoc, bus: &mut Bus) -> u8 {
+        let n = match l {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(AddrReg::HL) => bus.read(self.reg.get_pair(AddrReg::HL)),
+            DataLoc::Value(v) => v,
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        };
+        let r = self.reg.a ^ n;
+        self.reg.set_flag(7, r == 0);
+        self.reg.set_flag(6, false);
+        self.reg.set_flag(5, false);
+        self.reg.set_flag(4, false);
+        r
+    }
+
+    fn ld_8_bit(&mut self, to: DataLoc, from: DataLoc, bus: &mut Bus) {
+        // TODO this might be optimised by making the datalocs generic
+        let v = match from {
+            DataLoc::Reg(r) => self.reg.get(r),
+            DataLoc::AddrReg(r) => bus.read(self.reg.get_pair(r)),
+            DataLoc::Addr(addr) => bus.read(addr),
+            DataLoc::Value(v) => v,
+        };
+        match to {
+            DataLoc::Reg(r) => self.reg.set(r, v),
+            DataLoc::AddrReg(r) => bus.write(self.reg.get_pair(r), v),
+            DataLoc::Addr(addr) => bus.write(addr, v),
+            _ => self.cpu_crash("Not in instruction set.".to_string()),
+        }
+    }
+}
+
+impl Snapshot for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.reg.save_state(w);
+        w.write_bool(self.ime);
+        w.write_u8(self.ie_delay as u8);
+        w.write_bool(self.halted);
+        w.write_bool(self.locked);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.reg.load_state(r)?;
+        self.ime = r.read_bool()?;
+        self.ie_delay = r.read_u8()? as i8;
+        self.halted = r.read_bool()?;
+        self.locked = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::APU;
+    use crate::cartridge_header::CartridgeHeader;
+    use crate::memory::Memory;
+    use crate::ppu::PPU;
+    use crate::serial::Serial;
+
+    #[test]
+    fn illegal_opcode_locks_cpu() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x103].copy_from_slice(&[0x00, 0xD3, 0x00]);
+        let header = CartridgeHeader::read(&rom).unwrap();
+        let mut mem = Memory::new(Vec::new(), rom, header).unwrap();
+        mem[0xFF50] = 1;
+        let (mut ppu, mut apu, mut serial) = (PPU::new(), APU::new(), Serial::new());
+        let mut bus = Bus::new(&mut mem, &mut ppu, &mut apu, &mut serial);
+        let mut cpu = CPU::new();
+        cpu.reg.pc = 0x100;
+        cpu.ime = true;
+
+        cpu.run_cycle(&mut bus);
+        assert!(!cpu.is_locked());
+        cpu.run_cycle(&mut bus);
+        assert!(cpu.is_locked());
+        assert_eq!(cpu.reg.pc, 0x102);
+
+        // Neither execution nor interrupts get it going again.
+        bus.mem[0xFFFF] = 0x01;
+        bus.mem[0xFF0F] = 0x01;
+        for _ in 0..10 {
+            cpu.check_interrupts(&mut bus);
+            assert_eq!(cpu.run_cycle(&mut bus), 1);
+        }
+        assert_eq!(cpu.reg.pc, 0x102);
+    }
+}
@@ -0,0 +1,22 @@
+use crate::addrreg::AddrReg;
+use crate::reg::Reg;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum DataLoc {
+    Reg(Reg),
+    AddrReg(AddrReg),
+    Addr(u16),
+    Value(u8),
+}
+
+impl fmt::Display for DataLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataLoc::Reg(r) => write!(f, "{:?}", r),
+            DataLoc::AddrReg(r) => write!(f, "({:?})", r),
+            DataLoc::Addr(addr) => write!(f, "(${:04X})", addr),
+            DataLoc::Value(v) => write!(f, "${:02X}", v),
+        }
+    }
+}
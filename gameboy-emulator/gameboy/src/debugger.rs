@@ -0,0 +1,667 @@
+use crate::addrreg::AddrReg;
+use crate::cpu::CPU;
+use crate::gameboy::GameBoy;
+use crate::instruction::Instruction;
+use crate::memory::{AccessKind, Memory, MemoryAccess};
+use crate::reg::Reg;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+
+/// Instructions shown before and after PC by `dis` without arguments.
+const DISASSEMBLY_CONTEXT_BEFORE: usize = 4;
+const DISASSEMBLY_CONTEXT_AFTER: usize = 6;
+/// Longest instruction, used when searching backwards for instruction boundaries.
+const MAX_INSTRUCTION_LENGTH: u16 = 3;
+const HELP: &str = "\
+Commands (addresses and values are hex, prefix values with # for decimal):
+  c, continue                  resume execution
+  s, step [n]                  execute n instructions (default 1)
+  n, next                      step over calls
+  finish                       run until the current function returns
+  b, break <addr> [if <cond>]  break before executing <addr>, e.g. `b 150 if a == $3`
+  w, watch <addr>[-<end>] [r|w|rw]
+                               break after the CPU reads and/or writes the range
+  d, delete <id>               remove a breakpoint or watchpoint
+  l, list                      list breakpoints and watchpoints
+  r, regs                      show registers
+  x <addr> [len]               dump memory
+  dis [addr] [count]           disassemble, around PC by default
+  cheat                        list cheats
+  cheat add <code> [name]      add a Game Genie or GameShark code
+  cheat on|off|rm <id>         enable, disable or remove a cheat
+  q, quit                      exit the emulator
+Conditions compare two operands with ==, !=, <, <=, > or >=. Operands are registers
+(a, f, b, c, d, e, h, l, af, bc, de, hl, sp, pc), values or memory bytes like [ff44] or [hl].
+An empty line repeats the last command.";
+
+/// Decodes the instruction at `addr` without side effects. Returns the instruction, if the
+/// opcode is valid, and its length in bytes.
+pub fn decode_at(mem: &Memory, addr: u16) -> (Option<Instruction>, u16) {
+    let mut len = 1;
+    let instruction = Instruction::decode(mem[addr], || {
+        let byte = mem[addr.wrapping_add(len)];
+        len += 1;
+        byte
+    });
+    (instruction, len)
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(Reg),
+    F,
+    Pair(AddrReg),
+    PC,
+    Value(u16),
+    /// The byte at the address given by the inner operand.
+    Mem(Box<Operand>),
+}
+
+impl Operand {
+    fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return Ok(Operand::Mem(Box::new(Operand::parse(inner)?)));
+        }
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "a" => Operand::Reg(Reg::A),
+            "b" => Operand::Reg(Reg::B),
+            "c" => Operand::Reg(Reg::C),
+            "d" => Operand::Reg(Reg::D),
+            "e" => Operand::Reg(Reg::E),
+            "h" => Operand::Reg(Reg::H),
+            "l" => Operand::Reg(Reg::L),
+            "f" => Operand::F,
+            "af" => Operand::Pair(AddrReg::AF),
+            "bc" => Operand::Pair(AddrReg::BC),
+            "de" => Operand::Pair(AddrReg::DE),
+            "hl" => Operand::Pair(AddrReg::HL),
+            "sp" => Operand::Pair(AddrReg::SP),
+            "pc" => Operand::PC,
+            _ => Operand::Value(parse_value(s)?),
+        })
+    }
+
+    fn eval(&self, cpu: &CPU, mem: &Memory) -> u16 {
+        match self {
+            Operand::Reg(r) => cpu.reg.get(*r) as u16,
+            Operand::F => cpu.reg.get_pair(AddrReg::AF) & 0xFF,
+            Operand::Pair(r) => cpu.reg.get_pair(*r),
+            Operand::PC => cpu.reg.pc,
+            Operand::Value(v) => *v,
+            Operand::Mem(addr) => mem[addr.eval(cpu, mem)] as u16,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "{:?}", r),
+            Operand::F => write!(f, "F"),
+            Operand::Pair(r) => write!(f, "{:?}", r),
+            Operand::PC => write!(f, "PC"),
+            Operand::Value(v) => write!(f, "${:X}", v),
+            Operand::Mem(addr) => write!(f, "[{}]", addr),
+        }
+    }
+}
+
+/// A comparison like `a == $10` or `[ff44] >= #144`, checked when a breakpoint is hit.
+#[derive(Debug, Clone)]
+pub struct BreakCondition {
+    lhs: Operand,
+    op: &'static str,
+    rhs: Operand,
+}
+
+impl BreakCondition {
+    const OPERATORS: [&'static str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        for op in Self::OPERATORS {
+            if let Some((lhs, rhs)) = s.split_once(op) {
+                return Ok(Self {
+                    lhs: Operand::parse(lhs)?,
+                    op,
+                    rhs: Operand::parse(rhs)?,
+                });
+            }
+        }
+        Err(format!("No comparison operator in condition '{}'", s))
+    }
+
+    pub fn eval(&self, cpu: &CPU, mem: &Memory) -> bool {
+        let lhs = self.lhs.eval(cpu, mem);
+        let rhs = self.rhs.eval(cpu, mem);
+        match self.op {
+            "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<=" => lhs <= rhs,
+            ">=" => lhs >= rhs,
+            "<" => lhs < rhs,
+            ">" => lhs > rhs,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for BreakCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+struct Breakpoint {
+    id: u32,
+    addr: u16,
+    condition: Option<BreakCondition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+struct Watchpoint {
+    id: u32,
+    start: u16,
+    end: u16,
+    kind: WatchKind,
+}
+
+enum RunMode {
+    Continue,
+    Step(u32),
+    /// Stop once execution is back at `return_pc` with the stack unwound to `sp`.
+    StepOver {
+        return_pc: u16,
+        sp: u16,
+    },
+    /// Stop after a return pops the stack above `sp`.
+    Finish {
+        sp: u16,
+    },
+}
+
+/// What the emulator should do after the REPL returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplExit {
+    Resume,
+    Quit,
+}
+
+/// Breakpoints, watchpoints and stepping state. Attach it to `GameBoy::debugger`; the
+/// GameBoy then checks it after every instruction and stops `run_frame` early once it
+/// requests a stop, after which `run_repl` takes over.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: u32,
+    mode: RunMode,
+    stop_reason: Option<String>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            mode: RunMode::Continue,
+            stop_reason: None,
+            last_command: String::new(),
+        }
+    }
+
+    /// Stops before the next instruction.
+    pub fn break_now(&mut self, reason: &str) {
+        self.stop_reason = Some(reason.to_string());
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop_reason.is_some()
+    }
+
+    pub fn has_watchpoints(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<BreakCondition>) -> u32 {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            addr,
+            condition,
+        });
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) -> u32 {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            start,
+            end,
+            kind,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.watchpoints.retain(|w| w.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    fn take_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Called after every instruction with the memory accesses it made.
+    pub fn after_step(&mut self, cpu: &CPU, mem: &Memory, accesses: &[MemoryAccess]) {
+        if self.stop_reason.is_some() {
+            return;
+        }
+        let reason = self
+            .check_run_mode(cpu)
+            .or_else(|| self.check_watchpoints(mem, accesses))
+            .or_else(|| self.check_breakpoints(cpu, mem));
+        if reason.is_some() {
+            self.stop_reason = reason;
+            self.mode = RunMode::Continue;
+        }
+    }
+
+    fn check_run_mode(&mut self, cpu: &CPU) -> Option<String> {
+        match &mut self.mode {
+            RunMode::Continue => None,
+            RunMode::Step(n) => {
+                *n -= 1;
+                (*n == 0).then(|| "Step".to_string())
+            }
+            RunMode::StepOver { return_pc, sp } => {
+                (cpu.reg.pc == *return_pc && cpu.reg.sp >= *sp).then(|| "Next".to_string())
+            }
+            RunMode::Finish { sp } => {
+                let returned = matches!(
+                    cpu.last_instruction(),
+                    Instruction::RET | Instruction::RETc(_) | Instruction::RETI
+                );
+                (returned && cpu.reg.sp > *sp).then(|| "Finished".to_string())
+            }
+        }
+    }
+
+    fn check_watchpoints(&self, mem: &Memory, accesses: &[MemoryAccess]) -> Option<String> {
+        for access in accesses {
+            for w in &self.watchpoints {
+                if (w.start..=w.end).contains(&access.addr) && w.kind.matches(access.kind) {
+                    let verb = match access.kind {
+                        AccessKind::Read => "read",
+                        AccessKind::Write => "write",
+                    };
+                    return Some(format!(
+                        "Watchpoint {}: {} {:04X} (now {:02X})",
+                        w.id, verb, access.addr, mem[access.addr]
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    fn check_breakpoints(&self, cpu: &CPU, mem: &Memory) -> Option<String> {
+        self.breakpoints
+            .iter()
+            .find(|b| b.addr == cpu.reg.pc && b.condition.as_ref().is_none_or(|c| c.eval(cpu, mem)))
+            .map(|b| format!("Breakpoint {}", b.id))
+    }
+
+    /// Executes one command. Returns how to leave the REPL if the command resumes
+    /// execution or quits.
+    fn execute(
+        &mut self,
+        gb: &mut GameBoy,
+        line: &str,
+        out: &mut dyn Write,
+    ) -> Result<Option<ReplExit>, String> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.trim().to_string();
+            self.last_command.clone()
+        };
+        let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+        let args = args.trim();
+        let cpu = &gb.cpu;
+        let mem = &gb.mem;
+        match command {
+            "" => {}
+            "h" | "help" => writeln!(out, "{}", HELP).map_err(|e| e.to_string())?,
+            "c" | "continue" => {
+                self.mode = RunMode::Continue;
+                return Ok(Some(ReplExit::Resume));
+            }
+            "s" | "step" => {
+                let n = if args.is_empty() {
+                    1
+                } else {
+                    args.parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| format!("Invalid count '{}'", args))?
+                };
+                self.mode = RunMode::Step(n);
+                return Ok(Some(ReplExit::Resume));
+            }
+            "n" | "next" => {
+                let (instruction, len) = decode_at(mem, cpu.reg.pc);
+                self.mode = match instruction {
+                    Some(Instruction::CALL(_) | Instruction::CALLc(_, _) | Instruction::RST(_)) => {
+                        RunMode::StepOver {
+                            return_pc: cpu.reg.pc.wrapping_add(len),
+                            sp: cpu.reg.sp,
+                        }
+                    }
+                    _ => RunMode::Step(1),
+                };
+                return Ok(Some(ReplExit::Resume));
+            }
+            "finish" => {
+                self.mode = RunMode::Finish { sp: cpu.reg.sp };
+                return Ok(Some(ReplExit::Resume));
+            }
+            "b" | "break" => {
+                let (addr, condition) = match args.split_once(" if ") {
+                    Some((addr, condition)) => (addr, Some(BreakCondition::parse(condition)?)),
+                    None => (args, None),
+                };
+                let addr = parse_value(addr)?;
+                let id = self.add_breakpoint(addr, condition);
+                writeln!(out, "Breakpoint {} at {:04X}", id, addr).map_err(|e| e.to_string())?;
+            }
+            "w" | "watch" => {
+                let (range, kind) = args.split_once(' ').unwrap_or((args, "rw"));
+                let kind = match kind.trim() {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" => WatchKind::ReadWrite,
+                    kind => return Err(format!("Unknown watch kind '{}'", kind)),
+                };
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+                    None => (parse_value(range)?, parse_value(range)?),
+                };
+                let id = self.add_watchpoint(start, end, kind);
+                writeln!(out, "Watchpoint {} on {:04X}-{:04X}", id, start, end)
+                    .map_err(|e| e.to_string())?;
+            }
+            "d" | "delete" => {
+                let id = args.parse().map_err(|_| format!("Invalid id '{}'", args))?;
+                if !self.remove(id) {
+                    return Err(format!("No breakpoint or watchpoint {}", id));
+                }
+            }
+            "l" | "list" => self.print_list(out).map_err(|e| e.to_string())?,
+            "r" | "regs" => print_registers(cpu, out).map_err(|e| e.to_string())?,
+            "x" => {
+                let mut args = args.split_whitespace();
+                let addr = parse_value(args.next().ok_or("Missing address")?)?;
+                let len = args.next().map(parse_value).transpose()?.unwrap_or(0x40);
+                dump_memory(mem, addr, len, out).map_err(|e| e.to_string())?;
+            }
+            "dis" => {
+                let mut args = args.split_whitespace();
+                let start = args.next().map(parse_value).transpose()?;
+                let count = args.next().map(parse_value).transpose()?;
+                let (start, count) = match (start, count) {
+                    (Some(start), count) => (start, count.unwrap_or(10) as usize),
+                    (None, _) => (
+                        find_start_before(mem, cpu.reg.pc, DISASSEMBLY_CONTEXT_BEFORE),
+                        DISASSEMBLY_CONTEXT_BEFORE + DISASSEMBLY_CONTEXT_AFTER + 1,
+                    ),
+                };
+                self.print_disassembly(cpu, mem, start, count, out)
+                    .map_err(|e| e.to_string())?;
+            }
+            "cheat" => execute_cheat(gb, args, out)?,
+            "q" | "quit" => return Ok(Some(ReplExit::Quit)),
+            _ => return Err(format!("Unknown command '{}', try 'help'", command)),
+        }
+        Ok(None)
+    }
+
+    fn print_list(&self, out: &mut dyn Write) -> io::Result<()> {
+        for b in &self.breakpoints {
+            match &b.condition {
+                Some(c) => writeln!(out, "{:>3}: break {:04X} if {}", b.id, b.addr, c)?,
+                None => writeln!(out, "{:>3}: break {:04X}", b.id, b.addr)?,
+            }
+        }
+        for w in &self.watchpoints {
+            writeln!(
+                out,
+                "{:>3}: watch {:04X}-{:04X} {:?}",
+                w.id, w.start, w.end, w.kind
+            )?;
+        }
+        Ok(())
+    }
+
+    fn print_disassembly(
+        &self,
+        cpu: &CPU,
+        mem: &Memory,
+        start: u16,
+        count: usize,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut addr = start;
+        for _ in 0..count {
+            let (instruction, len) = decode_at(mem, addr);
+            let marker = if addr == cpu.reg.pc { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.iter().any(|b| b.addr == addr) {
+                "*"
+            } else {
+                " "
+            };
+            let bytes: Vec<String> = (0..len)
+                .map(|i| format!("{:02X}", mem[addr.wrapping_add(i)]))
+                .collect();
+            let text = match instruction {
+                Some(instruction) => instruction.to_string(),
+                None => "???".to_string(),
+            };
+            writeln!(
+                out,
+                "{}{}{:04X}: {:<9} {}",
+                marker,
+                breakpoint,
+                addr,
+                bytes.join(" "),
+                text
+            )?;
+            addr = addr.wrapping_add(len);
+        }
+        Ok(())
+    }
+}
+
+fn execute_cheat(gb: &mut GameBoy, args: &str, out: &mut dyn Write) -> Result<(), String> {
+    let cheats = &mut gb.mem.cheats;
+    let (command, args) = args.split_once(' ').unwrap_or((args, ""));
+    let args = args.trim();
+    let parse_id = || args.parse().map_err(|_| format!("Invalid id '{}'", args));
+    match command {
+        "" => {
+            for (i, c) in cheats.codes().iter().enumerate() {
+                let state = if c.enabled { "on " } else { "off" };
+                writeln!(
+                    out,
+                    "{:>3}: {} {:<11} {:<22} {}",
+                    i, state, c.code, c.cheat, c.name
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        "add" => {
+            let (code, name) = args.split_once(' ').unwrap_or((args, ""));
+            cheats.add(code, name.trim(), true)?;
+        }
+        "on" => cheats.set_enabled(parse_id()?, true)?,
+        "off" => cheats.set_enabled(parse_id()?, false)?,
+        "rm" => cheats.remove(parse_id()?)?,
+        _ => return Err(format!("Unknown cheat command '{}'", command)),
+    }
+    Ok(())
+}
+
+/// Reads debugger commands until one resumes execution or quits. Commands come from
+/// `input` and all output goes to `output`, so the REPL can run on a terminal or a socket.
+pub fn run_repl(
+    gb: &mut GameBoy,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<ReplExit> {
+    let mut debugger = gb.debugger.take().unwrap_or_default();
+    if let Some(reason) = debugger.stop_reason.take() {
+        writeln!(output, "{}", reason)?;
+    }
+    debugger.print_disassembly(&gb.cpu, &gb.mem, gb.cpu.reg.pc, 1, output)?;
+
+    let exit = loop {
+        write!(output, "(gbdb) ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break ReplExit::Quit;
+        }
+        match debugger.execute(gb, &line, output) {
+            Ok(Some(exit)) => break exit,
+            Ok(None) => {}
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+    };
+    gb.debugger = Some(debugger);
+    Ok(exit)
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let parsed = if let Some(decimal) = s.strip_prefix('#') {
+        decimal.parse()
+    } else {
+        let hex = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix('$'))
+            .unwrap_or(s);
+        u16::from_str_radix(hex, 16)
+    };
+    parsed.map_err(|_| format!("Invalid value '{}'", s))
+}
+
+fn print_registers(cpu: &CPU, out: &mut dyn Write) -> io::Result<()> {
+    let reg = &cpu.reg;
+    let flags: String = [(7, 'Z'), (6, 'N'), (5, 'H'), (4, 'C')]
+        .iter()
+        .map(|(bit, name)| if reg.get_flag(*bit) { *name } else { '-' })
+        .collect();
+    writeln!(
+        out,
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} {}",
+        reg.get_pair(AddrReg::AF),
+        reg.get_pair(AddrReg::BC),
+        reg.get_pair(AddrReg::DE),
+        reg.get_pair(AddrReg::HL),
+        reg.sp,
+        reg.pc,
+        flags
+    )
+}
+
+fn dump_memory(mem: &Memory, start: u16, len: u16, out: &mut dyn Write) -> io::Result<()> {
+    let end = start as u32 + len as u32;
+    for line_start in (start as u32..end).step_by(16) {
+        let bytes: Vec<String> = (line_start..end.min(line_start + 16))
+            .map(|addr| format!("{:02X}", mem[addr as u16]))
+            .collect();
+        writeln!(out, "{:04X}: {}", line_start, bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Finds an address up to `count` instructions before `pc` from which decoding lands
+/// exactly on `pc`. Code and data cannot be told apart, so this is a best guess.
+fn find_start_before(mem: &Memory, pc: u16, count: usize) -> u16 {
+    let max_distance = count as u16 * MAX_INSTRUCTION_LENGTH;
+    for distance in (1..=max_distance.min(pc)).rev() {
+        let start = pc - distance;
+        let mut addr = start;
+        let mut instructions = 0;
+        while addr < pc {
+            let (_, len) = decode_at(mem, addr);
+            // An instruction running past $FFFF never lands on `pc`.
+            let Some(next) = addr.checked_add(len) else {
+                break;
+            };
+            addr = next;
+            instructions += 1;
+        }
+        if addr == pc && instructions <= count {
+            return start;
+        }
+    }
+    pc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom::test_gameboy;
+
+    #[test]
+    fn step_count_must_be_positive() {
+        let mut gb = test_gameboy(&[]);
+        let mut debugger = Debugger::new();
+        let mut out = Vec::new();
+        assert_eq!(
+            debugger.execute(&mut gb, "s 0", &mut out),
+            Err("Invalid count '0'".to_string())
+        );
+        assert_eq!(
+            debugger.execute(&mut gb, "s 2", &mut out),
+            Ok(Some(ReplExit::Resume))
+        );
+    }
+
+    #[test]
+    fn disassembly_start_ignores_instructions_past_the_end_of_memory() {
+        let mut gb = test_gameboy(&[]);
+        // Every start before $FFFF runs into LD A, n at $FFFE, which ends past $FFFF.
+        gb.mem[0xFFFE] = 0x3E;
+        assert_eq!(find_start_before(&gb.mem, 0xFFFF, 3), 0xFFFF);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::instruction::Instruction;
+
+/// Size of a switchable ROM bank.
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Decodes the instruction at the start of `bytes`. Returns the instruction and its length,
+/// or None if the opcode is invalid or the instruction is cut off by the end of `bytes`.
+pub fn decode_bytes(bytes: &[u8]) -> Option<(Instruction, usize)> {
+    let mut len = 1;
+    let instruction = Instruction::decode(*bytes.first()?, || {
+        let byte = bytes.get(len).copied().unwrap_or(0);
+        len += 1;
+        byte
+    })?;
+    (len <= bytes.len()).then_some((instruction, len))
+}
+
+/// Linear sweep over the whole ROM. Returns every decoded instruction with its offset in
+/// the ROM; bytes that do not decode are skipped. Code and data are not told apart, so
+/// data regions show up as bogus instructions.
+pub fn disassemble(rom: &[u8]) -> Vec<(usize, Instruction)> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        match decode_bytes(&rom[offset..]) {
+            Some((instruction, len)) => {
+                instructions.push((offset, instruction));
+                offset += len;
+            }
+            None => offset += 1,
+        }
+    }
+    instructions
+}
+
+/// Maps a ROM offset to the bank holding it and the address it appears at on the bus.
+pub fn rom_address(offset: usize) -> (usize, u16) {
+    let bank = offset / ROM_BANK_SIZE;
+    let addr = if bank == 0 {
+        offset
+    } else {
+        ROM_BANK_SIZE + offset % ROM_BANK_SIZE
+    };
+    (bank, addr as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> Option<(String, usize)> {
+        decode_bytes(bytes).map(|(instruction, len)| (instruction.to_string(), len))
+    }
+
+    #[test]
+    fn decodes_operands_and_prefix() {
+        assert_eq!(text(&[0x00, 0xFF]), Some(("NOP".to_string(), 1)));
+        assert_eq!(
+            text(&[0x01, 0x34, 0x12]),
+            Some(("LD BC, $1234".to_string(), 3))
+        );
+        assert_eq!(text(&[0xE0, 0x40]), Some(("LDH ($FF40), A".to_string(), 2)));
+        assert_eq!(text(&[0xCB, 0x37]), Some(("SWAP A".to_string(), 2)));
+    }
+
+    #[test]
+    fn rejects_invalid_and_cut_off_instructions() {
+        assert_eq!(text(&[0xD3]), None);
+        assert_eq!(text(&[0xC3, 0x50]), None);
+        assert_eq!(text(&[0xCB]), None);
+        assert_eq!(text(&[]), None);
+    }
+
+    #[test]
+    fn sweep_skips_bytes_that_do_not_decode() {
+        let offsets: Vec<usize> = disassemble(&[0x00, 0xD3, 0x3E, 0x01, 0xC3, 0x00])
+            .into_iter()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(offsets, [0, 2, 5]);
+    }
+
+    #[test]
+    fn rom_offsets_map_to_banked_addresses() {
+        assert_eq!(rom_address(0x0150), (0, 0x0150));
+        assert_eq!(rom_address(0x4000), (1, 0x4000));
+        assert_eq!(rom_address(0x1_2345), (4, 0x6345));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+
+const TAC_ENABLE: u8 = 1 << 2;
+const TAC_CLOCK_SELECT: u8 = 0b11;
+const TAC_UNUSED_BITS: u8 = 0b1111_1000;
+/// Bit of the system counter whose falling edge clocks DIV-APU, in normal and double speed.
+const DIV_APU_BIT: u16 = 1 << 12;
+const DIV_APU_BIT_DOUBLE_SPEED: u16 = 1 << 13;
+
+/// DIV, TIMA, TMA and TAC. All of them run off a 16 bit system counter that counts T-cycles
+/// and of which DIV shows the upper byte. TIMA counts falling edges of one of its bits,
+/// selected by TAC and gated by the enable bit, so writes to DIV or TAC that pull that
+/// signal low increment TIMA as on hardware.
+pub struct DivTimer {
+    counter: u16,
+    div: u8,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    /// The TIMA clock signal after the last tick.
+    signal: bool,
+    /// TIMA overflowed and reads 0 until TMA is loaded on the next tick.
+    reload_pending: bool,
+    /// TMA was loaded into TIMA on the last tick, so CPU writes to TIMA in this M-cycle
+    /// are ignored.
+    reloaded: bool,
+    /// The CPU wrote TIMA since the last tick, which cancels a pending reload.
+    tima_written: bool,
+    interrupt: bool,
+    write_dummy: u8,
+    /// Bit of the system counter that clocks DIV-APU at the current speed.
+    div_apu_bit: u16,
+    /// DIV-APU was clocked and the APU hasn't been told yet.
+    div_apu_clocked: bool,
+    pub div_apu: u8,
+}
+
+impl DivTimer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            div: 0,
+            tima: 0,
+            tma: 0,
+            tac: TAC_UNUSED_BITS,
+            signal: false,
+            reload_pending: false,
+            reloaded: false,
+            tima_written: false,
+            interrupt: false,
+            write_dummy: 0,
+            div_apu_bit: DIV_APU_BIT,
+            div_apu_clocked: false,
+            div_apu: 0,
+        }
+    }
+
+    /// Advances the timer by one M-cycle.
+    pub fn tick(&mut self, double_speed: bool) {
+        self.tac |= TAC_UNUSED_BITS;
+        self.reloaded = false;
+        let tima_written = std::mem::take(&mut self.tima_written);
+        if self.reload_pending {
+            self.reload_pending = false;
+            // Writing TIMA in the cycle after the overflow cancels the reload.
+            if !tima_written {
+                self.tima = self.tma;
+                self.interrupt = true;
+                self.reloaded = true;
+            }
+        }
+
+        self.div_apu_bit = if double_speed {
+            DIV_APU_BIT_DOUBLE_SPEED
+        } else {
+            DIV_APU_BIT
+        };
+        self.set_counter(self.counter.wrapping_add(4));
+    }
+
+    /// Sets the system counter and clocks TIMA and DIV-APU on the falling edges this makes.
+    fn set_counter(&mut self, counter: u16) {
+        let falling_bits = self.counter & !counter;
+        self.counter = counter;
+        self.div = (counter >> 8) as u8;
+        self.update_signal();
+        if falling_bits & self.div_apu_bit != 0 {
+            self.div_apu = self.div_apu.wrapping_add(1);
+            self.div_apu_clocked = true;
+        }
+    }
+
+    /// Increments TIMA on a falling edge of the selected counter bit.
+    fn update_signal(&mut self) {
+        let bit = match self.tac & TAC_CLOCK_SELECT {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        let signal = self.tac & TAC_ENABLE != 0 && (self.counter >> bit) & 1 != 0;
+        if self.signal && !signal {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            self.reload_pending = overflow;
+        }
+        self.signal = signal;
+    }
+
+    /// Returns whether TIMA overflowed since the last call.
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    /// Returns whether DIV-APU was clocked since the last call.
+    pub fn take_div_apu_clock(&mut self) -> bool {
+        std::mem::take(&mut self.div_apu_clocked)
+    }
+
+    pub fn read(&self, addr: u16) -> &u8 {
+        match addr {
+            0xFF04 => &self.div,
+            0xFF05 => &self.tima,
+            0xFF06 => &self.tma,
+            _ => &self.tac,
+        }
+    }
+
+    /// Writing DIV resets the whole system counter, whatever the value, which clocks TIMA
+    /// and DIV-APU right away if their bits were set. Writes to TAC are picked up on the
+    /// next tick, which is when a disabled or switched clock can glitch TIMA.
+    pub fn write(&mut self, addr: u16) -> &mut u8 {
+        match addr {
+            0xFF04 => {
+                self.set_counter(0);
+                &mut self.write_dummy
+            }
+            0xFF05 if self.reloaded => &mut self.write_dummy,
+            0xFF05 => {
+                self.tima_written = true;
+                &mut self.tima
+            }
+            0xFF06 => &mut self.tma,
+            _ => &mut self.tac,
+        }
+    }
+}
+
+impl Snapshot for DivTimer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.counter);
+        w.write_bytes(&[self.div, self.tima, self.tma, self.tac, self.div_apu]);
+        w.write_bool(self.signal);
+        w.write_bool(self.reload_pending);
+        w.write_bool(self.reloaded);
+        w.write_bool(self.tima_written);
+        w.write_bool(self.interrupt);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.counter = r.read_u16()?;
+        [self.div, self.tima, self.tma, self.tac, self.div_apu] = r.read_array()?;
+        self.signal = r.read_bool()?;
+        self.reload_pending = r.read_bool()?;
+        self.reloaded = r.read_bool()?;
+        self.tima_written = r.read_bool()?;
+        self.interrupt = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timer counting TIMA every 4 M-cycles, from `tima`.
+    fn fast_timer(tima: u8, tma: u8) -> DivTimer {
+        let mut timer = DivTimer::new();
+        *timer.write(0xFF07) = TAC_ENABLE | 0b01;
+        *timer.write(0xFF05) = tima;
+        *timer.write(0xFF06) = tma;
+        timer
+    }
+
+    /// Ticks until TIMA overflows, leaving the reload pending.
+    fn tick_until_overflow(timer: &mut DivTimer) {
+        while !timer.reload_pending {
+            timer.tick(false);
+        }
+        assert_eq!(*timer.read(0xFF05), 0);
+    }
+
+    #[test]
+    fn tima_reloads_after_overflow() {
+        let mut timer = fast_timer(0xFF, 0x42);
+        tick_until_overflow(&mut timer);
+        timer.tick(false);
+        assert_eq!(*timer.read(0xFF05), 0x42);
+        assert!(timer.take_interrupt());
+    }
+
+    #[test]
+    fn writing_zero_to_tima_cancels_reload() {
+        let mut timer = fast_timer(0xFF, 0x42);
+        tick_until_overflow(&mut timer);
+        *timer.write(0xFF05) = 0;
+        timer.tick(false);
+        assert_eq!(*timer.read(0xFF05), 0);
+        assert!(!timer.take_interrupt());
+    }
+
+    #[test]
+    fn div_write_glitch_clocks_tima_immediately() {
+        let mut timer = fast_timer(0x10, 0);
+        timer.tick(false);
+        timer.tick(false);
+        assert_eq!(timer.counter & 1 << 3, 1 << 3);
+        *timer.write(0xFF04) = 0;
+        assert_eq!(*timer.read(0xFF05), 0x11);
+    }
+
+    #[test]
+    fn div_write_clocks_div_apu() {
+        let mut timer = DivTimer::new();
+        while timer.counter & DIV_APU_BIT == 0 {
+            timer.tick(false);
+        }
+        assert!(!timer.take_div_apu_clock());
+        *timer.write(0xFF04) = 0;
+        assert!(timer.take_div_apu_clock());
+        assert_eq!(timer.div_apu, 1);
+    }
+}
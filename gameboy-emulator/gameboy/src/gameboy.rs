@@ -0,0 +1,407 @@
+use crate::addrreg::AddrReg;
+use crate::apu::APU;
+use crate::bus::Bus;
+use crate::cartridge_header::CartridgeHeader;
+use crate::cpu::CPU;
+use crate::debugger::Debugger;
+use crate::hooks::Hooks;
+use crate::host::{
+    AudioSink, InputSource, VideoSink, SCREEN_HEIGHT, SCREEN_WIDTH, SGB_SCREEN_HEIGHT,
+    SGB_SCREEN_WIDTH,
+};
+use crate::joypad::JoyPad;
+use crate::memory::Memory;
+use crate::movie::fnv1a;
+use crate::ppu::PPU;
+use crate::rom_file::read_rom;
+use crate::save_file::SaveFile;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use crate::serial::Serial;
+use crate::trace::{TraceLog, STUB_LY};
+use log::{debug, error, info};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Dots in a frame: 154 scanlines of 456 dots each.
+pub const DOTS_PER_FRAME: u32 = 70224;
+/// Dot clock frequency of the DMG.
+pub const DOT_FREQ: u32 = 4_194_304;
+const CLOCK_FREQ_UPDATE_INTERVAL: u32 = 1_000_000;
+/// Flush battery-backed RAM to disk roughly every 5 seconds of emulated time.
+const SAVE_FILE_FLUSH_INTERVAL: u32 = 5 * 1_048_576;
+
+pub struct GameBoy {
+    pub mem: Memory,
+    pub cpu: CPU,
+    pub ppu: PPU,
+    pub apu: APU,
+    pub joy_pad: JoyPad,
+    pub serial: Serial,
+    pub debugger: Option<Debugger>,
+    pub trace: Option<TraceLog>,
+    pub hooks: Hooks,
+    /// Buttons held on top of the player's input, as a `JoyPad::buttons` mask. Set by
+    /// hooks to play the game; not part of recorded movies.
+    pub injected_buttons: u8,
+    cpu_last_cycle_cnt_reset: SystemTime,
+    cpu_cycle_counter: u32,
+    save_file: Option<SaveFile>,
+    save_flush_counter: u32,
+    header: CartridgeHeader,
+    rom_checksum: u64,
+    rom_path: PathBuf,
+}
+
+impl GameBoy {
+    /// Loads a cartridge, which may be zipped or gzipped, and checks its header unless
+    /// `check_header` is false. Without a boot ROM, emulation starts at the cartridge
+    /// entry point. With `sgb`, the machine is a Super Game Boy.
+    pub fn from_cartridge(
+        rom_path: &Path,
+        boot_rom_path: Option<&Path>,
+        check_header: bool,
+        sgb: bool,
+    ) -> Result<Self, String> {
+        let rom = read_rom(rom_path)?;
+        if check_header {
+            CartridgeHeader::read(&rom)?.validate(&rom)?;
+        }
+        let boot_rom = match boot_rom_path {
+            Some(path) => fs::read(path)
+                .map_err(|e| format!("Unable to read boot ROM {}: {}", path.display(), e))?,
+            None => Vec::new(),
+        };
+        let mut gb = Self::new(boot_rom, rom, rom_path.to_path_buf())?;
+        if sgb {
+            gb.enable_sgb();
+        }
+        if boot_rom_path.is_none() {
+            gb.skip_boot_rom();
+        }
+        Ok(gb)
+    }
+
+    /// Creates a GameBoy from ROM contents. The ROM path is only used to locate the save
+    /// file and save states next to it. If the boot ROM is empty, `skip_boot_rom`
+    /// must be called before running.
+    pub fn new(boot_rom: Vec<u8>, rom: Vec<u8>, rom_path: PathBuf) -> Result<Self, String> {
+        // Read cartridge header
+        let header = CartridgeHeader::read(&rom)?;
+        debug!("{:x?}", header);
+        let rom_checksum = fnv1a(&rom);
+
+        let mut save_file = SaveFile::for_cartridge(&rom_path, &header);
+        let mut mem = Memory::new(boot_rom, rom, header.clone())?;
+        if let Some(save_file) = save_file.as_mut() {
+            save_file
+                .load(&mut mem)
+                .map_err(|e| format!("Unable to read save file: {}", e))?;
+        }
+        let mut gb = GameBoy {
+            mem,
+            cpu: CPU::new(),
+            ppu: PPU::new(),
+            apu: APU::new(),
+            joy_pad: JoyPad::new(),
+            serial: Serial::new(),
+            debugger: None,
+            trace: None,
+            hooks: Hooks::default(),
+            injected_buttons: 0,
+            cpu_last_cycle_cnt_reset: SystemTime::now(),
+            cpu_cycle_counter: 0,
+            save_file,
+            save_flush_counter: 0,
+            header,
+            rom_checksum,
+            rom_path,
+        };
+        gb.update_joy_pad();
+        Ok(gb)
+    }
+
+    /// Turns the machine into a Super Game Boy, which colours DMG games and draws a border
+    /// around them. CGB cartridges run in DMG mode. Call before running.
+    pub fn enable_sgb(&mut self) {
+        self.mem.enable_sgb(self.header.supports_sgb());
+    }
+
+    /// Starts at the cartridge entry point with the registers the boot ROM leaves behind.
+    pub fn skip_boot_rom(&mut self) {
+        let reg = &mut self.cpu.reg;
+        if self.mem.is_cgb() {
+            reg.set_pair(AddrReg::AF, 0x1180);
+            reg.set_pair(AddrReg::BC, 0x0000);
+            reg.set_pair(AddrReg::DE, 0xFF56);
+            reg.set_pair(AddrReg::HL, 0x000D);
+        } else if self.mem.sgb.is_some() {
+            reg.set_pair(AddrReg::AF, 0x0100);
+            reg.set_pair(AddrReg::BC, 0x0014);
+            reg.set_pair(AddrReg::DE, 0x0000);
+            reg.set_pair(AddrReg::HL, 0xC060);
+        } else {
+            reg.set_pair(AddrReg::AF, 0x01B0);
+            reg.set_pair(AddrReg::BC, 0x0013);
+            reg.set_pair(AddrReg::DE, 0x00D8);
+            reg.set_pair(AddrReg::HL, 0x014D);
+        }
+        reg.sp = 0xFFFE;
+        reg.pc = 0x100;
+        self.mem[0xFF50] = 0x01;
+        info!("Starting ROM");
+        self.apu.finish_boot_rom(&mut self.mem);
+    }
+
+    fn save_state_path(&self, slot: u8) -> PathBuf {
+        self.rom_path.with_extension(format!("ss{slot}"))
+    }
+
+    /// The picture to show: the PPU's frame buffer, or in SGB mode the SGB screen with the
+    /// border.
+    pub fn screen(&self) -> &[u32] {
+        match &self.mem.sgb {
+            Some(sgb) => sgb.screen(),
+            None => self.ppu.frame_buffer(),
+        }
+    }
+
+    /// Width and height of `screen`.
+    pub fn screen_size(&self) -> (usize, usize) {
+        match self.mem.sgb {
+            Some(_) => (SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT),
+            None => (SCREEN_WIDTH, SCREEN_HEIGHT),
+        }
+    }
+
+    /// FNV-1a hash of the whole ROM, identifying the exact cartridge dump.
+    pub fn rom_checksum(&self) -> u64 {
+        self.rom_checksum
+    }
+
+    /// Serializes the whole machine into a save state.
+    pub fn save_state_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new(&self.header);
+        Snapshot::save_state(self, &mut w);
+        w.into_bytes()
+    }
+
+    /// Restores a save state produced by `save_state_bytes`. A state that fails to load
+    /// leaves the machine as it was.
+    pub fn load_state_bytes(&mut self, contents: &[u8]) -> Result<(), String> {
+        // The components are loaded one after another, so a state that turns out to be
+        // truncated or made for a different mode halfway through is rolled back.
+        let current = self.save_state_bytes();
+        if let Err(e) = self.read_state(contents) {
+            self.read_state(&current)
+                .expect("a state saved just now can be loaded");
+            return Err(e);
+        }
+        self.update_joy_pad();
+        Ok(())
+    }
+
+    fn read_state(&mut self, contents: &[u8]) -> Result<(), String> {
+        let mut r = StateReader::new(contents, &self.header)?;
+        Snapshot::load_state(self, &mut r)?;
+        r.finish()
+    }
+
+    pub fn save_state(&self, slot: u8) -> Result<(), String> {
+        fs::write(self.save_state_path(slot), self.save_state_bytes()).map_err(|e| e.to_string())
+    }
+
+    pub fn load_state(&mut self, slot: u8) -> Result<(), String> {
+        let contents = fs::read(self.save_state_path(slot)).map_err(|e| e.to_string())?;
+        self.load_state_bytes(&contents)
+    }
+
+    pub fn flush_save_file(&mut self) {
+        if let Some(save_file) = self.save_file.as_mut() {
+            if let Err(e) = save_file.flush(&self.mem) {
+                error!("Unable to write save file: {}", e);
+            }
+        }
+    }
+
+    /// Runs one frame worth of emulated time: until the PPU completes a frame, or for
+    /// `DOTS_PER_FRAME` dots while the LCD is off. If an attached debugger stops, the frame
+    /// ends early; check `debugger_stopped` afterwards. Input is polled once before the frame
+    /// starts, the frame is presented and the audio produced during it is pushed afterwards,
+    /// then the frame hooks run.
+    pub fn run_frame(
+        &mut self,
+        video: &mut dyn VideoSink,
+        audio: &mut dyn AudioSink,
+        input: &mut dyn InputSource,
+    ) {
+        input.poll(&mut self.joy_pad);
+        self.update_joy_pad();
+        self.apu.set_sample_rate(audio.sample_rate());
+        let mut dots = 0;
+        let mut m_cycles = 0;
+        while dots < DOTS_PER_FRAME {
+            let step = self.step();
+            m_cycles += step;
+            dots += step * self.mem.dots_per_m_cycle();
+            if self.ppu.frame_completed() || self.debugger_stopped() {
+                break;
+            }
+        }
+        self.ppu.catch_up(&mut self.mem);
+        self.count_m_cycles(m_cycles);
+        video.present_frame(self.screen());
+        audio.push_samples(&self.apu.take_samples());
+        let mut hooks = std::mem::take(&mut self.hooks);
+        hooks.run_frame(self);
+        self.restore_hooks(hooks);
+    }
+
+    /// Flushes the save file and logs the clock frequency every so many M-cycles.
+    fn count_m_cycles(&mut self, m_cycles: u32) {
+        self.save_flush_counter += m_cycles;
+        if self.save_flush_counter >= SAVE_FILE_FLUSH_INTERVAL {
+            self.save_flush_counter -= SAVE_FILE_FLUSH_INTERVAL;
+            self.flush_save_file();
+        }
+        self.cpu_cycle_counter += m_cycles;
+        if self.cpu_cycle_counter >= CLOCK_FREQ_UPDATE_INTERVAL {
+            self.cpu_cycle_counter -= CLOCK_FREQ_UPDATE_INTERVAL;
+            let cycle_time = SystemTime::now();
+            let dt = cycle_time
+                .duration_since(self.cpu_last_cycle_cnt_reset)
+                .unwrap();
+            self.cpu_last_cycle_cnt_reset = cycle_time;
+            debug!(
+                "Clock Freq: {} MHz",
+                CLOCK_FREQ_UPDATE_INTERVAL as f32 / dt.as_secs_f32() / 1_000_000.0
+            );
+        }
+    }
+
+    fn restore_hooks(&mut self, mut hooks: Hooks) {
+        hooks.append(std::mem::take(&mut self.hooks));
+        self.hooks = hooks;
+    }
+
+    /// Updates P1 from the buttons held, including injected ones. Needed whenever either
+    /// changes and after every write to P1.
+    fn update_joy_pad(&mut self) {
+        if self.injected_buttons == 0 {
+            self.joy_pad.update(&mut self.mem);
+        } else {
+            let held = self.joy_pad.buttons();
+            self.joy_pad.set_buttons(held | self.injected_buttons);
+            self.joy_pad.update(&mut self.mem);
+            self.joy_pad.set_buttons(held);
+        }
+    }
+
+    /// Executes a single instruction, or dispatches an interrupt and executes the first
+    /// instruction of its handler. The rest of the machine advances with every M-cycle the
+    /// CPU spends. Returns the number of M-cycles taken.
+    pub fn step(&mut self) -> u32 {
+        let watching = self
+            .debugger
+            .as_ref()
+            .is_some_and(Debugger::has_watchpoints);
+        let tracking = watching || self.hooks.has_write_hooks();
+        let mut bus = Bus::new(
+            &mut self.mem,
+            &mut self.ppu,
+            &mut self.apu,
+            &mut self.serial,
+        );
+        self.cpu.check_interrupts(&mut bus);
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(e) = Self::write_trace(trace, &self.cpu, bus.mem) {
+                error!("Unable to write trace, disabling it: {}", e);
+                self.trace = None;
+            }
+        }
+        bus.mem.set_access_tracking(tracking);
+        self.cpu.run_cycle(&mut bus);
+        bus.mem.set_access_tracking(false);
+        let m_cycles = bus.m_cycles();
+        if bus.p1_written() {
+            self.update_joy_pad();
+        }
+
+        if self.debugger.is_none() && !self.hooks.has_step_hooks() {
+            return m_cycles;
+        }
+        // The debugger and hooks may look at anything, so the PPU has to be up to date.
+        self.ppu.catch_up(&mut self.mem);
+        let accesses = self.mem.take_accesses();
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.after_step(&self.cpu, &self.mem, &accesses);
+        }
+        if self.hooks.has_step_hooks() {
+            let mut hooks = std::mem::take(&mut self.hooks);
+            hooks.run_step(self, &accesses);
+            self.restore_hooks(hooks);
+            // Hooks may have pressed or released buttons.
+            self.update_joy_pad();
+            // Or written LCD registers.
+            self.ppu.reschedule();
+        }
+        m_cycles
+    }
+
+    fn write_trace(trace: &mut TraceLog, cpu: &CPU, mem: &mut Memory) -> io::Result<()> {
+        if trace.stub_ly {
+            mem[0xFF44] = STUB_LY;
+        }
+        if cpu.is_halted() || cpu.is_locked() {
+            return Ok(());
+        }
+        trace.log(cpu, mem)
+    }
+
+    pub fn debugger_stopped(&self) -> bool {
+        self.debugger.as_ref().is_some_and(Debugger::is_stopped)
+    }
+
+    pub fn print_debug_summary(&mut self) {
+        self.cpu.print_exec_log();
+
+        #[cfg(feature = "cpu-debug")]
+        {
+            for i in 0..256 {
+                if self.cpu.instructions_count[i] > 0 {
+                    debug!("{:02x}: {}", i as u8, self.cpu.instructions_count[i]);
+                }
+            }
+            for i in 256..(2 * 256) {
+                if self.cpu.instructions_count[i] > 0 {
+                    debug!(
+                        "cb {:02x}: {}",
+                        (i - 256) as u8,
+                        self.cpu.instructions_count[i]
+                    );
+                }
+            }
+        }
+
+        self.mem.write_contents().unwrap();
+    }
+}
+
+impl Snapshot for GameBoy {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.cpu.save_state(w);
+        self.mem.save_state(w);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.serial.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.cpu.load_state(r)?;
+        self.mem.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.serial.load_state(r)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::gameboy::GameBoy;
+use crate::host::{AudioSink, NullHost, VideoSink};
+
+/// Output of a headless run: the last completed frame, as sized by `GameBoy::screen_size`,
+/// and all audio produced during the run.
+pub struct HeadlessOutput {
+    pub frame: Vec<u32>,
+    pub samples: Vec<f32>,
+}
+
+struct FrameCapture {
+    frame: Vec<u32>,
+}
+
+impl VideoSink for FrameCapture {
+    fn present_frame(&mut self, frame: &[u32]) {
+        self.frame.clear();
+        self.frame.extend_from_slice(frame);
+    }
+}
+
+struct SampleCapture {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl AudioSink for SampleCapture {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+}
+
+/// Runs a GameBoy without a window or audio device, e.g. in tests or on a server.
+pub struct HeadlessRunner {
+    pub gameboy: GameBoy,
+    sample_rate: u32,
+}
+
+impl HeadlessRunner {
+    pub fn new(gameboy: GameBoy, sample_rate: u32) -> Self {
+        Self {
+            gameboy,
+            sample_rate,
+        }
+    }
+
+    pub fn run_frames(&mut self, frames: usize) -> HeadlessOutput {
+        let (width, height) = self.gameboy.screen_size();
+        let mut video = FrameCapture {
+            frame: vec![0; width * height],
+        };
+        let mut audio = SampleCapture {
+            samples: Vec::new(),
+            sample_rate: self.sample_rate,
+        };
+        for _ in 0..frames {
+            self.gameboy
+                .run_frame(&mut video, &mut audio, &mut NullHost);
+        }
+        HeadlessOutput {
+            frame: video.frame,
+            samples: audio.samples,
+        }
+    }
+}
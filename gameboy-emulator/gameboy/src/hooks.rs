@@ -0,0 +1,86 @@
+use crate::gameboy::GameBoy;
+use crate::memory::{AccessKind, MemoryAccess};
+use std::ops::RangeInclusive;
+
+pub type FrameHook = Box<dyn FnMut(&mut GameBoy)>;
+pub type ExecHook = Box<dyn FnMut(&mut GameBoy)>;
+pub type WriteHook = Box<dyn FnMut(&mut GameBoy, u16, u8)>;
+
+/// Callbacks for embedding the emulator in bots and tools. They get the whole machine, so
+/// they can read and write memory through `GameBoy::mem` and press buttons through
+/// `GameBoy::injected_buttons`. Hooks registered from within a callback take effect after
+/// it returns.
+#[derive(Default)]
+pub struct Hooks {
+    frame: Vec<FrameHook>,
+    exec: Vec<(u16, ExecHook)>,
+    write: Vec<(RangeInclusive<u16>, WriteHook)>,
+}
+
+impl Hooks {
+    /// Calls `hook` at the end of every `GameBoy::run_frame`.
+    pub fn on_frame(&mut self, hook: impl FnMut(&mut GameBoy) + 'static) {
+        self.frame.push(Box::new(hook));
+    }
+
+    /// Calls `hook` whenever PC reaches `addr`, before the instruction there executes.
+    pub fn on_exec(&mut self, addr: u16, hook: impl FnMut(&mut GameBoy) + 'static) {
+        self.exec.push((addr, Box::new(hook)));
+    }
+
+    /// Calls `hook` with the address and value of every CPU write to `range`, after the
+    /// instruction that made it.
+    pub fn on_write(
+        &mut self,
+        range: RangeInclusive<u16>,
+        hook: impl FnMut(&mut GameBoy, u16, u8) + 'static,
+    ) {
+        self.write.push((range, Box::new(hook)));
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn has_write_hooks(&self) -> bool {
+        !self.write.is_empty()
+    }
+
+    /// Whether there is anything to do after each step.
+    pub(crate) fn has_step_hooks(&self) -> bool {
+        !self.exec.is_empty() || !self.write.is_empty()
+    }
+
+    /// Adds the hooks registered while these were running.
+    pub(crate) fn append(&mut self, mut other: Hooks) {
+        self.frame.append(&mut other.frame);
+        self.exec.append(&mut other.exec);
+        self.write.append(&mut other.write);
+    }
+
+    pub(crate) fn run_frame(&mut self, gb: &mut GameBoy) {
+        for hook in &mut self.frame {
+            hook(gb);
+        }
+    }
+
+    pub(crate) fn run_step(&mut self, gb: &mut GameBoy, accesses: &[MemoryAccess]) {
+        for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+            for (range, hook) in &mut self.write {
+                if range.contains(&access.addr) {
+                    hook(gb, access.addr, access.value);
+                }
+            }
+        }
+        // While halted or locked up, PC stays put without executing anything.
+        if gb.cpu.is_halted() || gb.cpu.is_locked() {
+            return;
+        }
+        let pc = gb.cpu.reg.pc;
+        for (addr, hook) in &mut self.exec {
+            if *addr == pc {
+                hook(gb);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::joypad::JoyPad;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+/// Size of the Super Game Boy picture, the screen with a border around it.
+pub const SGB_SCREEN_WIDTH: usize = 256;
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+
+/// Receives every completed frame. Pixels are `0x00RRGGBB`, row-major, `SCREEN_WIDTH` wide,
+/// or `SGB_SCREEN_WIDTH` in SGB mode; `GameBoy::screen_size` tells which.
+pub trait VideoSink {
+    fn present_frame(&mut self, frame: &[u32]);
+}
+
+/// Receives the audio produced by the APU, as interleaved left/right samples in [-1, 1].
+pub trait AudioSink {
+    /// The rate at which the APU should produce samples for this sink.
+    fn sample_rate(&self) -> u32;
+    fn push_samples(&mut self, samples: &[f32]);
+}
+
+/// Provides the button state, polled once per frame.
+pub trait InputSource {
+    fn poll(&mut self, joy_pad: &mut JoyPad);
+}
+
+/// Sink and source that do nothing, for running without a frontend.
+pub struct NullHost;
+
+impl VideoSink for NullHost {
+    fn present_frame(&mut self, _: &[u32]) {}
+}
+
+impl AudioSink for NullHost {
+    fn sample_rate(&self) -> u32 {
+        48_000
+    }
+
+    fn push_samples(&mut self, _: &[f32]) {}
+}
+
+impl InputSource for NullHost {
+    fn poll(&mut self, _: &mut JoyPad) {}
+}
+
+/// Presents frames to both sinks, e.g. a window and a recording.
+impl<A: VideoSink + ?Sized, B: VideoSink + ?Sized> VideoSink for (&mut A, &mut B) {
+    fn present_frame(&mut self, frame: &[u32]) {
+        self.0.present_frame(frame);
+        self.1.present_frame(frame);
+    }
+}
+
+/// Pushes samples to both sinks. They are produced at the rate the first sink asks for.
+impl<A: AudioSink + ?Sized, B: AudioSink + ?Sized> AudioSink for (&mut A, &mut B) {
+    fn sample_rate(&self) -> u32 {
+        self.0.sample_rate()
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.0.push_samples(samples);
+        self.1.push_samples(samples);
+    }
+}
@@ -0,0 +1,608 @@
+use crate::addrreg::AddrReg;
+use crate::condition::Condition;
+use crate::dataloc::DataLoc;
+use crate::reg::Reg;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    LD(DataLoc, DataLoc),
+    LD5,
+    LD6,
+    LDD(DataLoc, DataLoc),
+    LDI(DataLoc, DataLoc),
+    LDH1(u8),
+    LDH2(u8),
+    LD16(AddrReg, u16),
+    LDSPHL,
+    LDHL(i8),
+    LDnn(u16),
+    PUSH(AddrReg),
+    POP(AddrReg),
+    ADD(DataLoc),
+    ADC(DataLoc),
+    SUB(DataLoc),
+    SBC(DataLoc),
+    AND(DataLoc),
+    OR(DataLoc),
+    XOR(DataLoc),
+    CP(DataLoc),
+    INC(DataLoc),
+    DEC(DataLoc),
+    ADD16(AddrReg),
+    ADD16n(i8),
+    INC16(AddrReg),
+    DEC16(AddrReg),
+    SWAP(DataLoc),
+    DAA,
+    CPL,
+    CCF,
+    SCF,
+    NOP,
+    HALT,
+    STOP,
+    DI,
+    EI,
+    RLCA,
+    RLA,
+    RRCA,
+    RRA,
+    RLC(DataLoc),
+    RL(DataLoc),
+    RRC(DataLoc),
+    RR(DataLoc),
+    SLA(DataLoc),
+    SRA(DataLoc),
+    SRL(DataLoc),
+    BIT(u8, DataLoc),
+    SET(u8, DataLoc),
+    RES(u8, DataLoc),
+    JP1(u16),
+    JP2(Condition, u16),
+    JP3,
+    JR4(i8),
+    JR5(Condition, i8),
+    CALL(u16),
+    CALLc(Condition, u16),
+    RST(u8),
+    RET,
+    RETc(Condition),
+    RETI,
+}
+
+impl Instruction {
+    /// Decodes the instruction starting with `opcode`, calling `next_byte` for each operand
+    /// byte. Returns None for opcodes that do not exist. Always inlined so that decoding a
+    /// constant opcode folds away in the CPU's dispatch table.
+    #[inline(always)]
+    pub fn decode(opcode: u8, mut next_byte: impl FnMut() -> u8) -> Option<Instruction> {
+        let instruction = match opcode {
+            // INC
+            0x3C => Instruction::INC(DataLoc::Reg(Reg::A)),
+            0x04 => Instruction::INC(DataLoc::Reg(Reg::B)),
+            0x0C => Instruction::INC(DataLoc::Reg(Reg::C)),
+            0x14 => Instruction::INC(DataLoc::Reg(Reg::D)),
+            0x1C => Instruction::INC(DataLoc::Reg(Reg::E)),
+            0x24 => Instruction::INC(DataLoc::Reg(Reg::H)),
+            0x2C => Instruction::INC(DataLoc::Reg(Reg::L)),
+            0x34 => Instruction::INC(DataLoc::AddrReg(AddrReg::HL)),
+
+            // INC 16-bit
+            0x03 => Instruction::INC16(AddrReg::BC),
+            0x13 => Instruction::INC16(AddrReg::DE),
+            0x23 => Instruction::INC16(AddrReg::HL),
+            0x33 => Instruction::INC16(AddrReg::SP),
+
+            // DEC
+            0x3D => Instruction::DEC(DataLoc::Reg(Reg::A)),
+            0x05 => Instruction::DEC(DataLoc::Reg(Reg::B)),
+            0x0D => Instruction::DEC(DataLoc::Reg(Reg::C)),
+            0x15 => Instruction::DEC(DataLoc::Reg(Reg::D)),
+            0x1D => Instruction::DEC(DataLoc::Reg(Reg::E)),
+            0x25 => Instruction::DEC(DataLoc::Reg(Reg::H)),
+            0x2D => Instruction::DEC(DataLoc::Reg(Reg::L)),
+            0x35 => Instruction::DEC(DataLoc::AddrReg(AddrReg::HL)),
+
+            // DEC 16-bit
+            0x0B => Instruction::DEC16(AddrReg::BC),
+            0x1B => Instruction::DEC16(AddrReg::DE),
+            0x2B => Instruction::DEC16(AddrReg::HL),
+            0x3B => Instruction::DEC16(AddrReg::SP),
+
+            // 1. LD
+            0x06 => Instruction::LD(DataLoc::Reg(Reg::B), DataLoc::Value(next_byte())),
+            0x0E => Instruction::LD(DataLoc::Reg(Reg::C), DataLoc::Value(next_byte())),
+            0x16 => Instruction::LD(DataLoc::Reg(Reg::D), DataLoc::Value(next_byte())),
+            0x1E => Instruction::LD(DataLoc::Reg(Reg::E), DataLoc::Value(next_byte())),
+            0x26 => Instruction::LD(DataLoc::Reg(Reg::H), DataLoc::Value(next_byte())),
+            0x2E => Instruction::LD(DataLoc::Reg(Reg::L), DataLoc::Value(next_byte())),
+            0x36 => Instruction::LD(DataLoc::AddrReg(AddrReg::HL), DataLoc::Value(next_byte())),
+
+            // 2. LD
+            0b01000000..0b01110110 | 0b01110111..=0b01111111 => {
+                let first = (opcode >> 3) & 0x7;
+                let second = opcode & 0x7;
+                Instruction::LD(Self::decode_register(first), Self::decode_register(second))
+            }
+
+            // 3. LD
+            0x0A => Instruction::LD(DataLoc::Reg(Reg::A), DataLoc::AddrReg(AddrReg::BC)),
+            0x1A => Instruction::LD(DataLoc::Reg(Reg::A), DataLoc::AddrReg(AddrReg::DE)),
+            0xFA => Instruction::LD(
+                DataLoc::Reg(Reg::A),
+                DataLoc::Addr(Self::next_addr(&mut next_byte)),
+            ),
+            0x3E => Instruction::LD(DataLoc::Reg(Reg::A), DataLoc::Value(next_byte())),
+
+            // 4. LD
+            0x02 => Instruction::LD(DataLoc::AddrReg(AddrReg::BC), DataLoc::Reg(Reg::A)),
+            0x12 => Instruction::LD(DataLoc::AddrReg(AddrReg::DE), DataLoc::Reg(Reg::A)),
+            0xEA => Instruction::LD(
+                DataLoc::Addr(Self::next_addr(&mut next_byte)),
+                DataLoc::Reg(Reg::A),
+            ),
+
+            // 5. LD
+            0xF2 => Instruction::LD5,
+
+            // 6. LD
+            0xE2 => Instruction::LD6,
+
+            // LDH
+            0xE0 => Instruction::LDH1(next_byte()),
+            0xF0 => Instruction::LDH2(next_byte()),
+
+            // LDI
+            0x2A => Instruction::LDI(DataLoc::Reg(Reg::A), DataLoc::AddrReg(AddrReg::HL)),
+            0x22 => Instruction::LDI(DataLoc::AddrReg(AddrReg::HL), DataLoc::Reg(Reg::A)),
+
+            // LDD
+            0x3A => Instruction::LDD(DataLoc::Reg(Reg::A), DataLoc::AddrReg(AddrReg::HL)),
+            0x32 => Instruction::LDD(DataLoc::AddrReg(AddrReg::HL), DataLoc::Reg(Reg::A)),
+
+            // LD 16-bit
+            0x01 => Instruction::LD16(AddrReg::BC, Self::next_addr(&mut next_byte)),
+            0x11 => Instruction::LD16(AddrReg::DE, Self::next_addr(&mut next_byte)),
+            0x21 => Instruction::LD16(AddrReg::HL, Self::next_addr(&mut next_byte)),
+            0x31 => Instruction::LD16(AddrReg::SP, Self::next_addr(&mut next_byte)),
+            0xF9 => Instruction::LDSPHL,
+            0xF8 => Instruction::LDHL(next_byte() as i8),
+            0x08 => Instruction::LDnn(Self::next_addr(&mut next_byte)),
+
+            // PUSH
+            0xF5 => Instruction::PUSH(AddrReg::AF),
+            0xC5 => Instruction::PUSH(AddrReg::BC),
+            0xD5 => Instruction::PUSH(AddrReg::DE),
+            0xE5 => Instruction::PUSH(AddrReg::HL),
+
+            // POP
+            0xF1 => Instruction::POP(AddrReg::AF),
+            0xC1 => Instruction::POP(AddrReg::BC),
+            0xD1 => Instruction::POP(AddrReg::DE),
+            0xE1 => Instruction::POP(AddrReg::HL),
+
+            // ADD
+            0x87 => Instruction::ADD(DataLoc::Reg(Reg::A)),
+            0x80 => Instruction::ADD(DataLoc::Reg(Reg::B)),
+            0x81 => Instruction::ADD(DataLoc::Reg(Reg::C)),
+            0x82 => Instruction::ADD(DataLoc::Reg(Reg::D)),
+            0x83 => Instruction::ADD(DataLoc::Reg(Reg::E)),
+            0x84 => Instruction::ADD(DataLoc::Reg(Reg::H)),
+            0x85 => Instruction::ADD(DataLoc::Reg(Reg::L)),
+            0x86 => Instruction::ADD(DataLoc::AddrReg(AddrReg::HL)),
+            0xC6 => Instruction::ADD(DataLoc::Value(next_byte())),
+
+            // ADC
+            0x8F => Instruction::ADC(DataLoc::Reg(Reg::A)),
+            0x88 => Instruction::ADC(DataLoc::Reg(Reg::B)),
+            0x89 => Instruction::ADC(DataLoc::Reg(Reg::C)),
+            0x8A => Instruction::ADC(DataLoc::Reg(Reg::D)),
+            0x8B => Instruction::ADC(DataLoc::Reg(Reg::E)),
+            0x8C => Instruction::ADC(DataLoc::Reg(Reg::H)),
+            0x8D => Instruction::ADC(DataLoc::Reg(Reg::L)),
+            0x8E => Instruction::ADC(DataLoc::AddrReg(AddrReg::HL)),
+            0xCE => Instruction::ADC(DataLoc::Value(next_byte())),
+
+            // ADD 16-bit
+            0x09 => Instruction::ADD16(AddrReg::BC),
+            0x19 => Instruction::ADD16(AddrReg::DE),
+            0x29 => Instruction::ADD16(AddrReg::HL),
+            0x39 => Instruction::ADD16(AddrReg::SP),
+            0xE8 => Instruction::ADD16n(next_byte() as i8),
+
+            // SUB
+            0x9F => Instruction::SBC(DataLoc::Reg(Reg::A)),
+            0x98 => Instruction::SBC(DataLoc::Reg(Reg::B)),
+            0x99 => Instruction::SBC(DataLoc::Reg(Reg::C)),
+            0x9A => Instruction::SBC(DataLoc::Reg(Reg::D)),
+            0x9B => Instruction::SBC(DataLoc::Reg(Reg::E)),
+            0x9C => Instruction::SBC(DataLoc::Reg(Reg::H)),
+            0x9D => Instruction::SBC(DataLoc::Reg(Reg::L)),
+            0x9E => Instruction::SBC(DataLoc::AddrReg(AddrReg::HL)),
+            0xDE => Instruction::SBC(DataLoc::Value(next_byte())),
+
+            // SBC
+            0x97 => Instruction::SUB(DataLoc::Reg(Reg::A)),
+            0x90 => Instruction::SUB(DataLoc::Reg(Reg::B)),
+            0x91 => Instruction::SUB(DataLoc::Reg(Reg::C)),
+            0x92 => Instruction::SUB(DataLoc::Reg(Reg::D)),
+            0x93 => Instruction::SUB(DataLoc::Reg(Reg::E)),
+            0x94 => Instruction::SUB(DataLoc::Reg(Reg::H)),
+            0x95 => Instruction::SUB(DataLoc::Reg(Reg::L)),
+            0x96 => Instruction::SUB(DataLoc::AddrReg(AddrReg::HL)),
+            0xD6 => Instruction::SUB(DataLoc::Value(next_byte())),
+
+            // AND
+            0xA7 => Instruction::AND(DataLoc::Reg(Reg::A)),
+            0xA0 => Instruction::AND(DataLoc::Reg(Reg::B)),
+            0xA1 => Instruction::AND(DataLoc::Reg(Reg::C)),
+            0xA2 => Instruction::AND(DataLoc::Reg(Reg::D)),
+            0xA3 => Instruction::AND(DataLoc::Reg(Reg::E)),
+            0xA4 => Instruction::AND(DataLoc::Reg(Reg::H)),
+            0xA5 => Instruction::AND(DataLoc::Reg(Reg::L)),
+            0xA6 => Instruction::AND(DataLoc::AddrReg(AddrReg::HL)),
+            0xE6 => Instruction::AND(DataLoc::Value(next_byte())),
+
+            // OR
+            0xB7 => Instruction::OR(DataLoc::Reg(Reg::A)),
+            0xB0 => Instruction::OR(DataLoc::Reg(Reg::B)),
+            0xB1 => Instruction::OR(DataLoc::Reg(Reg::C)),
+            0xB2 => Instruction::OR(DataLoc::Reg(Reg::D)),
+            0xB3 => Instruction::OR(DataLoc::Reg(Reg::E)),
+            0xB4 => Instruction::OR(DataLoc::Reg(Reg::H)),
+            0xB5 => Instruction::OR(DataLoc::Reg(Reg::L)),
+            0xB6 => Instruction::OR(DataLoc::AddrReg(AddrReg::HL)),
+            0xF6 => Instruction::OR(DataLoc::Value(next_byte())),
+
+            // XOR
+            0xAF => Instruction::XOR(DataLoc::Reg(Reg::A)),
+            0xA8 => Instruction::XOR(DataLoc::Reg(Reg::B)),
+            0xA9 => Instruction::XOR(DataLoc::Reg(Reg::C)),
+            0xAA => Instruction::XOR(DataLoc::Reg(Reg::D)),
+            0xAB => Instruction::XOR(DataLoc::Reg(Reg::E)),
+            0xAC => Instruction::XOR(DataLoc::Reg(Reg::H)),
+            0xAD => Instruction::XOR(DataLoc::Reg(Reg::L)),
+            0xAE => Instruction::XOR(DataLoc::AddrReg(AddrReg::HL)),
+            0xEE => Instruction::XOR(DataLoc::Value(next_byte())),
+
+            // CP
+            0xBF => Instruction::CP(DataLoc::Reg(Reg::A)),
+            0xB8 => Instruction::CP(DataLoc::Reg(Reg::B)),
+            0xB9 => Instruction::CP(DataLoc::Reg(Reg::C)),
+            0xBA => Instruction::CP(DataLoc::Reg(Reg::D)),
+            0xBB => Instruction::CP(DataLoc::Reg(Reg::E)),
+            0xBC => Instruction::CP(DataLoc::Reg(Reg::H)),
+            0xBD => Instruction::CP(DataLoc::Reg(Reg::L)),
+            0xBE => Instruction::CP(DataLoc::AddrReg(AddrReg::HL)),
+            0xFE => Instruction::CP(DataLoc::Value(next_byte())),
+
+            // JP
+            0xC3 => Instruction::JP1(Self::next_addr(&mut next_byte)),
+            0xC2 => Instruction::JP2(Condition::NZ, Self::next_addr(&mut next_byte)),
+            0xCA => Instruction::JP2(Condition::Z, Self::next_addr(&mut next_byte)),
+            0xD2 => Instruction::JP2(Condition::NC, Self::next_addr(&mut next_byte)),
+            0xDA => Instruction::JP2(Condition::C, Self::next_addr(&mut next_byte)),
+            0xE9 => Instruction::JP3,
+
+            // JR
+            0x18 => Instruction::JR4(next_byte() as i8),
+            0x20 => Instruction::JR5(Condition::NZ, next_byte() as i8),
+            0x28 => Instruction::JR5(Condition::Z, next_byte() as i8),
+            0x30 => Instruction::JR5(Condition::NC, next_byte() as i8),
+            0x38 => Instruction::JR5(Condition::C, next_byte() as i8),
+
+            // CALL
+            0xCD => Instruction::CALL(Self::next_addr(&mut next_byte)),
+            0xC4 => Instruction::CALLc(Condition::NZ, Self::next_addr(&mut next_byte)),
+            0xCC => Instruction::CALLc(Condition::Z, Self::next_addr(&mut next_byte)),
+            0xD4 => Instruction::CALLc(Condition::NC, Self::next_addr(&mut next_byte)),
+            0xDC => Instruction::CALLc(Condition::C, Self::next_addr(&mut next_byte)),
+
+            // RET
+            0xC9 => Instruction::RET,
+            0xC0 => Instruction::RETc(Condition::NZ),
+            0xC8 => Instruction::RETc(Condition::Z),
+            0xD0 => Instruction::RETc(Condition::NC),
+            0xD8 => Instruction::RETc(Condition::C),
+            0xD9 => Instruction::RETI,
+
+            // Misc
+            0x27 => Instruction::DAA,
+            0x2F => Instruction::CPL,
+            0x3F => Instruction::CCF,
+            0x37 => Instruction::SCF,
+            0x00 => Instruction::NOP,
+            0x76 => Instruction::HALT,
+            0x10 => {
+                if next_byte() != 0 {
+                    return None;
+                }
+                Instruction::STOP
+            }
+            0xF3 => Instruction::DI,
+            0xFB => Instruction::EI,
+
+            // Rotates & Shifts
+            0x07 => Instruction::RLCA,
+            0x17 => Instruction::RLA,
+            0x0F => Instruction::RRCA,
+            0x1F => Instruction::RRA,
+
+            0xCB => {
+                let prefixed = next_byte();
+                match prefixed {
+                    // Rotates & Shifts
+                    0x00..=0x07 => {
+                        let r = prefixed & 0x07;
+                        Instruction::RLC(Self::decode_register(r))
+                    }
+                    0x08..=0x0F => {
+                        let r = prefixed & 0x07;
+                        Instruction::RRC(Self::decode_register(r))
+                    }
+                    0x10..=0x17 => {
+                        let r = prefixed & 0x07;
+                        Instruction::RL(Self::decode_register(r))
+                    }
+                    0x18..=0x1F => {
+                        let r = prefixed & 0x07;
+                        Instruction::RR(Self::decode_register(r))
+                    }
+                    0x20..=0x27 => {
+                        let r = prefixed & 0x07;
+                        Instruction::SLA(Self::decode_register(r))
+                    }
+                    0x28..=0x2F => {
+                        let r = prefixed & 0x07;
+                        Instruction::SRA(Self::decode_register(r))
+                    }
+                    0x38..=0x3F => {
+                        let r = prefixed & 0x07;
+                        Instruction::SRL(Self::decode_register(r))
+                    }
+                    0x30..=0x37 => {
+                        let r = prefixed & 0x07;
+                        Instruction::SWAP(Self::decode_register(r))
+                    }
+                    // BIT: data is encoded as 0b01bbbrrr.
+                    0x40..=0x7F => {
+                        let b = (prefixed >> 3) & 0b0000_0111;
+                        let r = prefixed & 0b0000_0111;
+                        Instruction::BIT(b, Self::decode_register(r))
+                    }
+                    // RES: data is encoded as 0b10bbbrrr.
+                    0b10000000..=0b10111111 => {
+                        let b = (prefixed >> 3) & 0x07;
+                        let r = prefixed & 0x07;
+                        Instruction::RES(b, Self::decode_register(r))
+                    }
+                    0b11000000..=0b11111111 => {
+                        let b = (prefixed >> 3) & 0x07;
+                        let r = prefixed & 0x07;
+                        Instruction::SET(b, Self::decode_register(r))
+                    }
+                }
+            }
+
+            // RST
+            0xC7 => Instruction::RST(0x00),
+            0xCF => Instruction::RST(0x08),
+            0xD7 => Instruction::RST(0x10),
+            0xDF => Instruction::RST(0x18),
+            0xE7 => Instruction::RST(0x20),
+            0xEF => Instruction::RST(0x28),
+            0xF7 => Instruction::RST(0x30),
+            0xFF => Instruction::RST(0x38),
+
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    fn next_addr(next_byte: &mut impl FnMut() -> u8) -> u16 {
+        (next_byte() as u16) | ((next_byte() as u16) << 8)
+    }
+
+    fn decode_register(encoding: u8) -> DataLoc {
+        match encoding {
+            0x7 => DataLoc::Reg(Reg::A),
+            0x0 => DataLoc::Reg(Reg::B),
+            0x1 => DataLoc::Reg(Reg::C),
+            0x2 => DataLoc::Reg(Reg::D),
+            0x3 => DataLoc::Reg(Reg::E),
+            0x4 => DataLoc::Reg(Reg::H),
+            0x5 => DataLoc::Reg(Reg::L),
+            0x6 => DataLoc::AddrReg(AddrReg::HL),
+            _ => panic!("Invalid register encoding."),
+        }
+    }
+
+    pub fn clock_cycles(&self) -> u8 {
+        match self {
+            Instruction::LD(a, b) => match (a, b) {
+                (DataLoc::Reg(_), DataLoc::Reg(_)) => 4,
+                (DataLoc::Reg(_), DataLoc::AddrReg(_)) => 8,
+                (DataLoc::Reg(_), DataLoc::Addr(_)) => 16,
+                (DataLoc::Reg(_), DataLoc::Value(_)) => 8,
+                (DataLoc::AddrReg(_), DataLoc::Reg(_)) => 8,
+                (DataLoc::AddrReg(_), DataLoc::Value(_)) => 12,
+                (DataLoc::Addr(_), DataLoc::Reg(_)) => 16,
+                (a, b) => panic!("{:?} {:?}", a, b),
+            },
+            Instruction::LD5 => 8,
+            Instruction::LD6 => 8,
+            Instruction::LDD(_, _) => 8,
+            Instruction::LDI(_, _) => 8,
+            Instruction::LDH1(_) => 12,
+            Instruction::LDH2(_) => 12,
+            Instruction::LD16(_, _) => 12,
+            Instruction::LDSPHL => 8,
+            Instruction::LDHL(_) => 12,
+            Instruction::LDnn(_) => 20,
+            Instruction::PUSH(_) => 16,
+            Instruction::POP(_) => 12,
+            Instruction::ADD(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::ADC(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::SUB(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::SBC(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::AND(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::OR(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::XOR(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::CP(v) => Self::simple_arith_clock_cycles(v),
+            Instruction::INC(v) => match v {
+                DataLoc::Reg(_) => 4,
+                DataLoc::AddrReg(_) => 12,
+                _ => 4,
+            },
+            Instruction::DEC(v) => match v {
+                DataLoc::Reg(_) => 4,
+                DataLoc::AddrReg(_) => 12,
+                _ => 4,
+            },
+            Instruction::ADD16(_) => 8,
+            Instruction::ADD16n(_) => 16,
+            Instruction::INC16(_) => 8,
+            Instruction::DEC16(_) => 8,
+            Instruction::SWAP(v) => match v {
+                DataLoc::Reg(_) => 8,
+                DataLoc::AddrReg(_) => 16,
+                _ => 8,
+            },
+            Instruction::DAA => 4,
+            Instruction::CPL => 4,
+            Instruction::CCF => 4,
+            Instruction::SCF => 4,
+            Instruction::NOP => 4,
+            Instruction::HALT => 4,
+            Instruction::STOP => 4,
+            Instruction::DI => 4,
+            Instruction::EI => 4,
+            Instruction::RLCA => 4,
+            Instruction::RLA => 4,
+            Instruction::RRCA => 4,
+            Instruction::RRA => 4,
+            Instruction::RLC(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::RL(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::RRC(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::RR(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::SLA(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::SRA(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::SRL(v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::BIT(_, v) => Self::clock_cycles_bit(v),
+            Instruction::SET(_, v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::RES(_, v) => Self::clock_cycles_rotate_shift(v),
+            Instruction::JP1(_) => 16,
+            Instruction::JP2(_, _) => 12,
+            Instruction::JP3 => 4,
+            Instruction::JR4(_) => 12,
+            Instruction::JR5(_, _) => 8,
+            Instruction::CALL(_) => 24,
+            Instruction::CALLc(_, _) => 12,
+            Instruction::RST(_) => 16,
+            Instruction::RET => 16,
+            Instruction::RETc(_) => 8,
+            Instruction::RETI => 16,
+        }
+    }
+
+    fn clock_cycles_rotate_shift(v: &DataLoc) -> u8 {
+        match v {
+            DataLoc::Reg(_) => 8,
+            DataLoc::AddrReg(_) => 16,
+            _ => 8,
+        }
+    }
+
+    fn clock_cycles_bit(v: &DataLoc) -> u8 {
+        match v {
+            DataLoc::Reg(_) => 8,
+            DataLoc::AddrReg(_) => 12,
+            _ => 8,
+        }
+    }
+
+    fn simple_arith_clock_cycles(v: &DataLoc) -> u8 {
+        match v {
+            DataLoc::Reg(_) => 4,
+            DataLoc::AddrReg(_) => 8,
+            DataLoc::Value(_) => 8,
+            _ => 4,
+        }
+    }
+
+    pub fn machine_cycles(&self) -> u8 {
+        self.clock_cycles() / 4
+    }
+}
+
+/// Assembly syntax as in the Pan Docs, e.g. `LD A, (HL+)`. Immediate values are hex.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::LD(to, from) => write!(f, "LD {}, {}", to, from),
+            Instruction::LD5 => write!(f, "LD A, ($FF00+C)"),
+            Instruction::LD6 => write!(f, "LD ($FF00+C), A"),
+            Instruction::LDD(DataLoc::AddrReg(_), from) => write!(f, "LD (HL-), {}", from),
+            Instruction::LDD(to, _) => write!(f, "LD {}, (HL-)", to),
+            Instruction::LDI(DataLoc::AddrReg(_), from) => write!(f, "LD (HL+), {}", from),
+            Instruction::LDI(to, _) => write!(f, "LD {}, (HL+)", to),
+            Instruction::LDH1(n) => write!(f, "LDH ($FF{:02X}), A", n),
+            Instruction::LDH2(n) => write!(f, "LDH A, ($FF{:02X})", n),
+            Instruction::LD16(r, v) => write!(f, "LD {:?}, ${:04X}", r, v),
+            Instruction::LDSPHL => write!(f, "LD SP, HL"),
+            Instruction::LDHL(e) => write!(f, "LD HL, SP{:+}", e),
+            Instruction::LDnn(addr) => write!(f, "LD (${:04X}), SP", addr),
+            Instruction::PUSH(r) => write!(f, "PUSH {:?}", r),
+            Instruction::POP(r) => write!(f, "POP {:?}", r),
+            Instruction::ADD(v) => write!(f, "ADD A, {}", v),
+            Instruction::ADC(v) => write!(f, "ADC A, {}", v),
+            Instruction::SUB(v) => write!(f, "SUB {}", v),
+            Instruction::SBC(v) => write!(f, "SBC A, {}", v),
+            Instruction::AND(v) => write!(f, "AND {}", v),
+            Instruction::OR(v) => write!(f, "OR {}", v),
+            Instruction::XOR(v) => write!(f, "XOR {}", v),
+            Instruction::CP(v) => write!(f, "CP {}", v),
+            Instruction::INC(v) => write!(f, "INC {}", v),
+            Instruction::DEC(v) => write!(f, "DEC {}", v),
+            Instruction::ADD16(r) => write!(f, "ADD HL, {:?}", r),
+            Instruction::ADD16n(e) => write!(f, "ADD SP, {}", e),
+            Instruction::INC16(r) => write!(f, "INC {:?}", r),
+            Instruction::DEC16(r) => write!(f, "DEC {:?}", r),
+            Instruction::SWAP(v) => write!(f, "SWAP {}", v),
+            Instruction::DAA => write!(f, "DAA"),
+            Instruction::CPL => write!(f, "CPL"),
+            Instruction::CCF => write!(f, "CCF"),
+            Instruction::SCF => write!(f, "SCF"),
+            Instruction::NOP => write!(f, "NOP"),
+            Instruction::HALT => write!(f, "HALT"),
+            Instruction::STOP => write!(f, "STOP"),
+            Instruction::DI => write!(f, "DI"),
+            Instruction::EI => write!(f, "EI"),
+            Instruction::RLCA => write!(f, "RLCA"),
+            Instruction::RLA => write!(f, "RLA"),
+            Instruction::RRCA => write!(f, "RRCA"),
+            Instruction::RRA => write!(f, "RRA"),
+            Instruction::RLC(v) => write!(f, "RLC {}", v),
+            Instruction::RL(v) => write!(f, "RL {}", v),
+            Instruction::RRC(v) => write!(f, "RRC {}", v),
+            Instruction::RR(v) => write!(f, "RR {}", v),
+            Instruction::SLA(v) => write!(f, "SLA {}", v),
+            Instruction::SRA(v) => write!(f, "SRA {}", v),
+            Instruction::SRL(v) => write!(f, "SRL {}", v),
+            Instruction::BIT(b, v) => write!(f, "BIT {}, {}", b, v),
+            Instruction::SET(b, v) => write!(f, "SET {}, {}", b, v),
+            Instruction::RES(b, v) => write!(f, "RES {}, {}", b, v),
+            Instruction::JP1(addr) => write!(f, "JP ${:04X}", addr),
+            Instruction::JP2(c, addr) => write!(f, "JP {:?}, ${:04X}", c, addr),
+            Instruction::JP3 => write!(f, "JP HL"),
+            Instruction::JR4(e) => write!(f, "JR {:+}", e),
+            Instruction::JR5(c, e) => write!(f, "JR {:?}, {:+}", c, e),
+            Instruction::CALL(addr) => write!(f, "CALL ${:04X}", addr),
+            Instruction::CALLc(c, addr) => write!(f, "CALL {:?}, ${:04X}", c, addr),
+            Instruction::RST(n) => write!(f, "RST ${:02X}", n),
+            Instruction::RET => write!(f, "RET"),
+            Instruction::RETc(c) => write!(f, "RET {:?}", c),
+            Instruction::RETI => write!(f, "RETI"),
+        }
+    }
+}
@@ -38,9 +38,51 @@ impl JoyPad {
         self.right = false;
     }
 
+    /// The buttons as a bit mask: A, B, Select, Start, Right, Left, Up, Down from bit 0.
+    pub fn buttons(&self) -> u8 {
+        [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.right,
+            self.left,
+            self.up,
+            self.down,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, pressed)| bits | (*pressed as u8) << i)
+    }
+
+    /// Sets the buttons from a bit mask as returned by `buttons`.
+    pub fn set_buttons(&mut self, bits: u8) {
+        let pressed = |i: u8| bits & (1 << i) != 0;
+        self.a = pressed(0);
+        self.b = pressed(1);
+        self.select = pressed(2);
+        self.start = pressed(3);
+        self.right = pressed(4);
+        self.left = pressed(5);
+        self.up = pressed(6);
+        self.down = pressed(7);
+    }
+
     pub fn update(&mut self, mem: &mut Memory) {
         mem[0xFF00] |= 0x0F;
         let input_select = mem[0xFF00] >> 4;
+        // With SGB multiplayer, the joypads take turns and only the first one is connected.
+        // P1 reads their number with neither buttons nor directions selected.
+        if let Some(sgb) = mem.sgb.as_ref().filter(|sgb| sgb.multiplayer()) {
+            let player = sgb.player();
+            if input_select == 0b11 {
+                mem[0xFF00] &= 0xF0 | !player;
+            }
+            if player != 0 {
+                self.previous = mem[0xFF00];
+                return;
+            }
+        }
         if input_select == 0b01 {
             if self.start {
                 self.set_button_bit(mem, 3);
@@ -77,7 +119,7 @@ impl JoyPad {
             panic!("Button out of range");
         }
         if self.previous & (1 << button) != 0 {
-            mem[0xFF0F] |= 1 << 4;
+            mem.request_interrupt(1 << 4);
         }
         mem[0xFF00] &= 0x10 | !(1 << button);
     }
@@ -0,0 +1,37 @@
+pub mod addrreg;
+pub mod apu;
+pub mod audio_registers;
+pub mod bus;
+pub mod capture;
+pub mod cartridge_header;
+pub mod cgb;
+pub mod cheats;
+pub mod condition;
+pub mod cpu;
+pub mod dataloc;
+pub mod debugger;
+pub mod disassembler;
+pub mod div_timer;
+pub mod gameboy;
+pub mod headless;
+pub mod hooks;
+pub mod host;
+pub mod instruction;
+pub mod joypad;
+pub mod memory;
+pub mod movie;
+pub mod ppu;
+pub mod reg;
+pub mod register;
+pub mod rewind;
+pub mod ring_buffer;
+pub mod rom_file;
+pub mod save_file;
+pub mod save_state;
+pub mod serial;
+pub mod sgb;
+pub mod test_rom;
+pub mod trace;
+pub mod vram_viewer;
+
+pub use gameboy::GameBoy;
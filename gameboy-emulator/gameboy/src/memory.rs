@@ -0,0 +1,1404 @@
+use crate::audio_registers::AudioRegisters;
+use crate::cartridge_header::CartridgeHeader;
+use crate::cgb::{ColorPalettes, Hdma};
+use crate::cheats::Cheats;
+use crate::div_timer::DivTimer;
+use crate::ppu::DmgPalette;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use crate::sgb::Sgb;
+use log::debug;
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::ops::{Index, IndexMut};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Number of 16 KiB banks `rom` is mapped as: its size rounded up to a power of two, and
+/// at least the two banks of a 32 KiB cartridge. The header's ROM size isn't used, as it
+/// may be corrupt when the header isn't checked.
+pub fn rom_bank_count(rom: &[u8]) -> usize {
+    rom.len().div_ceil(ROM_BANK_SIZE).next_power_of_two().max(2)
+}
+
+pub struct ROMOnly {
+    null: u8,
+}
+
+impl ROMOnly {
+    pub fn new() -> Self {
+        Self { null: 0 }
+    }
+
+    pub fn rom_bank(&self) -> usize {
+        1
+    }
+
+    pub fn rom_write(&mut self, _: u16) -> &mut u8 {
+        &mut self.null
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        None
+    }
+}
+
+#[derive(Debug)]
+enum MBC1MemoryModel {
+    ROMUpperBits,
+    RAMBanking,
+    Neither,
+}
+
+pub struct MBC1 {
+    memory_model: MBC1MemoryModel,
+    /// MBC1M multicarts wire the upper bank bits to bits 4-5 instead of 5-6 of the bank
+    /// number, so each of the four games sees a 256 KiB cartridge.
+    multicart: bool,
+    ram_enable: u8,
+    advanced_mode: u8, // last bit is: false == 16/8, true == 32/4
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    upper_rom_bank_bits: u8,
+    ram_bank: u8,
+    void: u8,
+}
+
+impl MBC1 {
+    pub fn new(rom_banks: usize, ram_size: u8, multicart: bool) -> Self {
+        let memory_model = if rom_banks >= 64 {
+            MBC1MemoryModel::ROMUpperBits
+        } else if ram_size >= 3 {
+            MBC1MemoryModel::RAMBanking
+        } else {
+            MBC1MemoryModel::Neither
+        };
+        Self {
+            memory_model,
+            multicart,
+            ram_enable: 0,
+            advanced_mode: 0,
+            rom_bank: 1,
+            rom_bank_mask: min(0b0001_1111, rom_banks - 1) as u8,
+            upper_rom_bank_bits: 0,
+            ram_bank: 0,
+            void: 0,
+        }
+    }
+
+    pub fn rom_bank(&self) -> usize {
+        if self.multicart {
+            // The zero check still looks at all 5 bits of the register.
+            let lower_bank = max(self.rom_bank & 0b0001_1111, 1) & 0b1111;
+            return (lower_bank | self.upper_bank_bits()) as usize;
+        }
+        let lower_bank = max(self.rom_bank & self.rom_bank_mask, 1);
+        let bank_number = lower_bank | self.upper_bank_bits();
+        max(bank_number, 1) as usize
+    }
+
+    /// In advanced mode the upper bank bits also switch the bank mapped at 0x0000-0x3FFF,
+    /// which is how multicart menus start the selected game.
+    pub fn rom_bank_zero(&self) -> usize {
+        if self.advanced_mode & 1 == 1 {
+            self.upper_bank_bits() as usize
+        } else {
+            0
+        }
+    }
+
+    fn upper_bank_bits(&self) -> u8 {
+        let shift = if self.multicart { 4 } else { 5 };
+        (self.upper_rom_bank_bits & 0b11) << shift
+    }
+
+    pub fn rom_write(&mut self, addr: u16) -> &mut u8 {
+        match addr {
+            0x0000..=0x1FFF => &mut self.ram_enable,
+            0x2000..=0x3FFF => &mut self.rom_bank,
+            0x4000..=0x5FFF => {
+                debug!("Write to rom ram banking {:?}", self.memory_model);
+                match self.memory_model {
+                    MBC1MemoryModel::ROMUpperBits => &mut self.upper_rom_bank_bits,
+                    MBC1MemoryModel::RAMBanking => &mut self.ram_bank,
+                    MBC1MemoryModel::Neither => &mut self.void,
+                }
+            }
+            0x6000..=0x7FFF => &mut self.advanced_mode,
+            _ => panic!("Invalid write to memory bank: {:04x}", addr),
+        }
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        if self.ram_enable == 0x0A {
+            Some(self.ram_bank as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// What MBC2 RAM reads as for each value of the lower 4 bits that exist.
+const MBC2_RAM_READS: [u8; 0x10] = [
+    0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE, 0xFF,
+];
+
+/// MBC2 has 512 half-bytes of RAM built in. Bit 8 of the address selects whether a write
+/// to 0x0000-0x3FFF goes to the RAM enable or the ROM bank register.
+pub struct MBC2 {
+    ram_enable: u8,
+    rom_bank_reg: u8,
+    void: u8,
+}
+
+impl MBC2 {
+    pub fn new() -> Self {
+        Self {
+            ram_enable: 0,
+            rom_bank_reg: 1,
+            void: 0,
+        }
+    }
+
+    pub fn rom_bank(&self) -> usize {
+        max(self.rom_bank_reg & 0b1111, 1) as usize
+    }
+
+    pub fn rom_write(&mut self, addr: u16) -> &mut u8 {
+        match addr {
+            0x0000..=0x3FFF if addr & 0x0100 == 0 => &mut self.ram_enable,
+            0x0000..=0x3FFF => &mut self.rom_bank_reg,
+            _ => &mut self.void,
+        }
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        self.ram_enabled().then_some(0)
+    }
+
+    /// The 512 bytes of RAM repeat throughout 0xA000-0xBFFF.
+    pub fn ram_offset(&self, addr: u16) -> Option<usize> {
+        self.ram_enabled().then_some((addr & 0x01FF) as usize)
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enable & 0x0F == 0x0A
+    }
+}
+
+impl Default for MBC2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bit of the MBC5 RAM bank register that drives the motor on rumble cartridges.
+const MBC5_RUMBLE_BIT: u8 = 1 << 3;
+
+pub struct MBC5 {
+    ram_enable: u8,
+    rom_bank_low: u8,
+    rom_bank_high: u8,
+    ram_bank_reg: u8,
+    has_rumble: bool,
+    rumble: bool,
+    rumble_event: Option<bool>,
+    void: u8,
+}
+
+impl MBC5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            ram_enable: 0,
+            rom_bank_low: 1,
+            rom_bank_high: 0,
+            ram_bank_reg: 0,
+            has_rumble,
+            rumble: false,
+            rumble_event: None,
+            void: 0,
+        }
+    }
+
+    /// Unlike MBC1 and MBC3, bank 0 can be mapped at 0x4000-0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        (((self.rom_bank_high & 1) as usize) << 8) | self.rom_bank_low as usize
+    }
+
+    pub fn rom_write(&mut self, addr: u16) -> &mut u8 {
+        match addr {
+            0x0000..=0x1FFF => &mut self.ram_enable,
+            0x2000..=0x2FFF => &mut self.rom_bank_low,
+            0x3000..=0x3FFF => &mut self.rom_bank_high,
+            0x4000..=0x5FFF => &mut self.ram_bank_reg,
+            _ => &mut self.void,
+        }
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        if self.ram_enable & 0x0F != 0x0A {
+            return None;
+        }
+        let mask = if self.has_rumble { 0b0111 } else { 0b1111 };
+        Some((self.ram_bank_reg & mask) as usize)
+    }
+
+    pub fn tick(&mut self) {
+        let rumble = self.has_rumble && self.ram_bank_reg & MBC5_RUMBLE_BIT != 0;
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            self.rumble_event = Some(rumble);
+        }
+    }
+}
+
+const KEY1_ARMED: u8 = 1 << 0;
+const KEY1_DOUBLE_SPEED: u8 = 1 << 7;
+const KEY1_UNUSED_BITS: u8 = 0b0111_1110;
+
+/// Number of dots in one second of real time, at which the MBC3 RTC ticks. Dots don't
+/// speed up in CGB double speed mode, unlike M-cycles.
+const RTC_DOTS_PER_SECOND: u32 = 4_194_304;
+/// Writable bits of the S, M, H, DL and DH RTC registers respectively.
+const RTC_REGISTER_MASKS: [u8; 5] = [
+    0b0011_1111,
+    0b0011_1111,
+    0b0001_1111,
+    0b1111_1111,
+    0b1100_0001,
+];
+const RTC_DH_DAY_HI: u8 = 1 << 0;
+const RTC_DH_HALT: u8 = 1 << 6;
+const RTC_DH_DAY_CARRY: u8 = 1 << 7;
+
+/// The real-time clock found on MBC3 cartridges. Registers are stored in the order
+/// S, M, H, DL, DH, which matches their select values 0x08-0x0C.
+pub struct RealTimeClock {
+    pub live: [u8; 5],
+    pub latched: [u8; 5],
+    dot_counter: u32,
+}
+
+impl RealTimeClock {
+    pub fn new() -> Self {
+        Self {
+            live: [0; 5],
+            latched: [0; 5],
+            dot_counter: 0,
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.live[4] & RTC_DH_HALT != 0
+    }
+
+    /// Copies the running clock to the registers the CPU reads, without the bits that
+    /// don't exist.
+    pub fn latch(&mut self) {
+        self.mask_registers();
+        self.latched = self.live;
+    }
+
+    pub fn tick(&mut self, dots: u32) {
+        self.mask_registers();
+        if self.halted() {
+            return;
+        }
+        self.dot_counter += dots;
+        while self.dot_counter >= RTC_DOTS_PER_SECOND {
+            self.dot_counter -= RTC_DOTS_PER_SECOND;
+            self.advance_seconds(1);
+        }
+    }
+
+    /// Advance the clock by a number of seconds. Out-of-range values written by the game
+    /// (e.g. 62 seconds) count up to the register's bit limit and wrap without carrying,
+    /// just like the hardware does.
+    pub fn advance_seconds(&mut self, seconds: u64) {
+        self.mask_registers();
+        let minutes = Self::advance(&mut self.live[0], seconds, 60, RTC_REGISTER_MASKS[0]);
+        let hours = Self::advance(&mut self.live[1], minutes, 60, RTC_REGISTER_MASKS[1]);
+        let days = Self::advance(&mut self.live[2], hours, 24, RTC_REGISTER_MASKS[2]);
+        let day = self.day() as u64 + days;
+        if day > 0x1FF {
+            self.live[4] |= RTC_DH_DAY_CARRY;
+        }
+        self.set_day((day & 0x1FF) as u16);
+    }
+
+    /// Writes land in the live registers unmasked, so clear the bits that do not exist.
+    fn mask_registers(&mut self) {
+        for (reg, mask) in self.live.iter_mut().zip(RTC_REGISTER_MASKS) {
+            *reg &= mask;
+        }
+    }
+
+    pub fn day(&self) -> u16 {
+        (((self.live[4] & RTC_DH_DAY_HI) as u16) << 8) | self.live[3] as u16
+    }
+
+    fn set_day(&mut self, day: u16) {
+        self.live[3] = (day & 0xFF) as u8;
+        self.live[4] = (self.live[4] & !RTC_DH_DAY_HI) | ((day >> 8) as u8 & RTC_DH_DAY_HI);
+    }
+
+    /// Advances a single counter register by `count`, returning how often it carried into
+    /// the next. A value at or above `limit` first counts up to `mask` and wraps to zero
+    /// without carrying.
+    fn advance(reg: &mut u8, count: u64, limit: u8, mask: u8) -> u64 {
+        let mut count = count;
+        if *reg >= limit {
+            let to_wrap = (mask - *reg) as u64 + 1;
+            if count < to_wrap {
+                *reg += count as u8;
+                return 0;
+            }
+            count -= to_wrap;
+            *reg = 0;
+        }
+        let total = *reg as u64 + count;
+        *reg = (total % limit as u64) as u8;
+        total / limit as u64
+    }
+}
+
+impl Default for RealTimeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct MBC3 {
+    ram_enable: u8,
+    rom_bank_reg: u8,
+    ram_rtc_select: u8,
+    latch_reg: u8,
+    latch_prev: u8,
+    pub rtc: RealTimeClock,
+}
+
+impl MBC3 {
+    pub fn new() -> Self {
+        Self {
+            ram_enable: 0,
+            rom_bank_reg: 1,
+            ram_rtc_select: 0,
+            latch_reg: 0xFF,
+            latch_prev: 0xFF,
+            rtc: RealTimeClock::new(),
+        }
+    }
+
+    pub fn rom_bank(&self) -> usize {
+        max(self.rom_bank_reg & 0b0111_1111, 1) as usize
+    }
+
+    pub fn rom_write(&mut self, addr: u16) -> &mut u8 {
+        match addr {
+            0x0000..=0x1FFF => &mut self.ram_enable,
+            0x2000..=0x3FFF => &mut self.rom_bank_reg,
+            0x4000..=0x5FFF => &mut self.ram_rtc_select,
+            0x6000..=0x7FFF => &mut self.latch_reg,
+            _ => panic!("Invalid write to memory bank: {:04x}", addr),
+        }
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        match self.ram_rtc_select {
+            0x00..=0x03 if self.ram_enabled() => Some(self.ram_rtc_select as usize),
+            _ => None,
+        }
+    }
+
+    /// Reads return the latched copy of the clock, writes go to the running clock.
+    pub fn rtc_register(&self) -> Option<&u8> {
+        match self.ram_rtc_select {
+            0x08..=0x0C if self.ram_enabled() => {
+                Some(&self.rtc.latched[(self.ram_rtc_select - 0x08) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn rtc_register_mut(&mut self) -> Option<&mut u8> {
+        match self.ram_rtc_select {
+            0x08..=0x0C if self.ram_enabled() => {
+                if self.ram_rtc_select == 0x08 {
+                    // Writing the seconds restarts the second in progress.
+                    self.rtc.dot_counter = 0;
+                }
+                Some(&mut self.rtc.live[(self.ram_rtc_select - 0x08) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, dots: u32) {
+        // Writes to the latch register are only observable after the fact, so the
+        // 0 -> 1 transition is detected here, on the M-cycle after the write.
+        if self.latch_prev == 0x00 && self.latch_reg == 0x01 {
+            self.rtc.latch();
+        }
+        self.latch_prev = self.latch_reg;
+        self.rtc.tick(dots);
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enable & 0x0F == 0x0A
+    }
+}
+
+impl Default for MBC3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub enum MemoryBankController {
+    ROMOnly(ROMOnly),
+    MBC1(MBC1),
+    MBC2(MBC2),
+    MBC3(MBC3),
+    MBC5(MBC5),
+}
+
+impl MemoryBankController {
+    pub fn rom_bank(&self) -> usize {
+        match self {
+            MemoryBankController::ROMOnly(c) => c.rom_bank(),
+            MemoryBankController::MBC1(c) => c.rom_bank(),
+            MemoryBankController::MBC2(c) => c.rom_bank(),
+            MemoryBankController::MBC3(c) => c.rom_bank(),
+            MemoryBankController::MBC5(c) => c.rom_bank(),
+        }
+    }
+
+    pub fn rom_write(&mut self, addr: u16) -> &mut u8 {
+        match self {
+            MemoryBankController::ROMOnly(c) => c.rom_write(addr),
+            MemoryBankController::MBC1(c) => c.rom_write(addr),
+            MemoryBankController::MBC2(c) => c.rom_write(addr),
+            MemoryBankController::MBC3(c) => c.rom_write(addr),
+            MemoryBankController::MBC5(c) => c.rom_write(addr),
+        }
+    }
+
+    pub fn ram_bank(&self) -> Option<usize> {
+        match self {
+            MemoryBankController::ROMOnly(c) => c.ram_bank(),
+            MemoryBankController::MBC1(c) => c.ram_bank(),
+            MemoryBankController::MBC2(c) => c.ram_bank(),
+            MemoryBankController::MBC3(c) => c.ram_bank(),
+            MemoryBankController::MBC5(c) => c.ram_bank(),
+        }
+    }
+
+    /// Bank mapped at 0x0000-0x3FFF, only ever non-zero on MBC1.
+    pub fn rom_bank_zero(&self) -> usize {
+        match self {
+            MemoryBankController::MBC1(c) => c.rom_bank_zero(),
+            _ => 0,
+        }
+    }
+
+    /// Offset into cartridge RAM of an access to 0xA000-0xBFFF, if RAM is mapped there.
+    pub fn ram_offset(&self, addr: u16) -> Option<usize> {
+        match self {
+            MemoryBankController::MBC2(c) => c.ram_offset(addr),
+            _ => Some(self.ram_bank()? * 0x2000 + (addr - 0xA000) as usize),
+        }
+    }
+
+    pub fn rtc_register(&self) -> Option<&u8> {
+        match self {
+            MemoryBankController::MBC3(c) => c.rtc_register(),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_register_mut(&mut self) -> Option<&mut u8> {
+        match self {
+            MemoryBankController::MBC3(c) => c.rtc_register_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, dots: u32) {
+        match self {
+            MemoryBankController::MBC3(c) => c.tick(dots),
+            MemoryBankController::MBC5(c) => c.tick(),
+            _ => {}
+        }
+    }
+
+    /// Returns the new motor state if a rumble cartridge switched it since the last call.
+    pub fn take_rumble_event(&mut self) -> Option<bool> {
+        match self {
+            MemoryBankController::MBC5(c) => c.rumble_event.take(),
+            _ => None,
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&RealTimeClock> {
+        match self {
+            MemoryBankController::MBC3(c) => Some(&c.rtc),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut RealTimeClock> {
+        match self {
+            MemoryBankController::MBC3(c) => Some(&mut c.rtc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A memory access made by the CPU, recorded while access tracking is enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryAccess {
+    pub addr: u16,
+    pub kind: AccessKind,
+    /// The byte read or written.
+    pub value: u8,
+}
+
+pub struct Memory {
+    bank_ctrl: MemoryBankController,
+    num_rom_banks: usize,
+    boot_rom: Vec<u8>,
+    rom: Vec<u8>,
+    /// Whether the cartridge runs in CGB mode, which enables the banked VRAM and WRAM,
+    /// colour palettes, VRAM DMA and double speed.
+    cgb: bool,
+    pub tile_ram: [u8; 0x1800],
+    pub background_map: [u8; 0x0800],
+    /// CGB VRAM bank 1: tile data and BG map attributes.
+    vram1: [u8; 0x2000],
+    cartridge_ram: Vec<u8>,
+    /// Eight 4 KiB banks, of which DMG mode only uses the first two.
+    wram: [u8; 0x8000],
+    pub sprite: [u8; 0xA0],
+    io1: [u8; 0x10],           // 00 - 0F
+    pub audio: AudioRegisters, // 10 - 26
+    wave_ram: [u8; 0x10],      // 30 - 3F
+    io2: [u8; 0x37],           // 40 - 77
+    pub high_ram: [u8; 0x89],  // 78 - FF
+    unused_response: u8,
+    unused_write_dummy: u8,
+    audio_disabled: u8,
+    pub div: DivTimer,
+    void: u8,
+    pub bg_palettes: ColorPalettes,
+    pub obj_palettes: ColorPalettes,
+    /// Colours of the DMG shades, a display setting that isn't part of the machine state.
+    pub dmg_palette: DmgPalette,
+    hdma: Hdma,
+    /// KEY1 as read: bit 7 is the current speed, bit 0 arms a switch on the next STOP.
+    key1: u8,
+    double_speed: bool,
+    /// Registers whose writes have side effects, handled in `update_cgb_registers`.
+    cgb_register_write: Option<u16>,
+    access_tracking: bool,
+    accesses: Vec<MemoryAccess>,
+    pub cheats: Cheats,
+    /// The Super Game Boy, in SGB mode.
+    pub sgb: Option<Box<Sgb>>,
+}
+
+impl Memory {
+    pub fn new(
+        boot_rom: Vec<u8>,
+        mut rom: Vec<u8>,
+        header: CartridgeHeader,
+    ) -> Result<Self, String> {
+        let num_rom_banks = rom_bank_count(&rom);
+        // Whatever is missing from a truncated ROM reads as open bus.
+        rom.resize(num_rom_banks * ROM_BANK_SIZE, 0xFF);
+        Ok(Self {
+            bank_ctrl: header.memory_bank_controller(&rom)?,
+            num_rom_banks,
+            boot_rom,
+            rom,
+            cgb: header.supports_cgb(),
+            tile_ram: [0; 0x1800],
+            background_map: [0; 0x0800],
+            vram1: [0; 0x2000],
+            cartridge_ram: vec![0; header.ram_size_bytes().max(0x2000)],
+            wram: [0; 0x8000],
+            sprite: [0; 0xA0],
+            io1: [0; 0x10],
+            audio: AudioRegisters::new(),
+            wave_ram: [0; 0x10],
+            // DMA reads 0xFF, which doesn't start an OAM DMA transfer.
+            io2: std::array::from_fn(|i| if i == 0x06 { 0xFF } else { 0 }),
+            high_ram: [0; 0x89],
+            unused_response: 0xFF,
+            unused_write_dummy: 0,
+            audio_disabled: 0,
+            div: DivTimer::new(),
+            void: 0xFF,
+            bg_palettes: ColorPalettes::new(),
+            obj_palettes: ColorPalettes::new(),
+            dmg_palette: DmgPalette::Grey,
+            hdma: Hdma::new(),
+            key1: KEY1_UNUSED_BITS,
+            double_speed: false,
+            cgb_register_write: None,
+            access_tracking: false,
+            accesses: Vec::new(),
+            cheats: Cheats::new(),
+            sgb: None,
+        })
+    }
+
+    pub fn write_contents(&self) -> io::Result<()> {
+        BufWriter::new(File::create("./tile_ram.bin")?).write_all(&self.tile_ram)?;
+        BufWriter::new(File::create("./background_map.bin")?).write_all(&self.background_map)?;
+        BufWriter::new(File::create("./sprite.bin")?).write_all(&self.sprite)?;
+        BufWriter::new(File::create("./high_ram.bin")?).write_all(&self.high_ram)?;
+        // BufWriter::new(File::create("./audio.bin")?).write_all(&self.audio)?;
+        Ok(())
+    }
+
+    pub fn cartridge_ram(&self) -> &[u8] {
+        &self.cartridge_ram
+    }
+
+    pub fn cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge_ram
+    }
+
+    /// Makes the writes of the enabled GameShark codes, once per frame.
+    pub fn apply_cheat_writes(&mut self) {
+        for (bank, addr, value) in self.cheats.ram_writes() {
+            match (bank, addr) {
+                (Some(bank), 0xD000..=0xDFFF) => {
+                    self.wram[bank.max(1) as usize * 0x1000 + (addr - 0xD000) as usize] = value
+                }
+                _ => self[addr] = value,
+            }
+        }
+    }
+
+    pub fn wave_ram(&self) -> &[u8; 0x10] {
+        &self.wave_ram
+    }
+
+    pub fn rtc(&self) -> Option<&RealTimeClock> {
+        self.bank_ctrl.rtc()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut RealTimeClock> {
+        self.bank_ctrl.rtc_mut()
+    }
+
+    /// While enabled, every CPU access made through the `Bus` is recorded.
+    pub fn set_access_tracking(&mut self, enabled: bool) {
+        self.access_tracking = enabled;
+    }
+
+    /// Returns the accesses recorded since the last call.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    pub fn record_access(&mut self, addr: u16, kind: AccessKind, value: u8) {
+        if self.access_tracking {
+            self.accesses.push(MemoryAccess { addr, kind, value });
+        }
+    }
+
+    /// Advances cartridge hardware that runs independently of the CPU, such as the MBC3 RTC,
+    /// by a number of dots.
+    pub fn tick_cartridge(&mut self, dots: u32) {
+        self.bank_ctrl.tick(dots);
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    /// Runs as a Super Game Boy, which has no CGB features.
+    pub fn enable_sgb(&mut self, accepts_commands: bool) {
+        self.cgb = false;
+        self.sgb = Some(Box::new(Sgb::new(accepts_commands)));
+    }
+
+    /// Called by the PPU at the start of VBlank with the completed frame.
+    pub fn sgb_vblank(&mut self, frame: &[u32]) {
+        if let Some(mut sgb) = self.sgb.take() {
+            sgb.vblank(self, frame);
+            self.sgb = Some(sgb);
+        }
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// The PPU and APU keep their speed in CGB double speed mode, so they only see half as
+    /// many dots per M-cycle.
+    pub fn dots_per_m_cycle(&self) -> u32 {
+        if self.double_speed {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// Whether KEY1 requests a speed switch on the next STOP.
+    pub fn speed_switch_armed(&self) -> bool {
+        self.cgb && self.key1 & KEY1_ARMED != 0
+    }
+
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.key1 = KEY1_UNUSED_BITS
+            | if self.double_speed {
+                KEY1_DOUBLE_SPEED
+            } else {
+                0
+            };
+    }
+
+    /// Reads VRAM from either bank, independent of VBK, as the PPU does.
+    pub fn vram(&self, bank: u8, addr: u16) -> u8 {
+        match (bank, addr) {
+            (1, _) => self.vram1[(addr - 0x8000) as usize],
+            (_, 0x8000..=0x97FF) => self.tile_ram[(addr - 0x8000) as usize],
+            _ => self.background_map[(addr - 0x9800) as usize],
+        }
+    }
+
+    /// Reads an LCD register, 0xFF40-0xFF4B, without going through the memory map. The PPU
+    /// reads them every dot.
+    pub fn lcd_reg(&self, addr: u16) -> u8 {
+        debug_assert!((0xFF40..=0xFF4B).contains(&addr));
+        self.io2[(addr - 0xFF40) as usize]
+    }
+
+    pub fn lcd_reg_mut(&mut self, addr: u16) -> &mut u8 {
+        debug_assert!((0xFF40..=0xFF4B).contains(&addr));
+        &mut self.io2[(addr - 0xFF40) as usize]
+    }
+
+    /// Sets bits in IF.
+    pub fn request_interrupt(&mut self, mask: u8) {
+        self.io1[0x0F] |= mask;
+    }
+
+    fn vram_bank(&self) -> u8 {
+        if self.cgb {
+            self.io2[0xFF4F - 0xFF40] & 1
+        } else {
+            0
+        }
+    }
+
+    /// Offset into WRAM of 0xC000-0xFDFF, including echo RAM and the SVBK bank switch.
+    fn wram_offset(&self, addr: u16) -> usize {
+        let addr = (addr as usize - 0xC000) % 0x2000;
+        if addr < 0x1000 {
+            return addr;
+        }
+        let bank = if self.cgb {
+            max(self.io2[0xFF70 - 0xFF40] & 0b111, 1) as usize
+        } else {
+            1
+        };
+        bank * 0x1000 + addr - 0x1000
+    }
+
+    pub fn boot_rom_active(&self) -> bool {
+        self.io2[0xFF50 - 0xFF40] == 0
+    }
+
+    /// Applies the side effects of writes to CGB registers. Writes only land in memory
+    /// when the reference returned by indexing is assigned, so the `Bus` runs this after
+    /// every write.
+    pub fn update_cgb_registers(&mut self) {
+        match self.cgb_register_write.take() {
+            Some(0xFF4D) => {
+                let speed = if self.double_speed {
+                    KEY1_DOUBLE_SPEED
+                } else {
+                    0
+                };
+                self.key1 = KEY1_UNUSED_BITS | speed | (self.key1 & KEY1_ARMED);
+            }
+            Some(0xFF55) => {
+                let blocks = self.hdma.start();
+                self.copy_hdma_blocks(blocks);
+            }
+            Some(0xFF69) => self.bg_palettes.after_data_write(),
+            Some(0xFF6B) => self.obj_palettes.after_data_write(),
+            _ => {}
+        }
+    }
+
+    /// Called by the PPU at the start of every HBlank to run HBlank DMA.
+    pub fn hblank(&mut self) {
+        let blocks = self.hdma.hblank();
+        self.copy_hdma_blocks(blocks);
+    }
+
+    /// Copies VRAM DMA blocks. The CPU is stalled while they are copied on hardware,
+    /// which is not emulated.
+    fn copy_hdma_blocks(&mut self, blocks: u16) {
+        for _ in 0..blocks {
+            let (src, dst) = self.hdma.next_block();
+            for i in 0..0x10 {
+                self[dst.wrapping_add(i)] = self[src.wrapping_add(i)];
+            }
+        }
+    }
+
+    pub fn take_rumble_event(&mut self) -> Option<bool> {
+        self.bank_ctrl.take_rumble_event()
+    }
+
+    /// Offset into the ROM of `addr` in `bank`. Bank numbers beyond the size of the ROM
+    /// wrap around, as the upper bits of the bank register have no address lines to drive.
+    fn rom_offset(&self, bank: usize, addr: u16) -> usize {
+        (bank & (self.num_rom_banks - 1)) * ROM_BANK_SIZE + (addr & 0x3FFF) as usize
+    }
+
+    /// Cartridge RAM banks beyond the installed RAM wrap around, like unconnected
+    /// address lines do.
+    fn cartridge_ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = self.bank_ctrl.ram_offset(addr)?;
+        Some(offset % self.cartridge_ram.len())
+    }
+}
+
+impl Index<u16> for Memory {
+    type Output = u8;
+
+    fn index(&self, addr: u16) -> &Self::Output {
+        match addr {
+            0x0000..=0x00FF if self.boot_rom_active() => &self.boot_rom[addr as usize],
+            // The CGB boot ROM continues after the cartridge header.
+            0x0200..=0x08FF if self.boot_rom_active() && (addr as usize) < self.boot_rom.len() => {
+                &self.boot_rom[addr as usize]
+            }
+            0x0000..=0x3FFF => {
+                let byte = &self.rom[self.rom_offset(self.bank_ctrl.rom_bank_zero(), addr)];
+                self.cheats.patch_rom(addr, byte)
+            }
+            0x4000..=0x7FFF => {
+                let byte = &self.rom[self.rom_offset(self.bank_ctrl.rom_bank(), addr)];
+                self.cheats.patch_rom(addr, byte)
+            }
+            0x8000..=0x9FFF if self.vram_bank() == 1 => &self.vram1[(addr - 0x8000) as usize],
+            0x8000..=0x97FF => &self.tile_ram[(addr - 0x8000) as usize],
+            0x9800..=0x9FFF => &self.background_map[(addr - 0x9800) as usize],
+            0xA000..=0xBFFF => match self.cartridge_ram_offset(addr) {
+                // MBC2 RAM is 4 bits wide, the upper bits read as 1s.
+                Some(offset) if matches!(self.bank_ctrl, MemoryBankController::MBC2(_)) => {
+                    &MBC2_RAM_READS[(self.cartridge_ram[offset] & 0x0F) as usize]
+                }
+                Some(offset) => &self.cartridge_ram[offset],
+                None => self.bank_ctrl.rtc_register().unwrap_or(&self.void),
+            },
+            0xC000..=0xFDFF => &self.wram[self.wram_offset(addr)], // Including echo RAM
+            0xFE00..=0xFE9F => &self.sprite[(addr - 0xFE00) as usize],
+            0xFF00..=0xFF03 => &self.io1[(addr - 0xFF00) as usize],
+            0xFF04..=0xFF07 => self.div.read(addr),
+            0xFF08..=0xFF0F => &self.io1[(addr - 0xFF00) as usize],
+            0xFF10..=0xFF26 => &self.audio[addr],
+            0xFF30..=0xFF3F => &self.wave_ram[(addr - 0xFF30) as usize],
+            0xFF4D if self.cgb => &self.key1,
+            0xFF51..=0xFF54 if self.cgb => &self.void,
+            0xFF55 if self.cgb => &self.hdma.status,
+            0xFF68 if self.cgb => &self.bg_palettes.spec,
+            0xFF69 if self.cgb => self.bg_palettes.data(),
+            0xFF6A if self.cgb => &self.obj_palettes.spec,
+            0xFF6B if self.cgb => self.obj_palettes.data(),
+            0xFF40..=0xFF77 => &self.io2[(addr - 0xFF40) as usize],
+            0xFF77..=0xFFFF => &self.high_ram[(addr - 0xFF77) as usize],
+            _ => {
+                debug!("Unused memory read {:04x}", addr);
+                &self.unused_response
+            }
+        }
+    }
+}
+
+impl IndexMut<u16> for Memory {
+    fn index_mut(&mut self, addr: u16) -> &mut Self::Output {
+        match addr {
+            0x0000..=0x7FFF => self.bank_ctrl.rom_write(addr),
+            0x8000..=0x9FFF if self.vram_bank() == 1 => &mut self.vram1[(addr - 0x8000) as usize],
+            0x8000..=0x97FF => &mut self.tile_ram[(addr - 0x8000) as usize],
+            0x9800..=0x9FFF => &mut self.background_map[(addr - 0x9800) as usize],
+            0xA000..=0xBFFF => match self.cartridge_ram_offset(addr) {
+                Some(offset) => &mut self.cartridge_ram[offset],
+                None => match self.bank_ctrl.rtc_register_mut() {
+                    Some(reg) => reg,
+                    None => &mut self.void,
+                },
+            },
+            0xC000..=0xFDFF => &mut self.wram[self.wram_offset(addr)], // Including echo RAM
+            0xFE00..=0xFE9F => &mut self.sprite[(addr - 0xFE00) as usize],
+            0xFF00..=0xFF03 => &mut self.io1[(addr - 0xFF00) as usize],
+            0xFF04..=0xFF07 => self.div.write(addr),
+            0xFF08..=0xFF0F => &mut self.io1[(addr - 0xFF00) as usize],
+            0xFF10..=0xFF26 => &mut self.audio[addr],
+            0xFF30..=0xFF3F => &mut self.wave_ram[(addr - 0xFF30) as usize],
+            0xFF4D | 0xFF51..=0xFF55 | 0xFF68..=0xFF6B if self.cgb => {
+                self.cgb_register_write = Some(addr);
+                match addr {
+                    0xFF4D => &mut self.key1,
+                    0xFF51..=0xFF55 => &mut self.hdma.regs[(addr - 0xFF51) as usize],
+                    0xFF68 => &mut self.bg_palettes.spec,
+                    0xFF69 => self.bg_palettes.data_mut(),
+                    0xFF6A => &mut self.obj_palettes.spec,
+                    _ => self.obj_palettes.data_mut(),
+                }
+            }
+            0xFF40..=0xFF77 => &mut self.io2[(addr - 0xFF40) as usize],
+            0xFF77..=0xFFFF => &mut self.high_ram[(addr - 0xFF77) as usize],
+            _ => {
+                debug!("Unused memory write {:04x}", addr);
+                &mut self.unused_write_dummy
+            }
+        }
+    }
+}
+
+impl Snapshot for MBC1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[
+            self.ram_enable,
+            self.advanced_mode,
+            self.rom_bank,
+            self.upper_rom_bank_bits,
+            self.ram_bank,
+        ]);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        [
+            self.ram_enable,
+            self.advanced_mode,
+            self.rom_bank,
+            self.upper_rom_bank_bits,
+            self.ram_bank,
+        ] = r.read_array()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for MBC2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[self.ram_enable, self.rom_bank_reg]);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        [self.ram_enable, self.rom_bank_reg] = r.read_array()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for MBC5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[
+            self.ram_enable,
+            self.rom_bank_low,
+            self.rom_bank_high,
+            self.ram_bank_reg,
+        ]);
+        w.write_bool(self.rumble);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        [
+            self.ram_enable,
+            self.rom_bank_low,
+            self.rom_bank_high,
+            self.ram_bank_reg,
+        ] = r.read_array()?;
+        self.rumble = r.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for RealTimeClock {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.live);
+        w.write_bytes(&self.latched);
+        w.write_u32(self.dot_counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.live)?;
+        r.read_into(&mut self.latched)?;
+        self.dot_counter = r.read_u32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for MBC3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[
+            self.ram_enable,
+            self.rom_bank_reg,
+            self.ram_rtc_select,
+            self.latch_reg,
+            self.latch_prev,
+        ]);
+        self.rtc.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        [
+            self.ram_enable,
+            self.rom_bank_reg,
+            self.ram_rtc_select,
+            self.latch_reg,
+            self.latch_prev,
+        ] = r.read_array()?;
+        self.rtc.load_state(r)
+    }
+}
+
+impl MemoryBankController {
+    fn tag(&self) -> u8 {
+        match self {
+            MemoryBankController::ROMOnly(_) => 0,
+            MemoryBankController::MBC1(_) => 1,
+            MemoryBankController::MBC2(_) => 2,
+            MemoryBankController::MBC3(_) => 3,
+            MemoryBankController::MBC5(_) => 5,
+        }
+    }
+}
+
+impl Snapshot for MemoryBankController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.tag());
+        match self {
+            MemoryBankController::ROMOnly(_) => {}
+            MemoryBankController::MBC1(c) => c.save_state(w),
+            MemoryBankController::MBC2(c) => c.save_state(w),
+            MemoryBankController::MBC3(c) => c.save_state(w),
+            MemoryBankController::MBC5(c) => c.save_state(w),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        if r.read_u8()? != self.tag() {
+            return Err("Save state has a different memory bank controller".to_string());
+        }
+        match self {
+            MemoryBankController::ROMOnly(_) => Ok(()),
+            MemoryBankController::MBC1(c) => c.load_state(r),
+            MemoryBankController::MBC2(c) => c.load_state(r),
+            MemoryBankController::MBC3(c) => c.load_state(r),
+            MemoryBankController::MBC5(c) => c.load_state(r),
+        }
+    }
+}
+
+impl Snapshot for Memory {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.bank_ctrl.save_state(w);
+        w.write_bytes(&self.tile_ram);
+        w.write_bytes(&self.background_map);
+        w.write_bytes(&self.vram1);
+        w.write_bytes(&self.cartridge_ram);
+        w.write_bytes(&self.wram);
+        w.write_bytes(&self.sprite);
+        w.write_bytes(&self.io1);
+        self.audio.save_state(w);
+        w.write_bytes(&self.wave_ram);
+        w.write_bytes(&self.io2);
+        w.write_bytes(&self.high_ram);
+        w.write_u8(self.audio_disabled);
+        self.div.save_state(w);
+        self.bg_palettes.save_state(w);
+        self.obj_palettes.save_state(w);
+        self.hdma.save_state(w);
+        w.write_u8(self.key1);
+        w.write_bool(self.double_speed);
+        w.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.bank_ctrl.load_state(r)?;
+        r.read_into(&mut self.tile_ram)?;
+        r.read_into(&mut self.background_map)?;
+        r.read_into(&mut self.vram1)?;
+        r.read_into(&mut self.cartridge_ram)?;
+        r.read_into(&mut self.wram)?;
+        r.read_into(&mut self.sprite)?;
+        r.read_into(&mut self.io1)?;
+        self.audio.load_state(r)?;
+        r.read_into(&mut self.wave_ram)?;
+        r.read_into(&mut self.io2)?;
+        r.read_into(&mut self.high_ram)?;
+        self.audio_disabled = r.read_u8()?;
+        self.div.load_state(r)?;
+        self.bg_palettes.load_state(r)?;
+        self.obj_palettes.load_state(r)?;
+        self.hdma.load_state(r)?;
+        self.key1 = r.read_u8()?;
+        self.double_speed = r.read_bool()?;
+        if r.read_bool()? != self.sgb.is_some() {
+            return Err("Save state was made with SGB mode set differently".to_string());
+        }
+        if let Some(sgb) = self.sgb.as_mut() {
+            sgb.load_state(r)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory for a cartridge of `cartridge_type` with `banks` 16 KiB ROM banks, each
+    /// filled with its bank number, with the boot ROM unmapped.
+    fn memory(cartridge_type: u8, banks: usize) -> Memory {
+        let mut rom: Vec<u8> = (0..banks).flat_map(|b| [b as u8; 0x4000]).collect();
+        rom[0x147] = cartridge_type;
+        rom[0x148] = (banks / 2).trailing_zeros() as u8;
+        let header = CartridgeHeader::read(&rom).unwrap();
+        let mut mem = Memory::new(Vec::new(), rom, header).unwrap();
+        mem[0xFF50] = 1;
+        mem
+    }
+
+    /// CGB-only memory, with writes applied the way the `Bus` does.
+    fn cgb_memory() -> Memory {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0xC0;
+        let header = CartridgeHeader::read(&rom).unwrap();
+        let mut mem = Memory::new(Vec::new(), rom, header).unwrap();
+        mem[0xFF50] = 1;
+        mem
+    }
+
+    fn cgb_write(mem: &mut Memory, addr: u16, value: u8) {
+        mem[addr] = value;
+        mem.update_cgb_registers();
+    }
+
+    #[test]
+    fn key1_arms_the_speed_switch() {
+        let mut mem = cgb_memory();
+        cgb_write(&mut mem, 0xFF4D, 0x01);
+        assert!(mem.speed_switch_armed());
+        assert_eq!(mem[0xFF4D], 0x7F);
+        mem.switch_speed();
+        assert!(mem.double_speed());
+        assert_eq!(mem[0xFF4D], 0xFE);
+    }
+
+    #[test]
+    fn cgb_vram_and_wram_are_banked() {
+        let mut mem = cgb_memory();
+        cgb_write(&mut mem, 0xFF4F, 0x01);
+        cgb_write(&mut mem, 0x8000, 0x11);
+        cgb_write(&mut mem, 0xFF4F, 0x00);
+        cgb_write(&mut mem, 0x8000, 0x22);
+        assert_eq!([mem.vram(0, 0x8000), mem.vram(1, 0x8000)], [0x22, 0x11]);
+
+        cgb_write(&mut mem, 0xFF70, 0x02);
+        cgb_write(&mut mem, 0xD000, 0x33);
+        // Bank 0 selects bank 1.
+        cgb_write(&mut mem, 0xFF70, 0x00);
+        assert_eq!(mem[0xD000], 0x00);
+        cgb_write(&mut mem, 0xFF70, 0x02);
+        assert_eq!(mem[0xD000], 0x33);
+    }
+
+    #[test]
+    fn general_purpose_dma_copies_to_vram() {
+        let mut mem = cgb_memory();
+        for i in 0..0x20 {
+            mem[0xC000 + i] = i as u8;
+        }
+        for (addr, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x80),
+            (0xFF54, 0x10),
+        ] {
+            cgb_write(&mut mem, addr, value);
+        }
+        cgb_write(&mut mem, 0xFF55, 0x01);
+        for i in 0..0x20 {
+            assert_eq!(mem[0x8010 + i], i as u8);
+        }
+        assert_eq!(mem[0xFF55], 0xFF);
+    }
+
+    #[test]
+    fn echo_ram_writes_reach_work_ram() {
+        let mut mem = memory(0x00, 2);
+        mem[0xE123] = 0x42;
+        assert_eq!(mem[0xC123], 0x42);
+        mem[0xD456] = 0x24;
+        assert_eq!(mem[0xF456], 0x24);
+    }
+
+    #[test]
+    fn mbc5_rom_bank_wraps_to_rom_size() {
+        let mut mem = memory(0x19, 4);
+        mem[0x2000] = 0x06;
+        mem[0x3000] = 0x01;
+        assert_eq!(mem[0x4000], 0x02);
+    }
+
+    #[test]
+    fn mbc3_rom_bank_wraps_to_rom_size() {
+        let mut mem = memory(0x11, 8);
+        mem[0x2000] = 0x7D;
+        assert_eq!(mem[0x4000], 0x05);
+    }
+
+    #[test]
+    fn mbc1_upper_bits_wrap_to_rom_size() {
+        // A 1 MiB cartridge has 64 banks, upper bits 3 select banks 0x60-0x7F.
+        let mut mem = memory(0x01, 64);
+        mem[0x4000] = 0x03;
+        mem[0x6000] = 0x01;
+        assert_eq!(mem[0x0000], 0x20);
+        assert_eq!(mem[0x4000], 0x21);
+    }
+
+    #[test]
+    fn rtc_seconds_carry_into_minutes_hours_and_days() {
+        let mut rtc = RealTimeClock::new();
+        rtc.live = [59, 59, 23, 0x41, 0];
+        rtc.tick(RTC_DOTS_PER_SECOND - 1);
+        assert_eq!(rtc.live, [59, 59, 23, 0x41, 0]);
+        rtc.tick(1);
+        assert_eq!(rtc.live, [0, 0, 0, 0x42, 0]);
+    }
+
+    #[test]
+    fn rtc_halt_stops_the_clock() {
+        let mut rtc = RealTimeClock::new();
+        rtc.live = [10, 0, 0, 0, RTC_DH_HALT];
+        rtc.tick(RTC_DOTS_PER_SECOND * 3);
+        assert_eq!(rtc.live, [10, 0, 0, 0, RTC_DH_HALT]);
+        rtc.live[4] = 0;
+        rtc.tick(RTC_DOTS_PER_SECOND);
+        assert_eq!(rtc.live[0], 11);
+    }
+
+    #[test]
+    fn rtc_day_overflow_sets_carry() {
+        let mut rtc = RealTimeClock::new();
+        rtc.live = [59, 59, 23, 0xFF, RTC_DH_DAY_HI];
+        rtc.tick(RTC_DOTS_PER_SECOND);
+        assert_eq!(rtc.live, [0, 0, 0, 0, RTC_DH_DAY_CARRY]);
+        assert_eq!(rtc.day(), 0);
+    }
+
+    #[test]
+    fn rtc_catch_up_matches_ticking_one_second_at_a_time() {
+        let starts = [
+            [0, 0, 0, 0, 0],
+            [59, 59, 23, 0xFF, RTC_DH_DAY_HI],
+            [62, 61, 30, 0x10, 0],
+            [13, 45, 7, 0x80, RTC_DH_DAY_HI],
+        ];
+        let seconds = 3 * 86_400 + 3_725;
+        for start in starts {
+            let mut stepped = RealTimeClock::new();
+            stepped.live = start;
+            for _ in 0..seconds {
+                stepped.advance_seconds(1);
+            }
+            let mut caught_up = RealTimeClock::new();
+            caught_up.live = start;
+            caught_up.advance_seconds(seconds);
+            assert_eq!(caught_up.live, stepped.live, "from {:?}", start);
+        }
+    }
+
+    #[test]
+    fn rtc_catch_up_over_years_overflows_days() {
+        let mut rtc = RealTimeClock::new();
+        rtc.advance_seconds(600 * 86_400 + 61);
+        assert_eq!(rtc.live, [1, 1, 0, 88, RTC_DH_DAY_CARRY]);
+    }
+
+    #[test]
+    fn mbc2_ram_reads_only_the_lower_bits() {
+        let mut mem = memory(0x06, 2);
+        mem[0x0000] = 0x0A;
+        assert_eq!(mem[0xA000], 0xF0);
+        mem[0xA001] = 0x5A;
+        // No cycle has to pass for the upper bits to read as 1s.
+        assert_eq!(mem[0xA001], 0xFA);
+        // The 512 half-bytes repeat.
+        assert_eq!(mem[0xA201], 0xFA);
+    }
+
+    #[test]
+    fn rtc_reads_latched_copy() {
+        let mut mem = memory(0x10, 4);
+        mem[0x0000] = 0x0A;
+        mem[0x4000] = 0x08;
+        mem[0xA000] = 30;
+        assert_eq!(mem[0xA000], 0);
+        mem[0x6000] = 0x00;
+        mem.tick_cartridge(4);
+        mem[0x6000] = 0x01;
+        mem.tick_cartridge(4);
+        assert_eq!(mem[0xA000], 30);
+        // Only a 0 -> 1 transition latches.
+        mem[0xA000] = 45;
+        mem[0x6000] = 0x01;
+        mem.tick_cartridge(4);
+        assert_eq!(mem[0xA000], 30);
+    }
+
+    #[test]
+    fn rtc_latch_masks_registers() {
+        let mut mem = memory(0x10, 4);
+        mem[0x0000] = 0x0A;
+        for (select, value) in (0x08..=0x0C).zip([0xFF, 0xFF, 0xFF, 0xFF, 0x3F]) {
+            mem[0x4000] = select;
+            mem[0xA000] = value;
+        }
+        mem[0x6000] = 0x00;
+        mem.tick_cartridge(4);
+        mem[0x6000] = 0x01;
+        mem.tick_cartridge(4);
+        let latched = (0x08..=0x0C).map(|select| {
+            mem[0x4000] = select;
+            mem[0xA000]
+        });
+        assert_eq!(latched.collect::<Vec<_>>(), [0x3F, 0x3F, 0x1F, 0xFF, 0x01]);
+    }
+
+    #[test]
+    fn rtc_seconds_write_restarts_the_second() {
+        let mut mem = memory(0x10, 4);
+        mem[0x0000] = 0x0A;
+        mem[0x4000] = 0x08;
+        mem.tick_cartridge(RTC_DOTS_PER_SECOND - 4);
+        mem[0xA000] = 10;
+        mem.tick_cartridge(RTC_DOTS_PER_SECOND - 4);
+        assert_eq!(mem.rtc().unwrap().live[0], 10);
+        mem.tick_cartridge(4);
+        assert_eq!(mem.rtc().unwrap().live[0], 11);
+    }
+}
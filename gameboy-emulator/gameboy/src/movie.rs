@@ -0,0 +1,373 @@
+use crate::gameboy::GameBoy;
+use crate::host::{AudioSink, InputSource, VideoSink};
+use crate::joypad::JoyPad;
+use std::fs;
+use std::path::Path;
+
+const MOVIE_MAGIC: &[u8; 4] = b"GBMV";
+const MOVIE_VERSION: u16 = 1;
+/// Frames between framebuffer hashes stored in a movie.
+pub const CHECKPOINT_INTERVAL: u32 = 60;
+
+/// 64-bit FNV-1a hash, used for ROM checksums and framebuffer hashes.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn frame_hash(frame: &[u32]) -> u64 {
+    let bytes: Vec<u8> = frame.iter().flat_map(|px| px.to_le_bytes()).collect();
+    fnv1a(&bytes)
+}
+
+/// Where a movie starts. Battery-backed RAM is part of the start of power-on movies, as
+/// the save file on disk could have changed since recording. The RTC is not, so games
+/// that read the clock only replay deterministically from a save state.
+pub enum MovieStart {
+    PowerOn { cartridge_ram: Vec<u8> },
+    SaveState(Vec<u8>),
+}
+
+/// The buttons held in every frame of a recording, with framebuffer hashes every
+/// `CHECKPOINT_INTERVAL` frames to verify replays against.
+pub struct Movie {
+    pub rom_checksum: u64,
+    pub start: MovieStart,
+    /// Button bit masks as returned by `JoyPad::buttons`, one per frame.
+    pub frames: Vec<u8>,
+    /// Frame index and hash of the framebuffer after that frame.
+    pub checkpoints: Vec<(u32, u64)>,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MOVIE_MAGIC);
+        buf.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.rom_checksum.to_le_bytes());
+        let (kind, data) = match &self.start {
+            MovieStart::PowerOn { cartridge_ram } => (0u8, cartridge_ram),
+            MovieStart::SaveState(state) => (1u8, state),
+        };
+        buf.push(kind);
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.frames);
+        buf.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        for (frame, hash) in &self.checkpoints {
+            buf.extend_from_slice(&frame.to_le_bytes());
+            buf.extend_from_slice(&hash.to_le_bytes());
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, String> {
+        let mut r = MovieReader { buf, pos: 0 };
+        if r.take(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            return Err("Not a movie file".to_string());
+        }
+        let version = u16::from_le_bytes(r.array()?);
+        if version != MOVIE_VERSION {
+            return Err(format!(
+                "Movie version {version} is not supported (expected {MOVIE_VERSION})"
+            ));
+        }
+        let rom_checksum = u64::from_le_bytes(r.array()?);
+        let [kind] = r.array()?;
+        let len = r.u32()? as usize;
+        let data = r.take(len)?.to_vec();
+        let start = match kind {
+            0 => MovieStart::PowerOn {
+                cartridge_ram: data,
+            },
+            1 => MovieStart::SaveState(data),
+            _ => return Err(format!("Unknown movie start {kind}")),
+        };
+        let frame_count = r.u32()? as usize;
+        let frames = r.take(frame_count)?.to_vec();
+        let mut checkpoints = Vec::new();
+        for _ in 0..r.u32()? {
+            checkpoints.push((r.u32()?, u64::from_le_bytes(r.array()?)));
+        }
+        Ok(Self {
+            rom_checksum,
+            start,
+            frames,
+            checkpoints,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_bytes(&fs::read(path).map_err(|e| e.to_string())?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| e.to_string())
+    }
+}
+
+struct MovieReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MovieReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or("Movie is truncated")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+/// Polls another input source and records what it returned, one entry per frame.
+struct RecordingInput<'a> {
+    input: &'a mut dyn InputSource,
+    buttons: Option<u8>,
+}
+
+impl InputSource for RecordingInput<'_> {
+    fn poll(&mut self, joy_pad: &mut JoyPad) {
+        self.input.poll(joy_pad);
+        self.buttons = Some(joy_pad.buttons());
+    }
+}
+
+/// Records the input of every frame run through it.
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Starts recording from power-on. Call this before running the first frame.
+    pub fn from_power_on(gb: &GameBoy) -> Self {
+        let cartridge_ram = gb.mem.cartridge_ram().to_vec();
+        Self::new(gb, MovieStart::PowerOn { cartridge_ram })
+    }
+
+    /// Starts recording from the current state, which is stored in the movie.
+    pub fn from_current_state(gb: &GameBoy) -> Self {
+        Self::new(gb, MovieStart::SaveState(gb.save_state_bytes()))
+    }
+
+    fn new(gb: &GameBoy, start: MovieStart) -> Self {
+        Self {
+            movie: Movie {
+                rom_checksum: gb.rom_checksum(),
+                start,
+                frames: Vec::new(),
+                checkpoints: Vec::new(),
+            },
+        }
+    }
+
+    /// Runs a frame with input from `input` and records it.
+    pub fn run_frame(
+        &mut self,
+        gb: &mut GameBoy,
+        video: &mut dyn VideoSink,
+        audio: &mut dyn AudioSink,
+        input: &mut dyn InputSource,
+    ) {
+        let mut recording = RecordingInput {
+            input,
+            buttons: None,
+        };
+        gb.run_frame(video, audio, &mut recording);
+        let frame = self.movie.frames.len() as u32;
+        self.movie.frames.push(recording.buttons.unwrap());
+        if (frame + 1).is_multiple_of(CHECKPOINT_INTERVAL) {
+            let hash = frame_hash(gb.ppu.frame_buffer());
+            self.movie.checkpoints.push((frame, hash));
+        }
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+struct ReplayInput(u8);
+
+impl InputSource for ReplayInput {
+    fn poll(&mut self, joy_pad: &mut JoyPad) {
+        joy_pad.set_buttons(self.0);
+    }
+}
+
+/// Replays a movie, feeding the recorded buttons to the emulator in place of live input
+/// and checking the framebuffer at every checkpoint.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+    next_checkpoint: usize,
+}
+
+impl MoviePlayer {
+    /// Checks that the movie was recorded with the loaded ROM and puts `gb` into the
+    /// starting state. A power-on movie needs a freshly created `gb`, set up the same way
+    /// as when it was recorded.
+    pub fn start(movie: Movie, gb: &mut GameBoy) -> Result<Self, String> {
+        if movie.rom_checksum != gb.rom_checksum() {
+            return Err("Movie was recorded with a different ROM".to_string());
+        }
+        match &movie.start {
+            MovieStart::PowerOn { cartridge_ram } => {
+                let ram = gb.mem.cartridge_ram_mut();
+                if ram.len() != cartridge_ram.len() {
+                    return Err("Movie has a different cartridge RAM size".to_string());
+                }
+                ram.copy_from_slice(cartridge_ram);
+            }
+            MovieStart::SaveState(state) => gb.load_state_bytes(state)?,
+        }
+        Ok(Self {
+            movie,
+            frame: 0,
+            next_checkpoint: 0,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    /// Runs the next frame of the movie. Fails if its framebuffer doesn't match the one
+    /// that was recorded, which means emulation diverged from the recording.
+    pub fn run_frame(
+        &mut self,
+        gb: &mut GameBoy,
+        video: &mut dyn VideoSink,
+        audio: &mut dyn AudioSink,
+    ) -> Result<(), String> {
+        let Some(buttons) = self.movie.frames.get(self.frame) else {
+            return Err("Movie has ended".to_string());
+        };
+        gb.run_frame(video, audio, &mut ReplayInput(*buttons));
+        let frame = self.frame as u32;
+        self.frame += 1;
+        match self.movie.checkpoints.get(self.next_checkpoint) {
+            Some((checkpoint, hash)) if *checkpoint == frame => {
+                self.next_checkpoint += 1;
+                if frame_hash(gb.ppu.frame_buffer()) != *hash {
+                    return Err(format!("Replay diverged from the movie at frame {frame}"));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::NullHost;
+    use crate::test_rom::test_gameboy;
+
+    /// Copies the action buttons to BGP in a loop, so the buttons held show on screen.
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 14] = [
+        0x3E, 0x91, // LD A, $91
+        0xE0, 0x40, // LDH ($40), A     ; LCD on
+        0x3E, 0x10, // loop: LD A, $10
+        0xE0, 0x00, // LDH ($00), A     ; select the action buttons
+        0xF0, 0x00, // LDH A, ($00)
+        0xE0, 0x47, // LDH ($47), A
+        0x18, 0xF6, // JR loop
+    ];
+
+    /// Presses A and B in a pattern that changes every few frames.
+    struct ScriptedInput(u32);
+
+    impl InputSource for ScriptedInput {
+        fn poll(&mut self, joy_pad: &mut JoyPad) {
+            joy_pad.set_buttons((self.0 / 7 % 4) as u8);
+            self.0 += 1;
+        }
+    }
+
+    fn record(frames: u32) -> Movie {
+        let mut gb = test_gameboy(&PROGRAM);
+        let mut recorder = MovieRecorder::from_power_on(&gb);
+        let mut input = ScriptedInput(0);
+        for _ in 0..frames {
+            recorder.run_frame(&mut gb, &mut NullHost, &mut NullHost, &mut input);
+        }
+        recorder.finish()
+    }
+
+    fn replay(movie: Movie) -> Result<(), String> {
+        let mut gb = test_gameboy(&PROGRAM);
+        let mut player = MoviePlayer::start(movie, &mut gb)?;
+        while !player.is_finished() {
+            player.run_frame(&mut gb, &mut NullHost, &mut NullHost)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn movie_bytes_round_trip() {
+        let movie = Movie {
+            rom_checksum: 0x0123_4567_89AB_CDEF,
+            start: MovieStart::SaveState(vec![1, 2, 3]),
+            frames: vec![0x00, 0x81, 0xFF],
+            checkpoints: vec![(59, 0xDEAD_BEEF)],
+        };
+        let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(loaded.rom_checksum, movie.rom_checksum);
+        assert!(matches!(loaded.start, MovieStart::SaveState(ref s) if s == &[1, 2, 3]));
+        assert_eq!(loaded.frames, movie.frames);
+        assert_eq!(loaded.checkpoints, movie.checkpoints);
+    }
+
+    #[test]
+    fn bad_movie_files_are_rejected() {
+        let bytes = record(3).to_bytes();
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]).err().unwrap(),
+            "Movie is truncated"
+        );
+        assert_eq!(
+            Movie::from_bytes(b"GBSS").err().unwrap(),
+            "Not a movie file"
+        );
+        let mut newer = bytes.clone();
+        newer[4] = MOVIE_VERSION as u8 + 1;
+        assert!(Movie::from_bytes(&newer).is_err());
+    }
+
+    #[test]
+    fn recording_replays_without_diverging() {
+        let movie = record(3 * CHECKPOINT_INTERVAL);
+        assert_eq!(movie.checkpoints.len(), 3);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(replay(movie), Ok(()));
+    }
+
+    #[test]
+    fn replay_with_other_input_diverges() {
+        let mut movie = record(CHECKPOINT_INTERVAL);
+        let last = movie.frames.len() - 1;
+        movie.frames[last] ^= 0x01;
+        assert_eq!(
+            replay(movie),
+            Err(format!(
+                "Replay diverged from the movie at frame {}",
+                CHECKPOINT_INTERVAL - 1
+            ))
+        );
+    }
+}
@@ -0,0 +1,954 @@
+use crate::cgb::rgb555_to_rgb888;
+use crate::host::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::memory::Memory;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use log::debug;
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+const WIDTH: usize = SCREEN_WIDTH;
+const HEIGHT: usize = SCREEN_HEIGHT;
+const TILE_X: u8 = 8;
+const TILE_Y: u8 = 8;
+const TILE_TABLE_SIZE: u16 = 32;
+const TILE_SIZE_BYTES: u16 = 16;
+
+const OAM_SCAN_DOTS: u16 = 80;
+const VBLANK_SL: u8 = 144;
+const TOTAL_DOTS: u16 = 456;
+const TOTAL_SL: u8 = 154;
+const OAM_DMA_LENGTH: u16 = 160;
+const SPRITE_BYTES: u16 = 4;
+const SL_SPRITE_CAPACITY: usize = 10;
+/// Dots before the fetcher starts at the beginning of mode 3. The hardware fetches the
+/// first tile twice, which together with the 160 pixels makes mode 3 at least 172 dots.
+const MODE_3_STARTUP_DOTS: u8 = 6;
+/// Dots the BG fetcher is paused for while a sprite's tile data is fetched.
+const SPRITE_FETCH_DOTS: u8 = 6;
+/// Dots lost when the window starts before the fetcher has done any work on the line.
+const WINDOW_START_DOTS: u8 = 6;
+
+const MODE_HBLANK: u8 = 0;
+const MODE_VBLANK: u8 = 1;
+const MODE_OAM_SCAN: u8 = 2;
+const MODE_DRAWING: u8 = 3;
+
+const STAT_HBLANK_INT: u8 = 1 << 3;
+const STAT_VBLANK_INT: u8 = 1 << 4;
+const STAT_OAM_SCAN_INT: u8 = 1 << 5;
+const STAT_LYC_INT: u8 = 1 << 6;
+
+// BG map attributes (CGB only) and sprite attributes share their bit layout.
+const ATTR_PALETTE: u8 = 0b0000_0111;
+const ATTR_VRAM_BANK: u8 = 1 << 3;
+const ATTR_DMG_PALETTE: u8 = 1 << 4;
+const ATTR_X_FLIP: u8 = 1 << 5;
+const ATTR_Y_FLIP: u8 = 1 << 6;
+const ATTR_PRIORITY: u8 = 1 << 7;
+
+/// Colour index of a background or window pixel with its BG map attributes.
+#[derive(Clone, Copy, Default)]
+struct BgPixel {
+    color: u8,
+    attrs: u8,
+}
+
+#[derive(Clone, Copy, Default)]
+struct SpritePixel {
+    color: u8,
+    attrs: u8,
+    oam_index: u8,
+}
+
+/// An OAM entry selected for the current scanline.
+#[derive(Clone, Copy)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile: u8,
+    attrs: u8,
+    oam_index: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+/// Fetches the next 8 BG or window pixels. Every step but the push takes 2 dots; the push
+/// waits until the BG FIFO is empty.
+struct Fetcher {
+    step: FetcherStep,
+    /// Whether the first dot of the current step has passed.
+    step_started: bool,
+    /// Tile column, counted from the left edge of the BG (after SCX) or the window.
+    tile_x: u8,
+    window: bool,
+    tile_idx: u8,
+    attrs: u8,
+    lo: u8,
+    hi: u8,
+}
+
+impl Fetcher {
+    fn new(window: bool) -> Self {
+        Self {
+            step: FetcherStep::Tile,
+            step_started: false,
+            tile_x: 0,
+            window,
+            tile_idx: 0,
+            attrs: 0,
+            lo: 0,
+            hi: 0,
+        }
+    }
+}
+
+/// Colours the four DMG shades are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmgPalette {
+    Grey,
+    /// The green tint of the original DMG screen.
+    Green,
+    /// The Game Boy Pocket's screen.
+    Pocket,
+}
+
+impl DmgPalette {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grey" | "gray" => Some(DmgPalette::Grey),
+            "green" => Some(DmgPalette::Green),
+            "pocket" => Some(DmgPalette::Pocket),
+            _ => None,
+        }
+    }
+
+    /// The shades from lightest to darkest, as BGR555.
+    pub fn colors(self) -> [u16; 4] {
+        const GREY: [u16; 4] = bgr555_shades([0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000]);
+        const GREEN: [u16; 4] = bgr555_shades([0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F]);
+        const POCKET: [u16; 4] = bgr555_shades([0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F]);
+        match self {
+            DmgPalette::Grey => GREY,
+            DmgPalette::Green => GREEN,
+            DmgPalette::Pocket => POCKET,
+        }
+    }
+}
+
+/// Converts `0xRRGGBB` colours to BGR555. Evaluated at compile time, as the PPU looks up
+/// a shade for every pixel.
+const fn bgr555_shades(rgb: [u32; 4]) -> [u16; 4] {
+    let mut shades = [0; 4];
+    let mut i = 0;
+    while i < 4 {
+        let [_, r, g, b] = rgb[i].to_be_bytes();
+        shades[i] = (b as u16 >> 3) << 10 | (g as u16 >> 3) << 5 | (r as u16 >> 3);
+        i += 1;
+    }
+    shades
+}
+
+struct LCDC {
+    lcd_ppu_enable: bool,
+    window_tile_map: bool,
+    window_enable: bool,
+    bg_window_tile_data_area: bool,
+    bg_tile_map: bool,
+    obj_size: bool,
+    obj_enable: bool,
+    bg_window_enable: bool,
+}
+
+impl LCDC {
+    pub fn load(mem: &Memory) -> Self {
+        let lcdc = mem.lcd_reg(0xFF40);
+        Self {
+            lcd_ppu_enable: lcdc & (1 << 7) != 0,
+            window_tile_map: lcdc & (1 << 6) != 0,
+            window_enable: lcdc & (1 << 5) != 0,
+            bg_window_tile_data_area: lcdc & (1 << 4) != 0,
+            bg_tile_map: lcdc & (1 << 3) != 0,
+            obj_size: lcdc & (1 << 2) != 0,
+            obj_enable: lcdc & (1 << 1) != 0,
+            bg_window_enable: lcdc & (1 << 0) != 0,
+        }
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.obj_size {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Address of the first byte of a BG or window tile.
+    fn tile_data_addr(&self, tile_idx: u8) -> u16 {
+        if self.bg_window_tile_data_area {
+            // 8000–8FFF unsigned
+            0x8000 + tile_idx as u16 * TILE_SIZE_BYTES
+        } else {
+            // 8800–97FF signed
+            0x9000u16.wrapping_add_signed((tile_idx as i8) as i16 * 16)
+        }
+    }
+}
+
+/// Pixel FIFO PPU. Mode 3 runs a BG/window fetcher that feeds a BG FIFO, from which one
+/// pixel is shifted out per dot and mixed with the sprite FIFO. Registers are sampled
+/// when the fetcher or the mixer uses them, so mid-scanline writes take effect like on
+/// hardware, and mode 3 gets longer with SCX, the window and sprites.
+pub struct PPU {
+    sl: u8,
+    dot: u16,
+    mode: u8,
+    /// X coordinate of the next pixel to be shifted out.
+    lx: u8,
+    buffer: [u32; WIDTH * HEIGHT],
+    oam_dma_start: u16,
+    oam_dma_ctr: u16,
+    sl_sprites: Vec<Sprite>,
+    /// One bit per entry of `sl_sprites` that has already been fetched.
+    fetched_sprites: u16,
+    bg_fifo: VecDeque<BgPixel>,
+    sprite_fifo: VecDeque<SpritePixel>,
+    fetcher: Fetcher,
+    /// Dots during which no pixel is shifted out: the mode 3 startup and sprite fetches.
+    stall: u8,
+    /// BG pixels still to be dropped, for SCX % 8 at the start of the line or the part of
+    /// the window left of the screen when WX < 7.
+    discard: u8,
+    /// One bit per BG tile column that already caused a sprite fetch penalty.
+    penalized_tiles: u32,
+    /// Set once LY matched WY this frame, which the window needs to be shown at all.
+    wy_triggered: bool,
+    /// The window's own line counter, which only advances on lines that show the window.
+    window_line: u8,
+    window_drawn: bool,
+    frame_start_time: SystemTime,
+    stat_line: bool,
+    frame_completed: bool,
+    /// Dots the PPU is behind the rest of the machine. It only catches up when the CPU is
+    /// about to access something it uses or changes, and before it can raise an interrupt,
+    /// start an HBlank DMA or complete a frame, so nobody can tell it ran late.
+    pending_dots: u32,
+    /// Pending dots after which the next of those events is due.
+    dots_to_event: u32,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self {
+            sl: 0,
+            dot: 0,
+            mode: MODE_OAM_SCAN,
+            lx: 0,
+            buffer: [0; WIDTH * HEIGHT],
+            oam_dma_start: 0xFF,
+            oam_dma_ctr: OAM_DMA_LENGTH,
+            sl_sprites: Vec::with_capacity(SL_SPRITE_CAPACITY),
+            fetched_sprites: 0,
+            bg_fifo: VecDeque::with_capacity(8),
+            sprite_fifo: VecDeque::with_capacity(8),
+            fetcher: Fetcher::new(false),
+            stall: 0,
+            discard: 0,
+            penalized_tiles: 0,
+            wy_triggered: false,
+            window_line: 0,
+            window_drawn: false,
+            frame_start_time: SystemTime::now(),
+            stat_line: false,
+            frame_completed: false,
+            pending_dots: 0,
+            dots_to_event: 0,
+        }
+    }
+
+    /// Whether the CPU accessing `addr` has to let the PPU catch up first: VRAM, OAM and
+    /// the registers and palettes it reads while drawing or updates. P1 is included for
+    /// the SGB commands sent through it, which change the colours.
+    pub fn is_ppu_address(addr: u16) -> bool {
+        matches!(
+            addr,
+            0x8000..=0x9FFF
+                | 0xFE00..=0xFE9F
+                | 0xFF00
+                | 0xFF40..=0xFF4B
+                | 0xFF51..=0xFF55
+                | 0xFF68..=0xFF6B
+        )
+    }
+
+    /// Lets `dots` pass, running them right away only if an event is due.
+    pub fn advance(&mut self, mem: &mut Memory, dots: u32) {
+        self.pending_dots += dots;
+        if self.pending_dots >= self.dots_to_event {
+            self.catch_up(mem);
+        }
+    }
+
+    /// Runs the pending dots. Stretches of HBlank, VBlank and OAM scan are skipped in one
+    /// go, as nothing changes during them but the OAM scan and the STAT line.
+    pub fn catch_up(&mut self, mem: &mut Memory) {
+        while self.pending_dots > 0 {
+            let lcdc = LCDC::load(mem);
+            if !lcdc.lcd_ppu_enable {
+                // Nothing moves while the LCD is off.
+                self.run_dot(mem);
+                self.pending_dots = 0;
+                break;
+            }
+            let idle = self.idle_dots().min(self.pending_dots);
+            if idle > 1 {
+                self.skip_idle_dots(mem, &lcdc, idle);
+                self.pending_dots -= idle;
+            } else if self.mode == MODE_DRAWING
+                && self.dot > OAM_SCAN_DOTS
+                && (self.lx as usize) < WIDTH
+            {
+                self.pending_dots -= self.draw_dots(mem, &lcdc, self.pending_dots);
+            } else {
+                self.run_dot(mem);
+                self.pending_dots -= 1;
+            }
+        }
+        self.dots_to_event = self.dots_to_event(mem);
+    }
+
+    /// Makes the next `advance` catch up, after a register write that can change when the
+    /// next event is due or raise the STAT interrupt right away.
+    pub fn reschedule(&mut self) {
+        self.dots_to_event = 0;
+    }
+
+    /// Dots until the next one in which the PPU may raise an interrupt, start an HBlank DMA
+    /// or complete a frame. The end of mode 3 isn't known in advance, but at most one pixel
+    /// is shifted out per dot.
+    fn dots_to_event(&self, mem: &Memory) -> u32 {
+        if !LCDC::load(mem).lcd_ppu_enable {
+            return u32::MAX;
+        }
+        let line_start = if self.dot == 0 {
+            1
+        } else {
+            (TOTAL_DOTS - self.dot) as u32 + 1
+        };
+        if self.sl >= VBLANK_SL {
+            return line_start;
+        }
+        let hblank = match self.mode {
+            MODE_DRAWING => (WIDTH - self.lx as usize) as u32 + 1,
+            _ if self.dot <= OAM_SCAN_DOTS => (OAM_SCAN_DOTS - self.dot) as u32 + 1 + WIDTH as u32,
+            _ => u32::MAX,
+        };
+        line_start.min(hblank)
+    }
+
+    /// Dots from the current one on in which the PPU does nothing but the OAM scan and
+    /// updating STAT. The dots that start a line, mode 3 or the next line are excluded.
+    fn idle_dots(&self) -> u32 {
+        if self.dot == 0 || self.mode == MODE_DRAWING {
+            0
+        } else if self.mode == MODE_OAM_SCAN {
+            (OAM_SCAN_DOTS - self.dot) as u32
+        } else {
+            (TOTAL_DOTS - 1 - self.dot) as u32
+        }
+    }
+
+    /// Does what `dots` calls of `run_dot` during `idle_dots` would do.
+    fn skip_idle_dots(&mut self, mem: &mut Memory, lcdc: &LCDC, dots: u32) {
+        let end = self.dot + dots as u16;
+        *mem.lcd_reg_mut(0xFF44) = self.sl;
+        if self.mode == MODE_OAM_SCAN {
+            for dot in (self.dot..end).filter(|dot| dot.is_multiple_of(2)) {
+                self.scan_oam(mem, lcdc, dot / 2);
+            }
+        }
+        self.update_stat(mem);
+        self.dot = end;
+    }
+
+    /// Does what up to `dots` calls of `run_dot` in mode 3 would do, stopping once the
+    /// line is drawn. Returns the dots run. The registers can't change in between, so
+    /// LY and STAT only need updating once.
+    fn draw_dots(&mut self, mem: &mut Memory, lcdc: &LCDC, dots: u32) -> u32 {
+        *mem.lcd_reg_mut(0xFF44) = self.sl;
+        self.update_stat(mem);
+        let mut drawn = 0;
+        while drawn < dots && (self.lx as usize) < WIDTH {
+            self.draw_dot(mem, lcdc);
+            drawn += 1;
+        }
+        self.dot += drawn as u16;
+        drawn
+    }
+
+    fn run_dot(&mut self, mem: &mut Memory) {
+        let lcdc = LCDC::load(mem);
+
+        if !lcdc.lcd_ppu_enable {
+            // LY stays 0 while the LCD is off, and the next frame starts from the top once
+            // it is switched back on.
+            self.sl = 0;
+            self.dot = 0;
+            self.mode = MODE_HBLANK;
+            self.stat_line = false;
+            self.wy_triggered = false;
+            self.window_line = 0;
+            *mem.lcd_reg_mut(0xFF44) = 0;
+            *mem.lcd_reg_mut(0xFF41) &= 0b1111_1100;
+            return;
+        }
+
+        *mem.lcd_reg_mut(0xFF44) = self.sl;
+        if self.sl < VBLANK_SL {
+            if self.dot == 0 {
+                self.mode = MODE_OAM_SCAN;
+                self.sl_sprites.clear();
+                if lcdc.window_enable && mem.lcd_reg(0xFF4A) == self.sl {
+                    self.wy_triggered = true;
+                }
+            } else if self.dot == OAM_SCAN_DOTS {
+                self.start_drawing(mem);
+            }
+            match self.mode {
+                // 40 sprites to check, 80 dots.
+                MODE_OAM_SCAN if self.dot.is_multiple_of(2) => {
+                    self.scan_oam(mem, &lcdc, self.dot / 2);
+                }
+                MODE_DRAWING => {
+                    if (self.lx as usize) < WIDTH {
+                        self.draw_dot(mem, &lcdc);
+                    } else {
+                        self.mode = MODE_HBLANK;
+                        if self.window_drawn {
+                            self.window_line += 1;
+                        }
+                        mem.hblank();
+                    }
+                }
+                _ => {}
+            }
+        } else if self.sl == VBLANK_SL && self.dot == 0 {
+            self.mode = MODE_VBLANK;
+            mem.request_interrupt(0b0000_0001);
+            mem.apply_cheat_writes();
+            mem.sgb_vblank(&self.buffer);
+            self.frame_completed = true;
+        }
+
+        self.update_stat(mem);
+
+        self.dot += 1;
+        if self.dot >= TOTAL_DOTS {
+            self.dot = 0;
+            self.sl += 1;
+            if self.sl >= TOTAL_SL {
+                self.sl = 0;
+                self.wy_triggered = false;
+                self.window_line = 0;
+                let now = SystemTime::now();
+                debug!(
+                    "FPS {:?}",
+                    1.0 / now
+                        .duration_since(self.frame_start_time)
+                        .unwrap()
+                        .as_secs_f32()
+                );
+                self.frame_start_time = now;
+            }
+        }
+    }
+
+    fn update_stat(&mut self, mem: &mut Memory) {
+        let stat = mem.lcd_reg(0xFF41);
+        let lyc_eq = mem.lcd_reg(0xFF45) == self.sl;
+        let mode_int = match self.mode {
+            MODE_HBLANK => STAT_HBLANK_INT,
+            MODE_VBLANK => STAT_VBLANK_INT,
+            MODE_OAM_SCAN => STAT_OAM_SCAN_INT,
+            _ => 0,
+        };
+        let new_stat_line = (lyc_eq && stat & STAT_LYC_INT != 0) || stat & mode_int != 0;
+        *mem.lcd_reg_mut(0xFF41) = stat & 0b1111_1000 | (lyc_eq as u8) << 2 | self.mode;
+
+        // Trigger stat interrupt on rising edge
+        if !self.stat_line && new_stat_line {
+            mem.request_interrupt(1 << 1);
+        }
+        self.stat_line = new_stat_line;
+    }
+
+    fn scan_oam(&mut self, mem: &Memory, lcdc: &LCDC, index: u16) {
+        if self.sl_sprites.len() == SL_SPRITE_CAPACITY {
+            return;
+        }
+        let addr = 0xFE00 + index * SPRITE_BYTES;
+        let sprite_y = mem[addr] as u16;
+        let line = self.sl as u16 + 16;
+        if sprite_y <= line && line < sprite_y + lcdc.sprite_height() as u16 {
+            self.sl_sprites.push(Sprite {
+                y: mem[addr],
+                x: mem[addr + 1],
+                tile: mem[addr + 2],
+                attrs: mem[addr + 3],
+                oam_index: index as u8,
+            });
+        }
+    }
+
+    fn start_drawing(&mut self, mem: &Memory) {
+        self.mode = MODE_DRAWING;
+        self.lx = 0;
+        self.bg_fifo.clear();
+        self.sprite_fifo.clear();
+        self.fetcher = Fetcher::new(false);
+        self.stall = MODE_3_STARTUP_DOTS;
+        self.discard = mem.lcd_reg(0xFF43) % TILE_X;
+        self.fetched_sprites = 0;
+        self.penalized_tiles = 0;
+        self.window_drawn = false;
+    }
+
+    fn draw_dot(&mut self, mem: &Memory, lcdc: &LCDC) {
+        if self.stall > 0 {
+            self.stall -= 1;
+            return;
+        }
+
+        let wx = mem.lcd_reg(0xFF4B);
+        if !self.fetcher.window
+            && lcdc.window_enable
+            && self.wy_triggered
+            && self.lx as u16 + 7 >= wx as u16
+        {
+            // Switching to the window restarts the fetcher, which costs another tile fetch.
+            let fetcher_idle = self.fetcher.tile_x == 0
+                && self.fetcher.step == FetcherStep::Tile
+                && !self.fetcher.step_started;
+            self.fetcher = Fetcher::new(true);
+            self.bg_fifo.clear();
+            self.discard = 7u8.saturating_sub(wx);
+            self.window_drawn = true;
+            if fetcher_idle {
+                // The hardware has started fetching the BG by now even if it hasn't here.
+                self.stall = WINDOW_START_DOTS - 1;
+                return;
+            }
+        }
+
+        if lcdc.obj_enable {
+            if let Some(i) = self.next_sprite() {
+                self.fetched_sprites |= 1 << i;
+                self.load_sprite(mem, lcdc, i);
+                // This dot is the first of the fetch.
+                self.stall = SPRITE_FETCH_DOTS + self.sprite_penalty(mem) - 1;
+                return;
+            }
+        }
+
+        self.tick_fetcher(mem, lcdc);
+        let Some(bg) = self.bg_fifo.pop_front() else {
+            return;
+        };
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
+        }
+        let sprite = self.sprite_fifo.pop_front();
+        let color = Self::mix(mem, lcdc, bg, sprite, self.lx, self.sl);
+        self.set_pixel(self.lx, self.sl, color);
+        self.lx += 1;
+    }
+
+    /// The first sprite in OAM order that starts at the current pixel and was not fetched
+    /// yet. Sprites partially left of the screen start at pixel 0.
+    fn next_sprite(&self) -> Option<usize> {
+        (0..self.sl_sprites.len()).find(|&i| {
+            self.fetched_sprites & (1 << i) == 0
+                && self.sl_sprites[i].x.saturating_sub(8) == self.lx
+        })
+    }
+
+    /// Extra dots a sprite fetch waits for the BG fetcher to finish the tile the sprite
+    /// starts on. Only the first sprite on each tile pays it.
+    fn sprite_penalty(&mut self, mem: &Memory) -> u8 {
+        let pos = if self.fetcher.window {
+            (self.lx + 7).wrapping_sub(mem.lcd_reg(0xFF4B))
+        } else {
+            self.lx.wrapping_add(mem.lcd_reg(0xFF43))
+        };
+        let tile = 1 << (pos / TILE_X);
+        if self.penalized_tiles & tile != 0 {
+            return 0;
+        }
+        self.penalized_tiles |= tile;
+        5u8.saturating_sub(pos % TILE_X)
+    }
+
+    /// Merges the sprite's row into the sprite FIFO. Opaque pixels already in the FIFO
+    /// stay on DMG, where the earlier fetched sprite has the lower X; on CGB the sprite that
+    /// comes first in OAM wins.
+    fn load_sprite(&mut self, mem: &Memory, lcdc: &LCDC, i: usize) {
+        let sprite = self.sl_sprites[i];
+        let cgb = mem.is_cgb();
+        let height = lcdc.sprite_height();
+        let mut row = (self.sl + 16).wrapping_sub(sprite.y) % height;
+        if sprite.attrs & ATTR_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        let tile = if height == 16 {
+            sprite.tile & 0xFE
+        } else {
+            sprite.tile
+        };
+        let bank = (cgb && sprite.attrs & ATTR_VRAM_BANK != 0) as u8;
+        let addr = 0x8000 + tile as u16 * TILE_SIZE_BYTES + 2 * row as u16;
+        let lo = mem.vram(bank, addr);
+        let hi = mem.vram(bank, addr + 1);
+
+        while self.sprite_fifo.len() < TILE_X as usize {
+            self.sprite_fifo.push_back(SpritePixel::default());
+        }
+        let skip = 8u8.saturating_sub(sprite.x);
+        for px in skip..TILE_X {
+            let bit = if sprite.attrs & ATTR_X_FLIP != 0 {
+                px
+            } else {
+                7 - px
+            };
+            let color = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
+            let slot = &mut self.sprite_fifo[(px - skip) as usize];
+            if color != 0 && (slot.color == 0 || (cgb && sprite.oam_index < slot.oam_index)) {
+                *slot = SpritePixel {
+                    color,
+                    attrs: sprite.attrs,
+                    oam_index: sprite.oam_index,
+                };
+            }
+        }
+    }
+
+    fn tick_fetcher(&mut self, mem: &Memory, lcdc: &LCDC) {
+        if self.fetcher.step == FetcherStep::Push {
+            if self.bg_fifo.is_empty() {
+                let f = &mut self.fetcher;
+                for px in 0..TILE_X {
+                    let bit = if f.attrs & ATTR_X_FLIP != 0 {
+                        px
+                    } else {
+                        7 - px
+                    };
+                    self.bg_fifo.push_back(BgPixel {
+                        color: ((f.hi >> bit) & 1) << 1 | ((f.lo >> bit) & 1),
+                        attrs: f.attrs,
+                    });
+                }
+                f.tile_x = f.tile_x.wrapping_add(1);
+                f.step = FetcherStep::Tile;
+            }
+            return;
+        }
+        if !self.fetcher.step_started {
+            self.fetcher.step_started = true;
+            return;
+        }
+        self.fetcher.step_started = false;
+
+        let (map_x, y) = self.fetch_position(mem);
+        match self.fetcher.step {
+            FetcherStep::Tile => {
+                let tile_map = if self.fetcher.window {
+                    lcdc.window_tile_map
+                } else {
+                    lcdc.bg_tile_map
+                };
+                let map_addr = if tile_map { 0x9C00 } else { 0x9800 }
+                    + (map_x % TILE_TABLE_SIZE as u8) as u16
+                    + (y / TILE_Y) as u16 * TILE_TABLE_SIZE;
+                self.fetcher.tile_idx = mem.vram(0, map_addr);
+                // CGB keeps the attributes of each tile at the same address in VRAM bank 1.
+                self.fetcher.attrs = if mem.is_cgb() {
+                    mem.vram(1, map_addr)
+                } else {
+                    0
+                };
+                self.fetcher.step = FetcherStep::DataLow;
+            }
+            FetcherStep::DataLow => {
+                self.fetcher.lo = self.fetch_tile_data(mem, lcdc, y, 0);
+                self.fetcher.step = FetcherStep::DataHigh;
+            }
+            FetcherStep::DataHigh => {
+                self.fetcher.hi = self.fetch_tile_data(mem, lcdc, y, 1);
+                self.fetcher.step = FetcherStep::Push;
+            }
+            FetcherStep::Push => unreachable!(),
+        }
+    }
+
+    /// Tile map column and pixel row the fetcher is working on.
+    fn fetch_position(&self, mem: &Memory) -> (u8, u8) {
+        if self.fetcher.window {
+            (self.fetcher.tile_x, self.window_line)
+        } else {
+            let scx = mem.lcd_reg(0xFF43);
+            let scy = mem.lcd_reg(0xFF42);
+            (
+                (scx / TILE_X).wrapping_add(self.fetcher.tile_x),
+                self.sl.wrapping_add(scy),
+            )
+        }
+    }
+
+    fn fetch_tile_data(&self, mem: &Memory, lcdc: &LCDC, y: u8, byte: u16) -> u8 {
+        let mut row = y % TILE_Y;
+        if self.fetcher.attrs & ATTR_Y_FLIP != 0 {
+            row = TILE_Y - 1 - row;
+        }
+        let bank = (self.fetcher.attrs & ATTR_VRAM_BANK != 0) as u8;
+        let addr = lcdc.tile_data_addr(self.fetcher.tile_idx) + 2 * row as u16 + byte;
+        mem.vram(bank, addr)
+    }
+
+    /// Picks the BG or sprite pixel at `x`, `y` and applies its palette. Returns a BGR555
+    /// colour.
+    fn mix(
+        mem: &Memory,
+        lcdc: &LCDC,
+        bg: BgPixel,
+        sprite: Option<SpritePixel>,
+        x: u8,
+        y: u8,
+    ) -> u16 {
+        let cgb = mem.is_cgb();
+        // On DMG LCDC bit 0 blanks BG and window, on CGB it only takes away their priority.
+        let bg_color = if lcdc.bg_window_enable || cgb {
+            bg.color
+        } else {
+            0
+        };
+        if let Some(s) = sprite.filter(|s| s.color != 0 && lcdc.obj_enable) {
+            let bg_priority = bg_color != 0
+                && (!cgb || lcdc.bg_window_enable)
+                && (s.attrs & ATTR_PRIORITY != 0 || (cgb && bg.attrs & ATTR_PRIORITY != 0));
+            if !bg_priority {
+                return if cgb {
+                    mem.obj_palettes.color(s.attrs & ATTR_PALETTE, s.color)
+                } else {
+                    let palette = if s.attrs & ATTR_DMG_PALETTE != 0 {
+                        mem.lcd_reg(0xFF49)
+                    } else {
+                        mem.lcd_reg(0xFF48)
+                    };
+                    Self::dmg_pixel(mem, palette, s.color, x, y)
+                };
+            }
+        }
+        if cgb {
+            mem.bg_palettes.color(bg.attrs & ATTR_PALETTE, bg_color)
+        } else {
+            Self::dmg_pixel(mem, mem.lcd_reg(0xFF47), bg_color, x, y)
+        }
+    }
+
+    pub fn get_pixel_in_tile(
+        mem: &Memory,
+        bank: u8,
+        in_tile_x: u8,
+        in_tile_y: u8,
+        tile_start: u16,
+    ) -> u8 {
+        let row = tile_start + 2 * in_tile_y as u16;
+        let lo_channel = (mem.vram(bank, row) >> (7 - in_tile_x)) & 1;
+        let hi_channel = (mem.vram(bank, row + 1) >> (7 - in_tile_x)) & 1;
+        hi_channel << 1 | lo_channel
+    }
+
+    /// Advances OAM DMA by one M-cycle. A write to DMA is picked up on the next M-cycle and
+    /// the transfer copies one byte per M-cycle after that.
+    pub fn tick_oam_dma(&mut self, mem: &mut Memory) {
+        if self.oam_dma_ctr < OAM_DMA_LENGTH {
+            // The PPU must see OAM as it was before this byte is copied.
+            self.catch_up(mem);
+        }
+        let reg = mem.lcd_reg(0xFF46);
+        if reg <= 0xDF {
+            self.oam_dma_start = (reg as u16) << 8;
+            self.oam_dma_ctr = 0;
+            debug!("STARTED OAM DMA {:02x}", self.oam_dma_start);
+            *mem.lcd_reg_mut(0xFF46) = 0xFF;
+            return;
+        }
+        if self.oam_dma_ctr < OAM_DMA_LENGTH {
+            let src = self.oam_dma_start | self.oam_dma_ctr;
+            let dst = 0xFE00 | self.oam_dma_ctr;
+            mem[dst] = mem[src];
+            self.oam_dma_ctr += 1;
+            if self.oam_dma_ctr == OAM_DMA_LENGTH {
+                debug!("COMPLETED OAM DMA")
+            }
+        }
+    }
+
+    /// While OAM DMA runs the CPU can't reach OAM, and it shares the bus the transfer reads
+    /// from with the DMA, so it sees the byte being copied instead. Returns what a CPU read
+    /// of `addr` sees if it conflicts with the transfer; writes that conflict are lost.
+    pub fn oam_dma_conflict(&self, mem: &Memory, addr: u16) -> Option<u8> {
+        if self.oam_dma_ctr >= OAM_DMA_LENGTH {
+            return None;
+        }
+        let vram_bus = |addr: u16| (0x8000..=0x9FFF).contains(&addr);
+        let src = self.oam_dma_start | self.oam_dma_ctr;
+        match addr {
+            0xFE00..=0xFEFF => Some(0xFF),
+            0xFF00..=0xFFFF => None,
+            _ if vram_bus(addr) == vram_bus(src) => Some(mem[src]),
+            _ => None,
+        }
+    }
+
+    pub fn frame_buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+
+    /// Returns whether a frame was presented since the last call.
+    pub fn frame_completed(&mut self) -> bool {
+        std::mem::take(&mut self.frame_completed)
+    }
+
+    /// Applies a DMG palette (BGP, OBP0 or OBP1) to a colour index and returns the shade,
+    /// from the lightest for 0 to the darkest for 3, in the colours of `mem.dmg_palette`
+    /// as BGR555.
+    pub fn dmg_shade(mem: &Memory, palette: u8, color: u8) -> u16 {
+        let shade = (palette >> (color * 2)) & 0b11;
+        mem.dmg_palette.colors()[shade as usize]
+    }
+
+    /// Like `dmg_shade`, but in SGB mode the shade is coloured by the SGB palette of the
+    /// block of the screen at `x`, `y`.
+    fn dmg_pixel(mem: &Memory, palette: u8, color: u8, x: u8, y: u8) -> u16 {
+        match &mem.sgb {
+            Some(sgb) => sgb.color(x, y, (palette >> (color * 2)) & 0b11),
+            None => Self::dmg_shade(mem, palette, color),
+        }
+    }
+
+    /// Stores a BGR555 colour, as used by CGB palettes, in the frame buffer.
+    fn set_pixel(&mut self, x: u8, y: u8, color: u16) {
+        self.buffer[x as usize + y as usize * WIDTH] = rgb555_to_rgb888(color);
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for PPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.sl);
+        w.write_u16(self.dot);
+        w.write_u8(self.mode);
+        w.write_u8(self.lx);
+        for pixel in self.buffer {
+            w.write_u32(pixel);
+        }
+        w.write_u16(self.oam_dma_start);
+        w.write_u16(self.oam_dma_ctr);
+        w.write_u8(self.sl_sprites.len() as u8);
+        for s in &self.sl_sprites {
+            w.write_bytes(&[s.y, s.x, s.tile, s.attrs, s.oam_index]);
+        }
+        w.write_u16(self.fetched_sprites);
+        w.write_u8(self.bg_fifo.len() as u8);
+        for p in &self.bg_fifo {
+            w.write_bytes(&[p.color, p.attrs]);
+        }
+        w.write_u8(self.sprite_fifo.len() as u8);
+        for p in &self.sprite_fifo {
+            w.write_bytes(&[p.color, p.attrs, p.oam_index]);
+        }
+        let f = &self.fetcher;
+        w.write_u8(f.step as u8);
+        w.write_bool(f.step_started);
+        w.write_bool(f.window);
+        w.write_bytes(&[f.tile_x, f.tile_idx, f.attrs, f.lo, f.hi]);
+        w.write_u8(self.stall);
+        w.write_u8(self.discard);
+        w.write_u32(self.penalized_tiles);
+        w.write_bool(self.wy_triggered);
+        w.write_u8(self.window_line);
+        w.write_bool(self.window_drawn);
+        w.write_bool(self.stat_line);
+        w.write_u32(self.pending_dots);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.sl = r.read_u8()?;
+        self.dot = r.read_u16()?;
+        self.mode = r.read_u8()?;
+        self.lx = r.read_u8()?;
+        for pixel in self.buffer.iter_mut() {
+            *pixel = r.read_u32()?;
+        }
+        self.oam_dma_start = r.read_u16()?;
+        self.oam_dma_ctr = r.read_u16()?;
+        self.sl_sprites.clear();
+        for _ in 0..r.read_u8()? {
+            let [y, x, tile, attrs, oam_index] = r.read_array()?;
+            self.sl_sprites.push(Sprite {
+                y,
+                x,
+                tile,
+                attrs,
+                oam_index,
+            });
+        }
+        self.fetched_sprites = r.read_u16()?;
+        self.bg_fifo.clear();
+        for _ in 0..r.read_u8()? {
+            let [color, attrs] = r.read_array()?;
+            self.bg_fifo.push_back(BgPixel { color, attrs });
+        }
+        self.sprite_fifo.clear();
+        for _ in 0..r.read_u8()? {
+            let [color, attrs, oam_index] = r.read_array()?;
+            self.sprite_fifo.push_back(SpritePixel {
+                color,
+                attrs,
+                oam_index,
+            });
+        }
+        let f = &mut self.fetcher;
+        f.step = match r.read_u8()? {
+            0 => FetcherStep::Tile,
+            1 => FetcherStep::DataLow,
+            2 => FetcherStep::DataHigh,
+            3 => FetcherStep::Push,
+            step => return Err(format!("Invalid fetcher step {step}")),
+        };
+        f.step_started = r.read_bool()?;
+        f.window = r.read_bool()?;
+        [f.tile_x, f.tile_idx, f.attrs, f.lo, f.hi] = r.read_array()?;
+        self.stall = r.read_u8()?;
+        self.discard = r.read_u8()?;
+        self.penalized_tiles = r.read_u32()?;
+        self.wy_triggered = r.read_bool()?;
+        self.window_line = r.read_u8()?;
+        self.window_drawn = r.read_bool()?;
+        self.stat_line = r.read_bool()?;
+        self.pending_dots = r.read_u32()?;
+        self.dots_to_event = 0;
+        Ok(())
+    }
+}
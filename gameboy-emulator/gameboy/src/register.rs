@@ -1,6 +1,7 @@
 use crate::addrreg::AddrReg;
 use crate::condition::Condition;
 use crate::reg::Reg;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
 
 #[derive(Debug, Clone)]
 pub struct Registers {
@@ -131,4 +132,19 @@ impl Registers {
             Condition::C => self.get_flag(4),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l]);
+        w.write_u16(self.sp);
+        w.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        [self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l] = r.read_array()?;
+        self.sp = r.read_u16()?;
+        self.pc = r.read_u16()?;
+        Ok(())
+    }
+}
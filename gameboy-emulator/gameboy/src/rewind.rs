@@ -0,0 +1,252 @@
+use crate::gameboy::GameBoy;
+use std::collections::VecDeque;
+
+/// Save states taken every few frames so that gameplay can be stepped backwards. Only
+/// the newest state is kept whole. Each older one is stored as the difference to the
+/// state after it, XORed and with runs of unchanged bytes compressed, which makes them a
+/// small fraction of a full state. Once the buffer exceeds its memory budget the oldest
+/// states are dropped.
+pub struct RewindBuffer {
+    interval: u32,
+    budget: usize,
+    frames_since_snapshot: u32,
+    newest: Option<Vec<u8>>,
+    /// Deltas to get from a state to the one before it, oldest first.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    /// Takes a snapshot every `interval` frames and keeps as many as fit in `budget`
+    /// bytes.
+    pub fn new(budget: usize, interval: u32) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            frames_since_snapshot: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    /// Call after every emulated frame.
+    pub fn after_frame(&mut self, gb: &GameBoy) {
+        self.frames_since_snapshot += 1;
+        if self.newest.is_some() && self.frames_since_snapshot < self.interval {
+            return;
+        }
+        self.frames_since_snapshot = 0;
+        let state = gb.save_state_bytes();
+        if let Some(previous) = self.newest.take() {
+            if previous.len() == state.len() {
+                let delta = encode_delta(&state, &previous);
+                self.delta_bytes += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                self.clear();
+            }
+        }
+        self.newest = Some(state);
+        while self.memory_used() > self.budget {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+            self.delta_bytes -= oldest.len();
+        }
+    }
+
+    /// Steps back to the previous snapshot. Returns `false` once the oldest one is
+    /// reached, where `gb` then stays.
+    pub fn rewind(&mut self, gb: &mut GameBoy) -> Result<bool, String> {
+        let Some(newest) = self.newest.as_mut() else {
+            return Ok(false);
+        };
+        // Frames were run since the newest snapshot, so go back to it first.
+        if self.frames_since_snapshot > 0 {
+            self.frames_since_snapshot = 0;
+            gb.load_state_bytes(newest)?;
+            return Ok(true);
+        }
+        let Some(delta) = self.deltas.pop_back() else {
+            return Ok(false);
+        };
+        self.delta_bytes -= delta.len();
+        apply_delta(newest, &delta);
+        gb.load_state_bytes(newest)?;
+        Ok(true)
+    }
+
+    /// Number of states that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes held by the snapshots.
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames_since_snapshot = 0;
+    }
+}
+
+/// Encodes `from XOR to` as pairs of a run of zero bytes and a run of literal bytes, each
+/// preceded by its length as a LEB128 varint.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < from.len() {
+        let zeros = from[i..].iter().zip(&to[i..]).take_while(|(a, b)| a == b);
+        let zero_run = zeros.count();
+        i += zero_run;
+        let literal_start = i;
+        while i < from.len() && from[i] != to[i] {
+            i += 1;
+        }
+        write_varint(&mut out, zero_run);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(|j| from[j] ^ to[j]));
+    }
+    out
+}
+
+/// Turns the state a delta was encoded from into the one it was encoded to.
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for b in &delta[pos..pos + literals] {
+            state[i] ^= b;
+            i += 1;
+        }
+        pos += literals;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let b = buf[*pos];
+        *pos += 1;
+        value |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::NullHost;
+    use crate::test_rom::test_gameboy;
+
+    /// Counts up in work RAM with the LCD on, so every frame leaves a different state.
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 10] = [
+        0x3E, 0x91,       // LD A, $91
+        0xE0, 0x40,       // LDH ($40), A     ; LCD on
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // loop: INC (HL)
+        0x18, 0xFD,       // JR loop
+    ];
+
+    fn delta_round_trip(from: &[u8], to: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(from, to);
+        let mut state = from.to_vec();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, to);
+        delta
+    }
+
+    #[test]
+    fn delta_restores_the_other_state() {
+        let from: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        assert!(delta_round_trip(&from, &from).len() <= 3);
+        let mut to = from.clone();
+        to[0] ^= 1;
+        to[500..520].fill(0xAA);
+        to[999] = 0;
+        delta_round_trip(&from, &to);
+        let inverted: Vec<u8> = from.iter().map(|b| !b).collect();
+        delta_round_trip(&from, &inverted);
+        delta_round_trip(&[], &[]);
+    }
+
+    #[test]
+    fn sparse_changes_make_small_deltas() {
+        let from = vec![0; 100_000];
+        let mut to = from.clone();
+        to[10] = 1;
+        to[70_000] = 2;
+        assert!(delta_round_trip(&from, &to).len() < 16);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let mut buf = Vec::new();
+        let values = [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, usize::MAX];
+        for value in values {
+            write_varint(&mut buf, value);
+        }
+        let mut pos = 0;
+        for value in values {
+            assert_eq!(read_varint(&buf, &mut pos), value);
+        }
+        assert_eq!(pos, buf.len());
+    }
+
+    #[test]
+    fn rewinding_restores_earlier_states() {
+        let mut gb = test_gameboy(&PROGRAM);
+        let mut rewind = RewindBuffer::new(usize::MAX, 2);
+        let mut states = Vec::new();
+        for frame in 0..10 {
+            gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+            rewind.after_frame(&gb);
+            if frame % 2 == 0 {
+                states.push(gb.save_state_bytes());
+            }
+        }
+        assert_eq!(rewind.len(), 5);
+        while let Some(expected) = states.pop() {
+            assert_eq!(rewind.rewind(&mut gb), Ok(true));
+            assert!(gb.save_state_bytes() == expected);
+        }
+        assert_eq!(rewind.rewind(&mut gb), Ok(false));
+    }
+
+    #[test]
+    fn oldest_states_are_dropped_over_budget() {
+        let mut gb = test_gameboy(&PROGRAM);
+        let state_size = gb.save_state_bytes().len();
+        let mut rewind = RewindBuffer::new(state_size + 1, 1);
+        for _ in 0..10 {
+            gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+            rewind.after_frame(&gb);
+        }
+        assert!(rewind.memory_used() <= state_size + 1);
+        assert_eq!(rewind.len(), 1);
+    }
+}
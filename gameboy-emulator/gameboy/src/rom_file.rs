@@ -0,0 +1,47 @@
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
+
+/// Reads a ROM image, unpacking it if the file is gzip-compressed or a zip archive. Zip
+/// archives must contain a file with a `.gb`, `.gbc` or `.sgb` extension.
+pub fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let contents =
+        fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    if contents.starts_with(ZIP_MAGIC) {
+        read_zip(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    } else if contents.starts_with(GZIP_MAGIC) {
+        let mut rom = Vec::new();
+        GzDecoder::new(contents.as_slice())
+            .read_to_end(&mut rom)
+            .map_err(|e| format!("Unable to decompress {}: {}", path.display(), e))?;
+        Ok(rom)
+    } else {
+        Ok(contents)
+    }
+}
+
+fn read_zip(contents: &[u8]) -> Result<Vec<u8>, String> {
+    let mut archive = ZipArchive::new(Cursor::new(contents)).map_err(|e| e.to_string())?;
+    let is_rom = |name: &str| {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    };
+    let name = archive
+        .file_names()
+        .find(|name| is_rom(name))
+        .ok_or("Archive contains no .gb or .gbc file")?
+        .to_string();
+    let mut file = archive.by_name(&name).map_err(|e| e.to_string())?;
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom)
+        .map_err(|e| format!("Unable to extract {}: {}", name, e))?;
+    Ok(rom)
+}
@@ -0,0 +1,191 @@
+use crate::cartridge_header::CartridgeHeader;
+use crate::memory::{Memory, RealTimeClock};
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of the RTC trailer appended to the RAM contents: 5 live and 5 latched registers
+/// as 32-bit little-endian words, followed by a 64-bit UNIX timestamp. This is the layout
+/// used by BGB and VBA-M, so save files can be exchanged with those emulators.
+const RTC_TRAILER_SIZE: usize = 48;
+
+/// Battery-backed cartridge RAM persisted to a `.sav` file next to the ROM.
+pub struct SaveFile {
+    path: PathBuf,
+    ram_size: usize,
+    has_rtc: bool,
+    last_flushed: Vec<u8>,
+}
+
+impl SaveFile {
+    /// Returns `None` for cartridges without a battery, as their RAM is not persistent.
+    pub fn for_cartridge(rom_path: &Path, header: &CartridgeHeader) -> Option<Self> {
+        if !header.has_battery() {
+            return None;
+        }
+        Some(Self {
+            path: rom_path.with_extension("sav"),
+            ram_size: header.ram_size_bytes(),
+            has_rtc: header.has_rtc(),
+            last_flushed: Vec::new(),
+        })
+    }
+
+    pub fn load(&mut self, mem: &mut Memory) -> io::Result<()> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        info!("Loading save file {}", self.path.display());
+
+        let ram = mem.cartridge_ram_mut();
+        let ram_len = self.ram_size.min(ram.len()).min(contents.len());
+        ram[..ram_len].copy_from_slice(&contents[..ram_len]);
+
+        if let Some(rtc) = mem.rtc_mut().filter(|_| self.has_rtc) {
+            match contents.get(self.ram_size..) {
+                Some(trailer) if trailer.len() >= RTC_TRAILER_SIZE => {
+                    Self::read_rtc_trailer(rtc, trailer)
+                }
+                _ => warn!("Save file has no RTC data, starting clock from zero"),
+            }
+        }
+        self.last_flushed = contents;
+        Ok(())
+    }
+
+    /// Writes the cartridge RAM to disk, skipping the write if nothing changed since the
+    /// last flush.
+    pub fn flush(&mut self, mem: &Memory) -> io::Result<()> {
+        let ram = mem.cartridge_ram();
+        let mut contents = ram[..self.ram_size.min(ram.len())].to_vec();
+        if let Some(rtc) = mem.rtc().filter(|_| self.has_rtc) {
+            Self::write_rtc_trailer(rtc, &mut contents);
+        }
+        // The trailing timestamp changes every second, so only compare the registers.
+        let compare_len = contents.len() - if self.has_rtc { 8 } else { 0 };
+        if contents.get(..compare_len) == self.last_flushed.get(..compare_len) {
+            return Ok(());
+        }
+        fs::write(&self.path, &contents)?;
+        self.last_flushed = contents;
+        Ok(())
+    }
+
+    fn read_rtc_trailer(rtc: &mut RealTimeClock, trailer: &[u8]) {
+        let word =
+            |i: usize| u32::from_le_bytes(trailer[4 * i..4 * i + 4].try_into().unwrap()) as u8;
+        for i in 0..5 {
+            rtc.live[i] = word(i);
+            rtc.latched[i] = word(5 + i);
+        }
+        let timestamp = u64::from_le_bytes(trailer[40..48].try_into().unwrap());
+        let elapsed = Self::now().saturating_sub(timestamp);
+        if !rtc.halted() {
+            rtc.advance_seconds(elapsed);
+        }
+    }
+
+    fn write_rtc_trailer(rtc: &RealTimeClock, contents: &mut Vec<u8>) {
+        for reg in rtc.live.iter().chain(rtc.latched.iter()) {
+            contents.extend_from_slice(&(*reg as u32).to_le_bytes());
+        }
+        contents.extend_from_slice(&Self::now().to_le_bytes());
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An MBC3 cartridge of `cartridge_type` with 8 KiB of RAM.
+    fn mbc3_cartridge(name: &str, cartridge_type: u8) -> (PathBuf, CartridgeHeader, Memory) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = cartridge_type;
+        rom[0x149] = 0x02;
+        let header = CartridgeHeader::read(&rom).unwrap();
+        let mem = Memory::new(Vec::new(), rom, header.clone()).unwrap();
+        let rom_path =
+            std::env::temp_dir().join(format!("save-file-{}-{}.gb", name, std::process::id()));
+        (rom_path, header, mem)
+    }
+
+    #[test]
+    fn rtc_trailer_round_trips() {
+        let (rom_path, header, mut mem) = mbc3_cartridge("round-trip", 0x10);
+        mem.cartridge_ram_mut()[0x1234] = 0x56;
+        let rtc = mem.rtc_mut().unwrap();
+        // Halted, so the clock doesn't advance between writing and reading.
+        rtc.live = [12, 34, 5, 0x78, 0x41];
+        rtc.latched = [1, 2, 3, 4, 0x01];
+        let mut save_file = SaveFile::for_cartridge(&rom_path, &header).unwrap();
+        save_file.flush(&mem).unwrap();
+
+        let contents = fs::read(rom_path.with_extension("sav")).unwrap();
+        assert_eq!(contents.len(), 0x2000 + RTC_TRAILER_SIZE);
+        assert_eq!(contents[0x2000..0x2004], [12, 0, 0, 0]);
+
+        let (_, _, mut loaded) = mbc3_cartridge("round-trip", 0x10);
+        SaveFile::for_cartridge(&rom_path, &header)
+            .unwrap()
+            .load(&mut loaded)
+            .unwrap();
+        fs::remove_file(rom_path.with_extension("sav")).unwrap();
+        assert_eq!(loaded.cartridge_ram()[0x1234], 0x56);
+        let rtc = loaded.rtc().unwrap();
+        assert_eq!(rtc.live, [12, 34, 5, 0x78, 0x41]);
+        assert_eq!(rtc.latched, [1, 2, 3, 4, 0x01]);
+    }
+
+    #[test]
+    fn rtc_catches_up_with_time_since_save() {
+        let (rom_path, header, mut loaded) = mbc3_cartridge("catch-up", 0x10);
+        let mut contents = vec![0; 0x2000];
+        let live = [50u32, 58, 22, 0xFF, 0x01];
+        for reg in live.iter().chain(&[0; 5]) {
+            contents.extend_from_slice(&reg.to_le_bytes());
+        }
+        let two_days = 2 * 86_400 + 3_661;
+        contents.extend_from_slice(&(SaveFile::now() - two_days).to_le_bytes());
+        fs::write(rom_path.with_extension("sav"), contents).unwrap();
+
+        SaveFile::for_cartridge(&rom_path, &header)
+            .unwrap()
+            .load(&mut loaded)
+            .unwrap();
+        fs::remove_file(rom_path.with_extension("sav")).unwrap();
+        // Day 511 22:58:50 plus two days and 1:01:01 is day 1 with the carry set. A second
+        // may pass between writing the file and loading it.
+        let [s, m, h, dl, dh] = loaded.rtc().unwrap().live;
+        assert!((51..=52).contains(&s));
+        assert_eq!([m, h, dl, dh], [59, 23, 1, 0x80]);
+    }
+
+    #[test]
+    fn cartridge_without_timer_ignores_trailer() {
+        // MBC3 with RAM and battery, but no timer.
+        let (rom_path, header, mut loaded) = mbc3_cartridge("no-timer", 0x13);
+        let mut contents = vec![0x42; 0x2000];
+        contents.extend_from_slice(&[7; RTC_TRAILER_SIZE]);
+        fs::write(rom_path.with_extension("sav"), contents).unwrap();
+
+        SaveFile::for_cartridge(&rom_path, &header)
+            .unwrap()
+            .load(&mut loaded)
+            .unwrap();
+        fs::remove_file(rom_path.with_extension("sav")).unwrap();
+        assert_eq!(loaded.cartridge_ram()[0x1FFF], 0x42);
+        assert_eq!(loaded.rtc().unwrap().live, [0; 5]);
+    }
+}
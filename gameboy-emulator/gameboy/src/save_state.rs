@@ -0,0 +1,176 @@
+use crate::cartridge_header::CartridgeHeader;
+
+/// Bump this whenever the layout written by any `Snapshot` implementation changes, so
+/// stale save states are rejected instead of being loaded into the wrong fields.
+pub const SAVE_STATE_VERSION: u16 = 14;
+const SAVE_STATE_MAGIC: &[u8; 4] = b"GBSS";
+
+/// Component of the machine whose state can be written to and restored from a save state.
+pub trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String>;
+}
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a new save state with the header identifying the format version and the
+    /// cartridge it belongs to.
+    pub fn new(header: &CartridgeHeader) -> Self {
+        let mut w = Self { buf: Vec::new() };
+        w.write_bytes(SAVE_STATE_MAGIC);
+        w.write_u16(SAVE_STATE_VERSION);
+        w.write_bytes(&header.title);
+        w.write_bytes(&header.global_checksum);
+        w
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn write_bool(&mut self, v: bool) {
+        self.write_u8(v as u8);
+    }
+
+    pub fn write_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, v: f32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Writes a fixed-size block. The reader must know its length.
+    pub fn write_bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+}
+
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header written by `StateWriter::new` against the loaded cartridge.
+    pub fn new(buf: &'a [u8], header: &CartridgeHeader) -> Result<Self, String> {
+        let mut r = Self { buf, pos: 0 };
+        if r.read_bytes(SAVE_STATE_MAGIC.len())? != SAVE_STATE_MAGIC {
+            return Err("Not a save state file".to_string());
+        }
+        let version = r.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(format!(
+                "Save state version {version} is not supported (expected {SAVE_STATE_VERSION})"
+            ));
+        }
+        if r.read_bytes(header.title.len())? != header.title
+            || r.read_bytes(header.global_checksum.len())? != header.global_checksum
+        {
+            return Err("Save state was made with a different cartridge".to_string());
+        }
+        Ok(r)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn read_into(&mut self, dst: &mut [u8]) -> Result<(), String> {
+        dst.copy_from_slice(self.read_bytes(dst.len())?);
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err("Save state is truncated".to_string());
+        }
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Fails if there is data left over, which means the state was written by a different
+    /// layout than the one reading it.
+    pub fn finish(self) -> Result<(), String> {
+        if self.pos != self.buf.len() {
+            return Err("Save state has trailing data".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::NullHost;
+    use crate::test_rom::test_gameboy;
+
+    /// Counts up in work RAM with the LCD on.
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 10] = [
+        0x3E, 0x91,       // LD A, $91
+        0xE0, 0x40,       // LDH ($40), A     ; LCD on
+        0x21, 0x00, 0xC0, // LD HL, $C000
+        0x34,             // loop: INC (HL)
+        0x18, 0xFD,       // JR loop
+    ];
+
+    #[test]
+    fn failed_load_leaves_the_machine_unchanged() {
+        let mut gb = test_gameboy(&PROGRAM);
+        gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+        let earlier = gb.save_state_bytes();
+        gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+        let current = gb.save_state_bytes();
+        assert_ne!(earlier, current);
+
+        let truncated = &earlier[..earlier.len() - 1];
+        assert_eq!(
+            gb.load_state_bytes(truncated),
+            Err("Save state is truncated".to_string())
+        );
+        assert_eq!(gb.save_state_bytes(), current);
+        let mut trailing = earlier.clone();
+        trailing.push(0);
+        assert_eq!(
+            gb.load_state_bytes(&trailing),
+            Err("Save state has trailing data".to_string())
+        );
+        assert_eq!(gb.save_state_bytes(), current);
+
+        gb.load_state_bytes(&earlier).unwrap();
+        assert_eq!(gb.save_state_bytes(), earlier);
+    }
+}
@@ -0,0 +1,445 @@
+use crate::memory::Memory;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use log::{info, warn};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// With the internal clock, bits are shifted at 8192 Hz, so a byte takes 8 * 128 M-cycles.
+/// The clock is derived from the CPU clock, so in double speed mode it runs twice as fast.
+const TRANSFER_M_CYCLES: u32 = 8 * 128;
+/// How often a transfer waiting on the other side checks the link for a byte.
+const POLL_M_CYCLES: u32 = 128;
+const SC_TRANSFER_ENABLE: u8 = 1 << 7;
+const SC_INTERNAL_CLOCK: u8 = 1 << 0;
+const SERIAL_INTERRUPT: u8 = 1 << 3;
+/// How long the clocking side waits for the other side to answer before giving up.
+const TCP_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(1);
+/// TCP link messages are three bytes: one of these kinds, a sequence number and a byte.
+const MSG_CLOCK: u8 = 0x01;
+const MSG_REPLY: u8 = 0x02;
+const MSG_CANCEL: u8 = 0x03;
+const MSG_SIZE: usize = 3;
+
+/// The other end of the link cable.
+pub trait LinkBackend {
+    /// Called when this GameBoy starts a transfer driving the clock, to send `outgoing`.
+    fn send(&mut self, outgoing: u8);
+
+    /// Called once the bits of the transfer started by `send` have been shifted, and then
+    /// repeatedly until it returns the byte shifted in from the other side, which is 0xFF
+    /// if nothing is connected. Must not block.
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Called while this GameBoy waits for the other side to drive the clock. Returns the
+    /// received byte once the other side has clocked a transfer, answering with `outgoing`.
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8>;
+}
+
+/// No cable plugged in.
+pub struct Disconnected;
+
+impl LinkBackend for Disconnected {
+    fn send(&mut self, _: u8) {}
+
+    fn receive(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn poll_external(&mut self, _: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// A cable connecting the port to itself.
+#[derive(Default)]
+pub struct Loopback {
+    sent: u8,
+}
+
+impl LinkBackend for Loopback {
+    fn send(&mut self, outgoing: u8) {
+        self.sent = outgoing;
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        Some(self.sent)
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        Some(outgoing)
+    }
+}
+
+/// Records every byte sent, optionally echoing it to stdout. Test ROMs use this to report
+/// their results as text.
+pub struct CaptureLink {
+    output: Arc<Mutex<Vec<u8>>>,
+    echo: bool,
+}
+
+impl CaptureLink {
+    pub fn new(echo: bool) -> Self {
+        Self {
+            output: Arc::new(Mutex::new(Vec::new())),
+            echo,
+        }
+    }
+
+    /// Handle to the captured bytes that stays valid after the link is handed to a GameBoy.
+    pub fn output(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.output.clone()
+    }
+}
+
+impl LinkBackend for CaptureLink {
+    fn send(&mut self, outgoing: u8) {
+        self.output.lock().unwrap().push(outgoing);
+        if self.echo {
+            print!("{}", outgoing as char);
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn poll_external(&mut self, _: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// A link cable to another emulator instance over TCP. The clocking side sends its byte
+/// in a clock message and the other side answers with its SB in a reply carrying the same
+/// sequence number, once it waits for a transfer. Neither side blocks: a clocking side
+/// that gets no reply in time gives up and cancels the transfer, and replies to transfers
+/// other than the current one are dropped, so a late byte can't answer a later transfer.
+pub struct TcpLink {
+    stream: TcpStream,
+    /// Received bytes that don't make up a whole message yet.
+    buffer: Vec<u8>,
+    seq: u8,
+    /// Sequence number and start of the transfer this side is clocking.
+    in_flight: Option<(u8, Instant)>,
+    reply: Option<u8>,
+    /// The latest clock message from the other side that hasn't been answered.
+    clock: Option<(u8, u8)>,
+    timeout: Duration,
+}
+
+impl TcpLink {
+    /// Waits for the other instance to connect.
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        let (stream, peer) = TcpListener::bind(addr)?.accept()?;
+        info!("Link cable connected to {}", peer);
+        Self::new(stream)
+    }
+
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+            seq: 0,
+            in_flight: None,
+            reply: None,
+            clock: None,
+            timeout: TCP_EXCHANGE_TIMEOUT,
+        })
+    }
+
+    fn write_message(&mut self, kind: u8, seq: u8, value: u8) {
+        if let Err(e) = self.stream.write_all(&[kind, seq, value]) {
+            warn!("Link cable transfer failed: {}", e);
+        }
+    }
+
+    /// Reads the messages that arrived since the last call.
+    fn read_messages(&mut self) {
+        let mut incoming = [0; 64];
+        loop {
+            match self.stream.read(&mut incoming) {
+                Ok(0) => break,
+                Ok(n) => self.buffer.extend_from_slice(&incoming[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Link cable transfer failed: {}", e);
+                    break;
+                }
+            }
+        }
+        let messages = self.buffer.len() / MSG_SIZE * MSG_SIZE;
+        for message in self
+            .buffer
+            .drain(..messages)
+            .collect::<Vec<_>>()
+            .chunks(MSG_SIZE)
+        {
+            let (kind, seq, value) = (message[0], message[1], message[2]);
+            match kind {
+                MSG_CLOCK => self.clock = Some((seq, value)),
+                MSG_REPLY if self.in_flight.is_some_and(|(s, _)| s == seq) => {
+                    self.reply = Some(value)
+                }
+                MSG_CANCEL if self.clock.is_some_and(|(s, _)| s == seq) => self.clock = None,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl LinkBackend for TcpLink {
+    fn send(&mut self, outgoing: u8) {
+        self.seq = self.seq.wrapping_add(1);
+        self.in_flight = Some((self.seq, Instant::now()));
+        self.reply = None;
+        self.write_message(MSG_CLOCK, self.seq, outgoing);
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        let Some((seq, started)) = self.in_flight else {
+            return Some(0xFF);
+        };
+        self.read_messages();
+        if let Some(incoming) = self.reply.take() {
+            self.in_flight = None;
+            return Some(incoming);
+        }
+        if started.elapsed() < self.timeout {
+            return None;
+        }
+        warn!("Link cable transfer timed out");
+        self.write_message(MSG_CANCEL, seq, 0);
+        self.in_flight = None;
+        Some(0xFF)
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        self.read_messages();
+        let (seq, incoming) = self.clock.take()?;
+        self.write_message(MSG_REPLY, seq, outgoing);
+        Some(incoming)
+    }
+}
+
+/// A link backend described as text: `none`, `loopback`, `stdout`, `listen:<addr>` or
+/// `connect:<addr>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkSpec {
+    None,
+    Loopback,
+    Stdout,
+    Listen(String),
+    Connect(String),
+}
+
+impl LinkSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            Some(("listen", addr)) => Ok(LinkSpec::Listen(addr.to_string())),
+            Some(("connect", addr)) => Ok(LinkSpec::Connect(addr.to_string())),
+            _ => match spec {
+                "none" => Ok(LinkSpec::None),
+                "loopback" => Ok(LinkSpec::Loopback),
+                "stdout" => Ok(LinkSpec::Stdout),
+                _ => Err(format!(
+                    "unknown link cable backend '{}', expected none, loopback, stdout, \
+                     listen:<addr> or connect:<addr>",
+                    spec
+                )),
+            },
+        }
+    }
+
+    /// Creates the backend, which for TCP waits for or connects to the other instance.
+    pub fn open(&self) -> io::Result<Box<dyn LinkBackend>> {
+        match self {
+            LinkSpec::None => Ok(Box::new(Disconnected)),
+            LinkSpec::Loopback => Ok(Box::<Loopback>::default()),
+            LinkSpec::Stdout => Ok(Box::new(CaptureLink::new(true))),
+            LinkSpec::Listen(addr) => Ok(Box::new(TcpLink::listen(addr)?)),
+            LinkSpec::Connect(addr) => Ok(Box::new(TcpLink::connect(addr)?)),
+        }
+    }
+}
+
+/// Serial port registers SB (0xFF01) and SC (0xFF02). A transfer starts when the game sets
+/// bit 7 of SC and ends by replacing SB with the received byte, clearing bit 7 and raising
+/// the serial interrupt.
+pub struct Serial {
+    link: Box<dyn LinkBackend>,
+    transferring: bool,
+    m_cycle_counter: u32,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            link: Box::new(Disconnected),
+            transferring: false,
+            m_cycle_counter: 0,
+        }
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn LinkBackend>) {
+        self.link = link;
+    }
+
+    /// Advances a transfer by one M-cycle.
+    pub fn tick(&mut self, mem: &mut Memory) {
+        let sc = mem[0xFF02];
+        if sc & SC_TRANSFER_ENABLE == 0 {
+            self.transferring = false;
+            return;
+        }
+        if !self.transferring {
+            self.transferring = true;
+            self.m_cycle_counter = 0;
+            if sc & SC_INTERNAL_CLOCK != 0 {
+                self.link.send(mem[0xFF01]);
+            }
+        }
+        self.m_cycle_counter += 1;
+
+        // The shifting is done after the transfer time, then the reply is polled for.
+        let incoming = if sc & SC_INTERNAL_CLOCK != 0 {
+            if self.m_cycle_counter < TRANSFER_M_CYCLES {
+                return;
+            }
+            self.m_cycle_counter = TRANSFER_M_CYCLES - POLL_M_CYCLES;
+            self.link.receive()
+        } else {
+            if self.m_cycle_counter < POLL_M_CYCLES {
+                return;
+            }
+            self.m_cycle_counter = 0;
+            self.link.poll_external(mem[0xFF01])
+        };
+        if let Some(incoming) = incoming {
+            Self::complete(mem, incoming);
+            self.transferring = false;
+        }
+    }
+
+    fn complete(mem: &mut Memory, incoming: u8) {
+        mem[0xFF01] = incoming;
+        mem[0xFF02] &= !SC_TRANSFER_ENABLE;
+        mem.request_interrupt(SERIAL_INTERRUPT);
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for Serial {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.transferring);
+        w.write_u32(self.m_cycle_counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.transferring = r.read_bool()?;
+        self.m_cycle_counter = r.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_rom::test_gameboy;
+
+    fn connected_pair() -> (TcpLink, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (TcpLink::new(client).unwrap(), server)
+    }
+
+    fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+        loop {
+            if let Some(value) = poll() {
+                return value;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn internal_clock_transfer_takes_1024_m_cycles() {
+        let mut gb = test_gameboy(&[]);
+        let mut serial = Serial::new();
+        serial.set_link(Box::<Loopback>::default());
+        gb.mem[0xFF01] = 0x5A;
+        gb.mem[0xFF02] = SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK;
+        for _ in 1..TRANSFER_M_CYCLES {
+            serial.tick(&mut gb.mem);
+        }
+        assert_ne!(gb.mem[0xFF02] & SC_TRANSFER_ENABLE, 0);
+        serial.tick(&mut gb.mem);
+        assert_eq!(gb.mem[0xFF02] & SC_TRANSFER_ENABLE, 0);
+        assert_eq!(gb.mem[0xFF01], 0x5A);
+        assert_ne!(gb.mem[0xFF0F] & SERIAL_INTERRUPT, 0);
+    }
+
+    #[test]
+    fn link_spec_parses_backends() {
+        assert_eq!(LinkSpec::parse("loopback"), Ok(LinkSpec::Loopback));
+        assert_eq!(
+            LinkSpec::parse("connect:localhost:8765"),
+            Ok(LinkSpec::Connect("localhost:8765".to_string()))
+        );
+        assert!(LinkSpec::parse("listen").is_err());
+        assert!(LinkSpec::parse("serial:/dev/ttyS0").is_err());
+    }
+
+    #[test]
+    fn tcp_link_transfers_both_ways() {
+        let (mut a, b) = connected_pair();
+        let mut b = TcpLink::new(b).unwrap();
+        a.send(0x12);
+        assert_eq!(a.receive(), None);
+        assert_eq!(wait_for(|| b.poll_external(0x34)), 0x12);
+        assert_eq!(wait_for(|| a.receive()), 0x34);
+    }
+
+    #[test]
+    fn tcp_link_drops_replies_to_earlier_transfers() {
+        let (mut a, mut peer) = connected_pair();
+        a.send(0x10);
+        let mut clock = [0; MSG_SIZE];
+        peer.read_exact(&mut clock).unwrap();
+        assert_eq!(clock[0], MSG_CLOCK);
+        assert_eq!(clock[2], 0x10);
+        let stale = clock[1].wrapping_sub(1);
+        peer.write_all(&[MSG_REPLY, stale, 0xAA, MSG_REPLY, clock[1], 0x42])
+            .unwrap();
+        assert_eq!(wait_for(|| a.receive()), 0x42);
+    }
+
+    #[test]
+    fn tcp_link_cancels_unanswered_transfer() {
+        let (mut a, b) = connected_pair();
+        let mut b = TcpLink::new(b).unwrap();
+        a.timeout = Duration::from_millis(10);
+        a.send(0x01);
+        assert_eq!(wait_for(|| a.receive()), 0xFF);
+        // The peer only starts waiting now and must not see the cancelled byte.
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(b.poll_external(0x55), None);
+        a.send(0x02);
+        assert_eq!(wait_for(|| b.poll_external(0x66)), 0x02);
+        assert_eq!(wait_for(|| a.receive()), 0x66);
+    }
+}
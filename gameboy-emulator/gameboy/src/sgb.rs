@@ -0,0 +1,516 @@
+use crate::cgb::rgb555_to_rgb888;
+use crate::host::{SCREEN_HEIGHT, SCREEN_WIDTH, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+use crate::memory::Memory;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use log::debug;
+
+const PACKET_BITS: usize = 128;
+const MAX_PACKETS: usize = 7;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// The game area is colourised in blocks of 8x8 pixels.
+const ATTR_COLUMNS: usize = SCREEN_WIDTH / 8;
+const ATTR_ROWS: usize = SCREEN_HEIGHT / 8;
+const ATTR_FILES: usize = 45;
+const ATTR_FILE_SIZE: usize = ATTR_COLUMNS * ATTR_ROWS / 4;
+const SYSTEM_PALETTES: usize = 512;
+/// VRAM transfers send 256 tiles of 16 bytes.
+const TRANSFER_SIZE: usize = 0x1000;
+const TRANSFER_TILES_PER_ROW: usize = 20;
+/// Border tiles are SNES 4 bits per pixel tiles of 32 bytes, sent in two halves.
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_TILES_SIZE: usize = 2 * TRANSFER_SIZE;
+const BORDER_MAP_COLUMNS: usize = SGB_SCREEN_WIDTH / 8;
+const BORDER_MAP_ROWS: usize = SGB_SCREEN_HEIGHT / 8;
+/// PCT_TRN sends the border map, 32x32 entries of which 28 rows are shown, followed by
+/// the border palettes 4-7 of 16 colours.
+const BORDER_MAP_SIZE: usize = 0x800;
+const BORDER_DATA_SIZE: usize = BORDER_MAP_SIZE + 4 * 16 * 2;
+const GAME_X: usize = (SGB_SCREEN_WIDTH - SCREEN_WIDTH) / 2;
+const GAME_Y: usize = (SGB_SCREEN_HEIGHT - SCREEN_HEIGHT) / 2;
+
+/// The palette the SGB starts with, its built-in palette 1-A.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+const MASK_NONE: u8 = 0;
+const MASK_FREEZE: u8 = 1;
+const MASK_BLACK: u8 = 2;
+const MASK_COLOR_0: u8 = 3;
+
+/// Data the SGB reads from the screen after a *_TRN command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    /// Border tiles 0x00-0x7F or 0x80-0xFF.
+    BorderTiles(u8),
+    BorderMap,
+    SystemPalettes,
+    AttrFiles,
+}
+
+impl Transfer {
+    fn to_u8(self) -> u8 {
+        match self {
+            Transfer::BorderTiles(half) => half,
+            Transfer::BorderMap => 2,
+            Transfer::SystemPalettes => 3,
+            Transfer::AttrFiles => 4,
+        }
+    }
+
+    fn from_u8(v: u8) -> Result<Self, String> {
+        match v {
+            0 | 1 => Ok(Transfer::BorderTiles(v)),
+            2 => Ok(Transfer::BorderMap),
+            3 => Ok(Transfer::SystemPalettes),
+            4 => Ok(Transfer::AttrFiles),
+            _ => Err(format!("Invalid SGB transfer {}", v)),
+        }
+    }
+}
+
+/// The Super Game Boy, which receives command packets that the game sends bit by bit
+/// through P1, colourises the game area with four palettes assigned to 8x8 blocks and
+/// draws a border around it. Sound and the other SNES side commands are not emulated.
+pub struct Sgb {
+    /// The SGB ignores commands from cartridges whose header doesn't enable them.
+    accepts_commands: bool,
+    command: [u8; MAX_PACKETS * 16],
+    bits_received: usize,
+    /// P14 and P15 were released since the last pulse.
+    ready_for_pulse: bool,
+    /// A reset pulse started the current packet.
+    ready_for_write: bool,
+    /// All bits of a packet were received, the stop bit is next.
+    ready_for_stop: bool,
+    /// P14 and P15 as last written.
+    p1_lines: u8,
+    player_count: u8,
+    player: u8,
+    palettes: [[u16; 4]; 4],
+    /// Palette of every 8x8 block of the game area.
+    attrs: [u8; ATTR_COLUMNS * ATTR_ROWS],
+    system_palettes: Vec<u16>,
+    attr_files: Vec<u8>,
+    border_tiles: Vec<u8>,
+    border_data: Vec<u8>,
+    mask: u8,
+    pending_transfer: Option<Transfer>,
+    /// The last game area shown, which stays while the screen is frozen.
+    game_area: Vec<u32>,
+    screen: Vec<u32>,
+}
+
+impl Sgb {
+    pub fn new(accepts_commands: bool) -> Self {
+        Self {
+            accepts_commands,
+            command: [0; MAX_PACKETS * 16],
+            bits_received: 0,
+            ready_for_pulse: true,
+            ready_for_write: false,
+            ready_for_stop: false,
+            p1_lines: 0b11,
+            player_count: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            attrs: [0; ATTR_COLUMNS * ATTR_ROWS],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attr_files: vec![0; ATTR_FILES * ATTR_FILE_SIZE],
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_data: vec![0; BORDER_DATA_SIZE],
+            mask: MASK_NONE,
+            pending_transfer: None,
+            game_area: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: vec![0; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT],
+        }
+    }
+
+    /// The border and game area as shown, `SGB_SCREEN_WIDTH` wide.
+    pub fn screen(&self) -> &[u32] {
+        &self.screen
+    }
+
+    /// The joypad that reads of P1 currently return, 0 unless MLT_REQ enabled several.
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    pub fn multiplayer(&self) -> bool {
+        self.player_count > 1
+    }
+
+    /// BGR555 colour of a pixel in the game area with the given DMG shade.
+    pub fn color(&self, x: u8, y: u8, shade: u8) -> u16 {
+        let attr = self.attrs[y as usize / 8 * ATTR_COLUMNS + x as usize / 8];
+        self.palettes[attr as usize][shade as usize]
+    }
+
+    /// Receives a write to P1. Pulling P14 and P15 low together starts a packet, after
+    /// which every pulse of only P14 sends a 0 and of only P15 a 1, LSB first. A 0 after
+    /// the 128 bits of a packet ends it.
+    pub fn write_p1(&mut self, value: u8) {
+        let lines = (value >> 4) & 0b11;
+        let p15_rising = self.p1_lines & 0b10 == 0 && lines & 0b10 != 0;
+        self.p1_lines = lines;
+        if p15_rising && self.multiplayer() {
+            self.player = (self.player + 1) % self.player_count;
+        }
+        if !self.accepts_commands {
+            return;
+        }
+        match lines {
+            0b11 => self.ready_for_pulse = true,
+            0b00 if self.ready_for_pulse => {
+                self.ready_for_pulse = false;
+                self.ready_for_write = true;
+                // Continue with the next packet of the command, or start over.
+                if self.ready_for_stop || !self.bits_received.is_multiple_of(PACKET_BITS) {
+                    self.reset_command();
+                }
+            }
+            0b10 | 0b01 if self.ready_for_pulse && self.ready_for_write => {
+                self.ready_for_pulse = false;
+                let bit = lines == 0b01;
+                if self.ready_for_stop {
+                    self.ready_for_write = false;
+                    self.ready_for_stop = false;
+                    if bit {
+                        debug!("SGB packet without stop bit");
+                        self.reset_command();
+                    } else if self.bits_received == self.command_len() * PACKET_BITS {
+                        self.run_command();
+                        self.reset_command();
+                    }
+                } else if self.bits_received < self.command.len() * 8 {
+                    self.command[self.bits_received / 8] |= (bit as u8) << (self.bits_received % 8);
+                    self.bits_received += 1;
+                    self.ready_for_stop = self.bits_received.is_multiple_of(PACKET_BITS);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset_command(&mut self) {
+        self.command = [0; MAX_PACKETS * 16];
+        self.bits_received = 0;
+        self.ready_for_stop = false;
+    }
+
+    /// Number of packets of the command being received.
+    fn command_len(&self) -> usize {
+        (self.command[0] as usize & 0b111).max(1)
+    }
+
+    fn run_command(&mut self) {
+        let c = self.command;
+        let color = |i: usize| u16::from_le_bytes([c[i], c[i + 1]]) & 0x7FFF;
+        match c[0] >> 3 {
+            cmd @ (PAL01 | PAL23 | PAL03 | PAL12) => {
+                let (a, b) = match cmd {
+                    PAL01 => (0, 1),
+                    PAL23 => (2, 3),
+                    PAL03 => (0, 3),
+                    _ => (1, 2),
+                };
+                for i in 1..4 {
+                    self.palettes[a][i] = color(1 + 2 * i);
+                    self.palettes[b][i] = color(7 + 2 * i);
+                }
+                self.set_shared_color(color(1));
+            }
+            ATTR_BLK => {
+                for set in c[2..].chunks_exact(6).take(c[1] as usize) {
+                    self.attr_block(set);
+                }
+            }
+            ATTR_LIN => {
+                for &line in c[2..].iter().take(c[1] as usize) {
+                    let (n, palette) = ((line & 0x1F) as usize, (line >> 5) & 0b11);
+                    for (x, y) in self.attr_cells() {
+                        let on_line = if line & 0x80 != 0 { y == n } else { x == n };
+                        if on_line {
+                            self.attrs[y * ATTR_COLUMNS + x] = palette;
+                        }
+                    }
+                }
+            }
+            ATTR_DIV => {
+                let at = (c[2] & 0x1F) as usize;
+                for (x, y) in self.attr_cells() {
+                    let pos = if c[1] & 0x40 != 0 { y } else { x };
+                    let shift = match pos.cmp(&at) {
+                        std::cmp::Ordering::Less => 2,
+                        std::cmp::Ordering::Equal => 4,
+                        std::cmp::Ordering::Greater => 0,
+                    };
+                    self.attrs[y * ATTR_COLUMNS + x] = (c[1] >> shift) & 0b11;
+                }
+            }
+            ATTR_CHR => {
+                let (mut x, mut y) = ((c[1] & 0x1F) as usize, (c[2] & 0x1F) as usize);
+                let count = u16::from_le_bytes([c[3], c[4]]) as usize;
+                for i in 0..count.min(ATTR_COLUMNS * ATTR_ROWS) {
+                    if x >= ATTR_COLUMNS || y >= ATTR_ROWS {
+                        break;
+                    }
+                    self.attrs[y * ATTR_COLUMNS + x] = (c[6 + i / 4] >> (6 - 2 * (i % 4))) & 0b11;
+                    if c[5] & 1 == 0 {
+                        x += 1;
+                        if x == ATTR_COLUMNS {
+                            (x, y) = (0, y + 1);
+                        }
+                    } else {
+                        y += 1;
+                        if y == ATTR_ROWS {
+                            (x, y) = (x + 1, 0);
+                        }
+                    }
+                }
+            }
+            PAL_SET => {
+                for i in 0..4 {
+                    let n = (u16::from_le_bytes([c[1 + 2 * i], c[2 + 2 * i]]) & 0x1FF) as usize;
+                    self.palettes[i].copy_from_slice(&self.system_palettes[n * 4..n * 4 + 4]);
+                }
+                self.set_shared_color(self.palettes[0][0]);
+                self.attr_file_command(c[9], c[9] & 0x80 != 0);
+            }
+            ATTR_SET => self.attr_file_command(c[1], true),
+            MASK_EN => self.mask = c[1] & 0b11,
+            MLT_REQ => {
+                self.player_count = match c[1] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.pending_transfer = Some(Transfer::BorderTiles(c[1] & 1)),
+            PCT_TRN => self.pending_transfer = Some(Transfer::BorderMap),
+            PAL_TRN => self.pending_transfer = Some(Transfer::SystemPalettes),
+            ATTR_TRN => self.pending_transfer = Some(Transfer::AttrFiles),
+            cmd => debug!("Unsupported SGB command {:02X}", cmd),
+        }
+    }
+
+    fn attr_cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        (0..ATTR_ROWS).flat_map(|y| (0..ATTR_COLUMNS).map(move |x| (x, y)))
+    }
+
+    /// Colour 0 is shared by all four palettes.
+    fn set_shared_color(&mut self, color: u16) {
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color;
+        }
+    }
+
+    /// Applies an ATTR_BLK data set: a rectangle given in 8x8 blocks, whose inside, edge
+    /// and outside each optionally get a palette.
+    fn attr_block(&mut self, set: &[u8]) {
+        let [control, palettes, x1, y1, x2, y2] = set.try_into().unwrap();
+        let palette = |i: u8| (palettes >> (2 * i)) & 0b11;
+        let (inside, outside) = (control & 0b001 != 0, control & 0b100 != 0);
+        // Changing only the inside or the outside changes the edge along with it.
+        let edge = match control & 0b111 {
+            0b001 => Some(palette(0)),
+            0b100 => Some(palette(2)),
+            _ if control & 0b010 != 0 => Some(palette(1)),
+            _ => None,
+        };
+        let (x1, y1, x2, y2) = (x1 & 0x1F, y1 & 0x1F, x2 & 0x1F, y2 & 0x1F);
+        for (x, y) in self.attr_cells() {
+            let (xb, yb) = (x as u8, y as u8);
+            let within = (x1..=x2).contains(&xb) && (y1..=y2).contains(&yb);
+            let on_edge = within && (xb == x1 || xb == x2 || yb == y1 || yb == y2);
+            let new = if on_edge {
+                edge
+            } else if within {
+                inside.then(|| palette(0))
+            } else {
+                outside.then(|| palette(2))
+            };
+            if let Some(new) = new {
+                self.attrs[y * ATTR_COLUMNS + x] = new;
+            }
+        }
+    }
+
+    /// Handles the attribute file byte of PAL_SET and ATTR_SET: bits 0-5 pick the file,
+    /// bit 6 cancels MASK_EN.
+    fn attr_file_command(&mut self, arg: u8, apply_file: bool) {
+        let file = (arg & 0x3F) as usize;
+        if apply_file && file < ATTR_FILES {
+            let data = &self.attr_files[file * ATTR_FILE_SIZE..(file + 1) * ATTR_FILE_SIZE];
+            for (i, attr) in self.attrs.iter_mut().enumerate() {
+                *attr = (data[i / 4] >> (6 - 2 * (i % 4))) & 0b11;
+            }
+        }
+        if arg & 0x40 != 0 {
+            self.mask = MASK_NONE;
+        }
+    }
+
+    /// Called at the start of VBlank with the completed frame. Completes a pending VRAM
+    /// transfer from the screen that was just shown, and draws the SGB screen.
+    pub fn vblank(&mut self, mem: &Memory, frame: &[u32]) {
+        if let Some(transfer) = self.pending_transfer.take() {
+            let data = transfer_data(mem);
+            match transfer {
+                Transfer::BorderTiles(half) => {
+                    let start = half as usize * TRANSFER_SIZE;
+                    self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(&data);
+                }
+                Transfer::BorderMap => {
+                    self.border_data.copy_from_slice(&data[..BORDER_DATA_SIZE]);
+                }
+                Transfer::SystemPalettes => {
+                    for (color, bytes) in self.system_palettes.iter_mut().zip(data.chunks(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7FFF;
+                    }
+                }
+                Transfer::AttrFiles => {
+                    self.attr_files
+                        .copy_from_slice(&data[..ATTR_FILES * ATTR_FILE_SIZE]);
+                }
+            }
+        }
+        match self.mask {
+            MASK_FREEZE => {}
+            MASK_BLACK => self.game_area.fill(0),
+            MASK_COLOR_0 => self.game_area.fill(rgb555_to_rgb888(self.palettes[0][0])),
+            _ => self.game_area.copy_from_slice(frame),
+        }
+        self.draw_border();
+        for (y, row) in self.game_area.chunks(SCREEN_WIDTH).enumerate() {
+            let start = (GAME_Y + y) * SGB_SCREEN_WIDTH + GAME_X;
+            self.screen[start..start + SCREEN_WIDTH].copy_from_slice(row);
+        }
+    }
+
+    /// Draws the border tiles. Their colour 0 is transparent and shows the shared colour 0.
+    fn draw_border(&mut self) {
+        let backdrop = rgb555_to_rgb888(self.palettes[0][0]);
+        for ty in 0..BORDER_MAP_ROWS {
+            for tx in 0..BORDER_MAP_COLUMNS {
+                let i = (ty * BORDER_MAP_COLUMNS + tx) * 2;
+                let entry = u16::from_le_bytes([self.border_data[i], self.border_data[i + 1]]);
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..];
+                // Bits 10-12 select palette 4-7.
+                let palette = BORDER_MAP_SIZE + ((entry >> 10) & 0b11) as usize * 32;
+                let (x_flip, y_flip) = (entry & 0x4000 != 0, entry & 0x8000 != 0);
+                for py in 0..8 {
+                    let row = if y_flip { 7 - py } else { py };
+                    for px in 0..8 {
+                        let bit = if x_flip { px } else { 7 - px };
+                        let plane = |offset: usize| (tile[offset + row * 2] >> bit) & 1;
+                        let color = plane(0) | plane(1) << 1 | plane(16) << 2 | plane(17) << 3;
+                        let rgb = if color == 0 {
+                            backdrop
+                        } else {
+                            let c = palette + color as usize * 2;
+                            rgb555_to_rgb888(u16::from_le_bytes([
+                                self.border_data[c],
+                                self.border_data[c + 1],
+                            ]))
+                        };
+                        self.screen[(ty * 8 + py) * SGB_SCREEN_WIDTH + tx * 8 + px] = rgb;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The data of a VRAM transfer: the tiles the BG map shows, 20 to a row, until 4 KiB are
+/// read. Games set this up on screen before sending a *_TRN command.
+fn transfer_data(mem: &Memory) -> Vec<u8> {
+    let lcdc = mem[0xFF40];
+    let map = if lcdc & (1 << 3) != 0 { 0x9C00 } else { 0x9800 };
+    let mut data = Vec::with_capacity(TRANSFER_SIZE);
+    for i in 0..TRANSFER_SIZE / 16 {
+        let (x, y) = (i % TRANSFER_TILES_PER_ROW, i / TRANSFER_TILES_PER_ROW);
+        let tile = mem.vram(0, map + (y * 32 + x) as u16);
+        let addr = if lcdc & (1 << 4) != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+        };
+        data.extend((0..16).map(|b| mem.vram(0, addr + b)));
+    }
+    data
+}
+
+impl Snapshot for Sgb {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.command);
+        w.write_u16(self.bits_received as u16);
+        w.write_bool(self.ready_for_pulse);
+        w.write_bool(self.ready_for_write);
+        w.write_bool(self.ready_for_stop);
+        w.write_u8(self.p1_lines);
+        w.write_u8(self.player_count);
+        w.write_u8(self.player);
+        for color in self.palettes.as_flattened() {
+            w.write_u16(*color);
+        }
+        w.write_bytes(&self.attrs);
+        for color in &self.system_palettes {
+            w.write_u16(*color);
+        }
+        w.write_bytes(&self.attr_files);
+        w.write_bytes(&self.border_tiles);
+        w.write_bytes(&self.border_data);
+        w.write_u8(self.mask);
+        w.write_u8(self.pending_transfer.map_or(0xFF, Transfer::to_u8));
+        for pixel in &self.game_area {
+            w.write_u32(*pixel);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.command)?;
+        self.bits_received = r.read_u16()? as usize;
+        self.ready_for_pulse = r.read_bool()?;
+        self.ready_for_write = r.read_bool()?;
+        self.ready_for_stop = r.read_bool()?;
+        self.p1_lines = r.read_u8()?;
+        self.player_count = r.read_u8()?;
+        self.player = r.read_u8()?;
+        for color in self.palettes.as_flattened_mut() {
+            *color = r.read_u16()?;
+        }
+        r.read_into(&mut self.attrs)?;
+        for color in self.system_palettes.iter_mut() {
+            *color = r.read_u16()?;
+        }
+        r.read_into(&mut self.attr_files)?;
+        r.read_into(&mut self.border_tiles)?;
+        r.read_into(&mut self.border_data)?;
+        self.mask = r.read_u8()?;
+        self.pending_transfer = match r.read_u8()? {
+            0xFF => None,
+            v => Some(Transfer::from_u8(v)?),
+        };
+        for pixel in self.game_area.iter_mut() {
+            *pixel = r.read_u32()?;
+        }
+        Ok(())
+    }
+}
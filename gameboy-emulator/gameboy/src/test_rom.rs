@@ -0,0 +1,356 @@
+use crate::gameboy::GameBoy;
+use crate::host::SCREEN_WIDTH;
+use crate::serial::CaptureLink;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// M-cycles per second of emulated time, used to express test timeouts.
+const M_CYCLES_PER_SECOND: u64 = 1_048_576;
+/// Blargg's tests that cannot use the serial port report through cartridge RAM instead:
+/// a status byte at 0xA000 followed by this signature and a zero-terminated text.
+const BLARGG_MEMORY_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_STATUS_RUNNING: u8 = 0x80;
+/// Mooneye tests finish by executing `LD B, B` with these values in B, C, D, E, H and L.
+const MOONEYE_PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL_SIGNATURE: [u8; 6] = [0x42; 6];
+const LD_B_B: u8 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestRomKind {
+    Blargg,
+    Mooneye,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestRomResult {
+    Passed,
+    Failed(String),
+    Timeout,
+    Crashed(String),
+}
+
+impl fmt::Display for TestRomResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestRomResult::Passed => write!(f, "passed"),
+            TestRomResult::Failed(reason) => write!(f, "FAILED: {}", reason),
+            TestRomResult::Timeout => write!(f, "TIMEOUT"),
+            TestRomResult::Crashed(reason) => write!(f, "CRASHED: {}", reason),
+        }
+    }
+}
+
+/// Runs a test ROM until it reports a result or `timeout_secs` of emulated time pass.
+/// Emulator panics are caught and reported as `TestRomResult::Crashed`.
+pub fn run_test_rom(gb: &mut GameBoy, kind: TestRomKind, timeout_secs: u64) -> TestRomResult {
+    catch_crash(|| match kind {
+        TestRomKind::Blargg => run_blargg(gb, timeout_secs),
+        TestRomKind::Mooneye => run_mooneye(gb, timeout_secs),
+    })
+}
+
+/// Runs a screenshot test such as dmg-acid2 until it executes `LD B, B`, then compares the
+/// screen with `reference`, an image in the frame buffer's `0x00RRGGBB` format. Pixels are
+/// compared by shade of grey, so the exact colours of the reference don't matter.
+pub fn run_screenshot_test(
+    gb: &mut GameBoy,
+    reference: &[u32],
+    timeout_secs: u64,
+) -> TestRomResult {
+    catch_crash(|| {
+        let mut m_cycles = 0u64;
+        while m_cycles < timeout_secs * M_CYCLES_PER_SECOND {
+            m_cycles += gb.step() as u64;
+            if gb.cpu.last_opcode() == Some(LD_B_B) {
+                gb.ppu.catch_up(&mut gb.mem);
+                return compare_screen(gb.ppu.frame_buffer(), reference);
+            }
+        }
+        TestRomResult::Timeout
+    })
+}
+
+fn compare_screen(screen: &[u32], reference: &[u32]) -> TestRomResult {
+    if screen.len() != reference.len() {
+        return TestRomResult::Failed(format!(
+            "reference has {} pixels, the screen {}",
+            reference.len(),
+            screen.len()
+        ));
+    }
+    // Four shades from the red channel, which is enough for DMG greys.
+    let shade = |pixel: u32| (pixel >> 16 & 0xFF) * 4 / 256;
+    let mismatches: Vec<usize> = (0..screen.len())
+        .filter(|&i| shade(screen[i]) != shade(reference[i]))
+        .collect();
+    match mismatches.first() {
+        None => TestRomResult::Passed,
+        Some(i) => TestRomResult::Failed(format!(
+            "{} pixels differ, first at ({}, {})",
+            mismatches.len(),
+            i % SCREEN_WIDTH,
+            i / SCREEN_WIDTH
+        )),
+    }
+}
+
+fn catch_crash(f: impl FnOnce() -> TestRomResult) -> TestRomResult {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|e| {
+        let reason = e
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default();
+        TestRomResult::Crashed(reason)
+    })
+}
+
+fn run_blargg(gb: &mut GameBoy, timeout_secs: u64) -> TestRomResult {
+    let link = CaptureLink::new(false);
+    let output = link.output();
+    gb.serial.set_link(Box::new(link));
+
+    let mut output_len = 0;
+    let mut m_cycles = 0u64;
+    while m_cycles < timeout_secs * M_CYCLES_PER_SECOND {
+        m_cycles += gb.step() as u64;
+
+        let output = output.lock().unwrap();
+        if output.len() != output_len {
+            output_len = output.len();
+            let text = String::from_utf8_lossy(&output);
+            if text.contains("Passed") {
+                return TestRomResult::Passed;
+            }
+            if text.contains("Failed") {
+                return TestRomResult::Failed(text.trim().to_string());
+            }
+        }
+        drop(output);
+
+        if let Some(result) = blargg_memory_result(gb) {
+            return result;
+        }
+    }
+    TestRomResult::Timeout
+}
+
+fn blargg_memory_result(gb: &GameBoy) -> Option<TestRomResult> {
+    let signature = [gb.mem[0xA001], gb.mem[0xA002], gb.mem[0xA003]];
+    let status = gb.mem[0xA000];
+    if signature != BLARGG_MEMORY_SIGNATURE || status == BLARGG_STATUS_RUNNING {
+        return None;
+    }
+    if status == 0 {
+        return Some(TestRomResult::Passed);
+    }
+    let text: String = (0xA004..0xBFFF)
+        .map(|addr| gb.mem[addr])
+        .take_while(|c| *c != 0)
+        .map(|c| c as char)
+        .collect();
+    Some(TestRomResult::Failed(format!(
+        "code {:02x}: {}",
+        status,
+        text.trim()
+    )))
+}
+
+fn run_mooneye(gb: &mut GameBoy, timeout_secs: u64) -> TestRomResult {
+    let mut m_cycles = 0u64;
+    while m_cycles < timeout_secs * M_CYCLES_PER_SECOND {
+        m_cycles += gb.step() as u64;
+        if gb.cpu.last_opcode() != Some(LD_B_B) {
+            continue;
+        }
+        let reg = &gb.cpu.reg;
+        let signature = [reg.b, reg.c, reg.d, reg.e, reg.h, reg.l];
+        if signature == MOONEYE_PASS_SIGNATURE {
+            return TestRomResult::Passed;
+        }
+        if signature == MOONEYE_FAIL_SIGNATURE {
+            return TestRomResult::Failed("fail signature in registers".to_string());
+        }
+    }
+    TestRomResult::Timeout
+}
+
+/// A GameBoy past the boot ROM that runs `program` from $0150 of a 32 KiB MBC1 cartridge
+/// with 8 KiB of RAM. The cartridge has no battery, and every call gets its own ROM path
+/// so tests running in parallel never share save states.
+#[cfg(test)]
+pub(crate) fn test_gameboy(program: &[u8]) -> GameBoy {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, process};
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x147] = 0x02;
+    rom[0x149] = 0x02;
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("gameboy-test-{}-{}.gb", process::id(), id));
+    let mut gb = GameBoy::new(Vec::new(), rom, path).unwrap();
+    gb.skip_boot_rom();
+    gb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JR_SELF: [u8; 2] = [0x18, 0xFE];
+
+    /// Sends `text` over the serial port, waiting for each byte to go out.
+    fn print_serial(text: &str) -> Vec<u8> {
+        let mut program = Vec::new();
+        for c in text.bytes() {
+            #[rustfmt::skip]
+            program.extend_from_slice(&[
+                0x3E, c,    // LD A, c
+                0xE0, 0x01, // LDH (SB), A
+                0x3E, 0x81, // LD A, $81
+                0xE0, 0x02, // LDH (SC), A
+                0xF0, 0x02, // wait: LDH A, (SC)
+                0xCB, 0x7F, // BIT 7, A
+                0x20, 0xFA, // JR NZ, wait
+            ]);
+        }
+        program.extend_from_slice(&JR_SELF);
+        program
+    }
+
+    /// Enables cartridge RAM and writes a memory report like Blargg's tests do: the running
+    /// status, the signature and text, then the final status.
+    fn blargg_memory_report(status: u8, text: &str) -> Vec<u8> {
+        #[rustfmt::skip]
+        let mut program = vec![
+            0x3E, 0x0A,                  // LD A, $0A
+            0xEA, 0x00, 0x00,            // LD ($0000), A
+            0x21, 0x00, 0xA0,            // LD HL, $A000
+            0x36, BLARGG_STATUS_RUNNING, // LD (HL), $80
+            0x23,                        // INC HL
+        ];
+        let report = BLARGG_MEMORY_SIGNATURE
+            .iter()
+            .chain(text.as_bytes())
+            .chain(&[0]);
+        for b in report {
+            program.extend_from_slice(&[0x36, *b, 0x23]); // LD (HL), b; INC HL
+        }
+        program.extend_from_slice(&[0x3E, status, 0xEA, 0x00, 0xA0]); // LD A, status; LD ($A000), A
+        program.extend_from_slice(&JR_SELF);
+        program
+    }
+
+    /// Loads `signature` into B, C, D, E, H and L.
+    fn load_signature(program: &mut Vec<u8>, signature: [u8; 6]) {
+        for (opcode, value) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E]
+            .into_iter()
+            .zip(signature)
+        {
+            program.extend_from_slice(&[opcode, value]);
+        }
+    }
+
+    /// Loads `signature` into B, C, D, E, H and L, then executes `LD B, B`.
+    fn mooneye_signature(signature: [u8; 6]) -> Vec<u8> {
+        let mut program = Vec::new();
+        load_signature(&mut program, signature);
+        program.push(LD_B_B);
+        program.extend_from_slice(&JR_SELF);
+        program
+    }
+
+    /// Loads the pass signature, but a timer interrupt is taken right where `LD B, B`
+    /// would execute. The handler runs through the empty vectors back to the entry point,
+    /// where the program sees the flag it left in HRAM and hangs, so `LD B, B` never runs.
+    fn mooneye_signature_interrupted() -> Vec<u8> {
+        #[rustfmt::skip]
+        let mut program = vec![
+            0xF0, 0x80, // LDH A, ($80)
+            0xB7,       // OR A
+            0x20, 0x00, // JR NZ, hang
+            0x3C,       // INC A
+            0xE0, 0x80, // LDH ($80), A
+            0x3E, 0x04, // LD A, $04
+            0xE0, 0xFF, // LDH (IE), A
+            0xE0, 0x0F, // LDH (IF), A
+        ];
+        load_signature(&mut program, MOONEYE_PASS_SIGNATURE);
+        program.extend_from_slice(&[0xFB, 0x00, LD_B_B]); // EI; NOP; LD B, B
+        program[4] = (program.len() - 5) as u8;
+        program.extend_from_slice(&JR_SELF); // hang: JR hang
+        program
+    }
+
+    #[test]
+    fn blargg_serial_pass_and_fail() {
+        let mut gb = test_gameboy(&print_serial("cpu_instrs\n\nPassed\n"));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Blargg, 1),
+            TestRomResult::Passed
+        );
+        let mut gb = test_gameboy(&print_serial("01-special\n\nFailed #3\n"));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Blargg, 1),
+            TestRomResult::Failed("01-special\n\nFailed".to_string())
+        );
+    }
+
+    #[test]
+    fn blargg_memory_pass_and_fail() {
+        let mut gb = test_gameboy(&blargg_memory_report(0x00, ""));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Blargg, 1),
+            TestRomResult::Passed
+        );
+        let mut gb = test_gameboy(&blargg_memory_report(0x03, "OAM "));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Blargg, 1),
+            TestRomResult::Failed("code 03: OAM".to_string())
+        );
+    }
+
+    #[test]
+    fn blargg_memory_waits_while_running() {
+        let mut gb = test_gameboy(&blargg_memory_report(0x80, ""));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Blargg, 1),
+            TestRomResult::Timeout
+        );
+    }
+
+    #[test]
+    fn mooneye_fibonacci_registers_pass() {
+        let mut gb = test_gameboy(&mooneye_signature(MOONEYE_PASS_SIGNATURE));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Mooneye, 1),
+            TestRomResult::Passed
+        );
+    }
+
+    #[test]
+    fn mooneye_fail_signature_fails() {
+        let mut gb = test_gameboy(&mooneye_signature(MOONEYE_FAIL_SIGNATURE));
+        assert!(matches!(
+            run_test_rom(&mut gb, TestRomKind::Mooneye, 1),
+            TestRomResult::Failed(_)
+        ));
+        let mut gb = test_gameboy(&mooneye_signature([1, 2, 3, 4, 5, 6]));
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Mooneye, 1),
+            TestRomResult::Timeout
+        );
+    }
+
+    #[test]
+    fn mooneye_ignores_ld_b_b_skipped_by_an_interrupt() {
+        let mut gb = test_gameboy(&mooneye_signature_interrupted());
+        assert_eq!(
+            run_test_rom(&mut gb, TestRomKind::Mooneye, 1),
+            TestRomResult::Timeout
+        );
+    }
+}
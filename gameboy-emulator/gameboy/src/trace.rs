@@ -0,0 +1,51 @@
+use crate::addrreg::AddrReg;
+use crate::cpu::CPU;
+use crate::memory::Memory;
+use std::io;
+use std::io::Write;
+
+/// Value LY reads as while the LY stub is enabled. Gameboy Doctor logs are recorded with
+/// LY fixed at $90 so that they don't depend on PPU timing.
+pub const STUB_LY: u8 = 0x90;
+
+/// Formats the CPU state before an instruction in the Gameboy Doctor format:
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+pub fn format_state(cpu: &CPU, mem: &Memory) -> String {
+    let reg = &cpu.reg;
+    let pc = reg.pc;
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        reg.a,
+        reg.get_pair(AddrReg::AF) & 0xFF,
+        reg.b,
+        reg.c,
+        reg.d,
+        reg.e,
+        reg.h,
+        reg.l,
+        reg.sp,
+        pc,
+        mem[pc],
+        mem[pc.wrapping_add(1)],
+        mem[pc.wrapping_add(2)],
+        mem[pc.wrapping_add(3)],
+    )
+}
+
+/// Execution trace written one line per instruction, so runs can be diffed against
+/// known-good logs from reference emulators.
+pub struct TraceLog {
+    out: Box<dyn Write>,
+    /// Whether LY is held at `STUB_LY`, as Gameboy Doctor expects.
+    pub stub_ly: bool,
+}
+
+impl TraceLog {
+    pub fn new(out: Box<dyn Write>, stub_ly: bool) -> Self {
+        Self { out, stub_ly }
+    }
+
+    pub fn log(&mut self, cpu: &CPU, mem: &Memory) -> io::Result<()> {
+        writeln!(self.out, "{}", format_state(cpu, mem))
+    }
+}
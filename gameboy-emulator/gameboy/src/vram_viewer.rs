@@ -0,0 +1,298 @@
+use crate::cgb::rgb555_to_rgb888;
+use crate::host::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::memory::Memory;
+use crate::ppu::PPU;
+
+const TILES_PER_BANK: u16 = 384;
+const TILES_PER_ROW: usize = 16;
+const TILE_SIZE_BYTES: u16 = 16;
+const MAP_SIZE: usize = 256;
+const MAP_TILES: u16 = 32;
+/// Space between the panels of a view.
+const GAP: usize = 8;
+const SPRITE_COUNT: usize = 40;
+const OAM_ROWS: usize = 20;
+/// Height of an OAM table row, fitting an 8x16 sprite.
+const OAM_ROW_HEIGHT: usize = 18;
+const SWATCH_SIZE: usize = 16;
+
+const BACKGROUND: u32 = 0x404040;
+const VIEWPORT_COLOR: u32 = 0xFF0000;
+const TEXT_COLOR: u32 = 0xFFFFFF;
+/// Shown for sprite pixels with colour 0, which are transparent.
+const TRANSPARENT_COLOR: u32 = 0x808080;
+
+/// Glyphs of a 3x5 pixel font, one bit per pixel, row by row from the top left.
+const FONT: [(char, u16); 20] = [
+    ('0', 0b111_101_101_101_111),
+    ('1', 0b010_110_010_010_111),
+    ('2', 0b111_001_111_100_111),
+    ('3', 0b111_001_111_001_111),
+    ('4', 0b101_101_111_001_001),
+    ('5', 0b111_100_111_001_111),
+    ('6', 0b111_100_111_101_111),
+    ('7', 0b111_001_001_001_001),
+    ('8', 0b111_101_111_101_111),
+    ('9', 0b111_101_111_001_111),
+    ('A', 0b010_101_111_101_101),
+    ('B', 0b110_101_110_101_110),
+    ('C', 0b011_100_100_100_011),
+    ('D', 0b110_101_101_101_110),
+    ('E', 0b111_100_110_100_111),
+    ('F', 0b111_100_110_100_100),
+    ('P', 0b110_101_110_100_100),
+    ('X', 0b101_101_010_101_101),
+    ('Y', 0b101_101_010_010_010),
+    ('-', 0b000_000_111_000_000),
+];
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// An image of some part of the video state, `0x00RRGGBB` pixels in rows of `width`.
+pub struct DebugView {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl DebugView {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: u32) {
+        if x < self.width && y < self.height {
+            self.pixels[x + y * self.width] = color;
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Draws text made of the characters in `FONT`; anything else is left blank.
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let Some((_, glyph)) = FONT.iter().find(|(g, _)| *g == c) else {
+                continue;
+            };
+            for py in 0..GLYPH_HEIGHT {
+                for px in 0..GLYPH_WIDTH {
+                    let bit = (GLYPH_HEIGHT - py) * GLYPH_WIDTH - px - 1;
+                    if glyph >> bit & 1 != 0 {
+                        self.set(x + i * (GLYPH_WIDTH + 1) + px, y + py, TEXT_COLOR);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws a tile with its top left corner at `x`, `y`. `color` maps colour indexes to
+    /// pixels.
+    #[allow(clippy::too_many_arguments)]
+    fn tile(
+        &mut self,
+        mem: &Memory,
+        bank: u8,
+        tile_start: u16,
+        x: usize,
+        y: usize,
+        flip_x: bool,
+        flip_y: bool,
+        color: impl Fn(u8) -> u32,
+    ) {
+        for ty in 0..8u8 {
+            for tx in 0..8u8 {
+                let src_x = if flip_x { 7 - tx } else { tx };
+                let src_y = if flip_y { 7 - ty } else { ty };
+                let c = PPU::get_pixel_in_tile(mem, bank, src_x, src_y, tile_start);
+                self.set(x + tx as usize, y + ty as usize, color(c));
+            }
+        }
+    }
+}
+
+/// Colour of a BG colour index in the palette given by the tile attributes, which are
+/// only used on the CGB.
+fn bg_color(mem: &Memory, attrs: u8, color: u8) -> u32 {
+    if mem.is_cgb() {
+        rgb555_to_rgb888(mem.bg_palettes.color(attrs & 0b111, color))
+    } else {
+        rgb555_to_rgb888(PPU::dmg_shade(mem, mem[0xFF47], color))
+    }
+}
+
+fn obj_color(mem: &Memory, attrs: u8, color: u8) -> u32 {
+    if color == 0 {
+        TRANSPARENT_COLOR
+    } else if mem.is_cgb() {
+        rgb555_to_rgb888(mem.obj_palettes.color(attrs & 0b111, color))
+    } else {
+        let obp = if attrs & (1 << 4) != 0 {
+            0xFF49
+        } else {
+            0xFF48
+        };
+        rgb555_to_rgb888(PPU::dmg_shade(mem, mem[obp], color))
+    }
+}
+
+/// The 384 tiles of pattern data in 8000-97FF, 16 per row, drawn with the first BG
+/// palette. On the CGB the second VRAM bank is shown next to the first.
+pub fn tile_data(mem: &Memory) -> DebugView {
+    let banks = if mem.is_cgb() { 2 } else { 1 };
+    let bank_width = TILES_PER_ROW * 8;
+    let rows = TILES_PER_BANK as usize / TILES_PER_ROW;
+    let mut view = DebugView::new(banks * bank_width + (banks - 1) * GAP, rows * 8);
+    for bank in 0..banks {
+        for tile in 0..TILES_PER_BANK {
+            let x = bank * (bank_width + GAP) + (tile as usize % TILES_PER_ROW) * 8;
+            let y = (tile as usize / TILES_PER_ROW) * 8;
+            let start = 0x8000 + tile * TILE_SIZE_BYTES;
+            view.tile(mem, bank as u8, start, x, y, false, false, |c| {
+                bg_color(mem, 0, c)
+            });
+        }
+    }
+    view
+}
+
+/// Both BG tile maps, 9800 on the left and 9C00 on the right, using the tile data area
+/// selected by LCDC. The 160x144 viewport at SCX/SCY is outlined on the map LCDC selects
+/// for the background, wrapping around its edges like the hardware does.
+pub fn bg_maps(mem: &Memory) -> DebugView {
+    let lcdc = mem[0xFF40];
+    let mut view = DebugView::new(2 * MAP_SIZE + GAP, MAP_SIZE);
+    for map in 0..2 {
+        let map_start = if map == 0 { 0x9800 } else { 0x9C00 };
+        for ti in 0..MAP_TILES * MAP_TILES {
+            let tile_idx = mem.vram(0, map_start + ti);
+            let attrs = if mem.is_cgb() {
+                mem.vram(1, map_start + ti)
+            } else {
+                0
+            };
+            let start = if lcdc & (1 << 4) != 0 {
+                0x8000 + tile_idx as u16 * TILE_SIZE_BYTES
+            } else {
+                0x9000u16.wrapping_add_signed((tile_idx as i8) as i16 * 16)
+            };
+            let bank = (attrs >> 3) & 1;
+            let x = map * (MAP_SIZE + GAP) + (ti % MAP_TILES) as usize * 8;
+            let y = (ti / MAP_TILES) as usize * 8;
+            let flip_x = attrs & (1 << 5) != 0;
+            let flip_y = attrs & (1 << 6) != 0;
+            view.tile(mem, bank, start, x, y, flip_x, flip_y, |c| {
+                bg_color(mem, attrs, c)
+            });
+        }
+    }
+
+    let map_x = if lcdc & (1 << 3) != 0 {
+        MAP_SIZE + GAP
+    } else {
+        0
+    };
+    let (scx, scy) = (mem[0xFF43] as usize, mem[0xFF42] as usize);
+    let (width, height) = (SCREEN_WIDTH, SCREEN_HEIGHT);
+    for i in 0..width {
+        let x = map_x + (scx + i) % MAP_SIZE;
+        view.set(x, scy, VIEWPORT_COLOR);
+        view.set(x, (scy + height - 1) % MAP_SIZE, VIEWPORT_COLOR);
+    }
+    for i in 0..height {
+        let y = (scy + i) % MAP_SIZE;
+        view.set(map_x + scx, y, VIEWPORT_COLOR);
+        view.set(map_x + (scx + width - 1) % MAP_SIZE, y, VIEWPORT_COLOR);
+    }
+    view
+}
+
+/// The 40 OAM entries in two columns. Each row shows the sprite at the current size
+/// (LCDC bit 2) followed by its index, Y, X and tile number in hex, then the decoded
+/// attributes: X for X flip, Y for Y flip, P for BG priority, and the palette, which is
+/// OBP0 or OBP1 on the DMG, and the VRAM bank and colour palette on the CGB.
+pub fn oam(mem: &Memory) -> DebugView {
+    let tall = mem[0xFF40] & (1 << 2) != 0;
+    let column_width = 8 + 4 + 18 * (GLYPH_WIDTH + 1);
+    let mut view = DebugView::new(2 * column_width + GAP, OAM_ROWS * OAM_ROW_HEIGHT);
+    for i in 0..SPRITE_COUNT {
+        let [y, x, tile, attrs] = [0, 1, 2, 3].map(|b| mem.sprite[i * 4 + b]);
+        let left = (i / OAM_ROWS) * (column_width + GAP);
+        let top = (i % OAM_ROWS) * OAM_ROW_HEIGHT + 1;
+        let flip_x = attrs & (1 << 5) != 0;
+        let flip_y = attrs & (1 << 6) != 0;
+        let bank = if mem.is_cgb() { (attrs >> 3) & 1 } else { 0 };
+        let tiles = if tall {
+            [tile & 0xFE, tile | 0x01]
+        } else {
+            [tile; 2]
+        };
+        let count = if tall { 2 } else { 1 };
+        for (half, t) in tiles.iter().take(count).enumerate() {
+            // With Y flip the bottom tile of an 8x16 sprite is drawn at the top.
+            let row = if tall && flip_y { 1 - half } else { half };
+            let start = 0x8000 + *t as u16 * TILE_SIZE_BYTES;
+            view.tile(mem, bank, start, left, top + row * 8, flip_x, flip_y, |c| {
+                obj_color(mem, attrs, c)
+            });
+        }
+
+        let flag = |bit: u8, c: char| if attrs & (1 << bit) != 0 { c } else { '-' };
+        let palette = if mem.is_cgb() {
+            format!("{}{}", bank, attrs & 0b111)
+        } else {
+            format!("{}", (attrs >> 4) & 1)
+        };
+        let line = format!(
+            "{:02X} {:02X} {:02X} {:02X} {}{}{}{}",
+            i,
+            y,
+            x,
+            tile,
+            flag(5, 'X'),
+            flag(6, 'Y'),
+            flag(7, 'P'),
+            palette
+        );
+        view.text(left + 12, top + 5, &line);
+    }
+    view
+}
+
+/// The palettes in use, four swatches per palette: BG palettes on the left and OBJ
+/// palettes on the right. On the DMG these are BGP, and OBP0 and OBP1.
+pub fn palettes(mem: &Memory) -> DebugView {
+    let rows = if mem.is_cgb() { 8 } else { 2 };
+    let column_width = 4 * SWATCH_SIZE;
+    let mut view = DebugView::new(2 * column_width + GAP, rows * SWATCH_SIZE);
+    for row in 0..rows {
+        for color in 0..4u8 {
+            let (bg, obj) = if mem.is_cgb() {
+                (
+                    Some(mem.bg_palettes.color(row as u8, color)),
+                    mem.obj_palettes.color(row as u8, color),
+                )
+            } else {
+                let bg = (row == 0).then(|| PPU::dmg_shade(mem, mem[0xFF47], color));
+                (bg, PPU::dmg_shade(mem, mem[0xFF48 + row as u16], color))
+            };
+            let x = color as usize * SWATCH_SIZE;
+            let y = row * SWATCH_SIZE;
+            if let Some(bg) = bg {
+                view.fill(x, y, SWATCH_SIZE, SWATCH_SIZE, rgb555_to_rgb888(bg));
+            }
+            let obj_x = column_width + GAP + x;
+            view.fill(obj_x, y, SWATCH_SIZE, SWATCH_SIZE, rgb555_to_rgb888(obj));
+        }
+    }
+    view
+}
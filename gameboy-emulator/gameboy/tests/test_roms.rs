@@ -0,0 +1,197 @@
+//! Runs the Blargg, Mooneye and dmg-acid2 test ROMs headlessly and prints a per-ROM
+//! result table. The Blargg ROMs are also used to check that movies replay
+//! deterministically and that rewinding restores earlier states exactly.
+//!
+//! The ROMs are not part of the repository. Place them in `test-roms/blargg`,
+//! `test-roms/mooneye` and `test-roms/acid2` (sub-directories are searched as well), or
+//! point the `GB_TEST_ROMS` environment variable at a directory with that layout. Screenshot
+//! tests in `acid2` need their reference image next to the ROM, e.g. `dmg-acid2.png`. ROMs
+//! that are missing are skipped; the result detection itself is covered by unit tests in
+//! `test_rom.rs` that run built-in programs. Run with `cargo test --release -- --nocapture`
+//! to see the table.
+
+use gameboy::host::{InputSource, NullHost};
+use gameboy::joypad::JoyPad;
+use gameboy::movie::{Movie, MoviePlayer, MovieRecorder};
+use gameboy::rewind::RewindBuffer;
+use gameboy::test_rom::{run_screenshot_test, run_test_rom, TestRomKind, TestRomResult};
+use gameboy::GameBoy;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BLARGG_TIMEOUT_SECS: u64 = 120;
+const MOONEYE_TIMEOUT_SECS: u64 = 20;
+const ACID2_TIMEOUT_SECS: u64 = 10;
+const MOVIE_FRAMES: u32 = 300;
+const REWIND_INTERVAL: u32 = 4;
+const REWIND_STEPS: u32 = 20;
+
+fn test_rom_dir() -> PathBuf {
+    env::var_os("GB_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-roms"))
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+/// Reads a PNG as `0x00RRGGBB` pixels.
+fn load_reference(path: &Path) -> Vec<u32> {
+    let mut decoder = png::Decoder::new(fs::File::open(path).unwrap());
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    let channels = info.color_type.samples();
+    buf[..info.buffer_size()]
+        .chunks(channels)
+        .map(|px| match px {
+            [l] | [l, _] => (*l as u32) * 0x010101,
+            [r, g, b, ..] => (*r as u32) << 16 | (*g as u32) << 8 | *b as u32,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+fn run_suite(
+    name: &str,
+    sub_dir: &str,
+    mut run: impl FnMut(&mut GameBoy, &Path) -> Option<TestRomResult>,
+) {
+    let root = test_rom_dir();
+    let mut roms = Vec::new();
+    find_roms(&root.join(sub_dir), &mut roms);
+    roms.sort();
+
+    let mut results = Vec::new();
+    for rom_path in roms {
+        let rom = fs::read(&rom_path).unwrap();
+        // Keep any save files the cartridge might write out of the ROM directory.
+        let save_path = env::temp_dir().join(rom_path.file_name().unwrap());
+        let mut gb = GameBoy::new(Vec::new(), rom, save_path).unwrap();
+        gb.skip_boot_rom();
+        let Some(result) = run(&mut gb, &rom_path) else {
+            continue;
+        };
+        let name = rom_path.strip_prefix(&root).unwrap().display().to_string();
+        results.push((name, result));
+    }
+    if results.is_empty() {
+        println!(
+            "No {} test ROMs found in {}, skipping",
+            name,
+            root.display()
+        );
+        return;
+    }
+
+    let width = results.iter().map(|(name, _)| name.len()).max().unwrap();
+    println!("{:<width$} | Result", "ROM");
+    println!("{:-<width$}-+-{:-<6}", "", "");
+    for (name, result) in &results {
+        println!("{:<width$} | {}", name, result);
+    }
+    let passed = results
+        .iter()
+        .filter(|(_, r)| *r == TestRomResult::Passed)
+        .count();
+    println!("{}/{} passed", passed, results.len());
+    assert_eq!(passed, results.len(), "{} test ROMs failed", name);
+}
+
+#[test]
+fn blargg() {
+    run_suite("Blargg", "blargg", |gb, _| {
+        Some(run_test_rom(gb, TestRomKind::Blargg, BLARGG_TIMEOUT_SECS))
+    });
+}
+
+#[test]
+fn mooneye() {
+    run_suite("Mooneye", "mooneye", |gb, _| {
+        Some(run_test_rom(gb, TestRomKind::Mooneye, MOONEYE_TIMEOUT_SECS))
+    });
+}
+
+#[test]
+fn acid2() {
+    run_suite("acid2", "acid2", |gb, rom_path| {
+        let reference_path = rom_path.with_extension("png");
+        if !reference_path.exists() {
+            println!("No reference image for {}, skipping", rom_path.display());
+            return None;
+        }
+        let reference = load_reference(&reference_path);
+        Some(run_screenshot_test(gb, &reference, ACID2_TIMEOUT_SECS))
+    });
+}
+
+/// Presses a different combination of buttons every frame.
+struct ScriptedInput(u32);
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self, joy_pad: &mut JoyPad) {
+        joy_pad.set_buttons((self.0.wrapping_mul(37) >> 2) as u8);
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn movie_replay() {
+    run_suite("movie replay", "blargg", |gb, rom_path| {
+        let mut recorder = MovieRecorder::from_power_on(gb);
+        let mut input = ScriptedInput(0);
+        for _ in 0..MOVIE_FRAMES {
+            recorder.run_frame(gb, &mut NullHost, &mut NullHost, &mut input);
+        }
+        let movie = Movie::from_bytes(&recorder.finish().to_bytes()).unwrap();
+
+        let rom = fs::read(rom_path).unwrap();
+        let save_path = env::temp_dir().join(rom_path.file_name().unwrap());
+        let mut replay = GameBoy::new(Vec::new(), rom, save_path).unwrap();
+        replay.skip_boot_rom();
+        let mut player = MoviePlayer::start(movie, &mut replay).unwrap();
+        while !player.is_finished() {
+            if let Err(e) = player.run_frame(&mut replay, &mut NullHost, &mut NullHost) {
+                return Some(TestRomResult::Failed(e));
+            }
+        }
+        Some(TestRomResult::Passed)
+    });
+}
+
+#[test]
+fn rewind() {
+    run_suite("rewind", "blargg", |gb, _| {
+        let mut rewind = RewindBuffer::new(usize::MAX, REWIND_INTERVAL);
+        let mut states = Vec::new();
+        for frame in 0..REWIND_INTERVAL * REWIND_STEPS {
+            gb.run_frame(&mut NullHost, &mut NullHost, &mut NullHost);
+            rewind.after_frame(gb);
+            if frame % REWIND_INTERVAL == 0 {
+                states.push(gb.save_state_bytes());
+            }
+        }
+        while let Some(expected) = states.pop() {
+            if !rewind.rewind(gb).unwrap() || gb.save_state_bytes() != expected {
+                let msg = format!(
+                    "{} snapshots from the end differ",
+                    REWIND_STEPS as usize - states.len()
+                );
+                return Some(TestRomResult::Failed(msg));
+            }
+        }
+        Some(TestRomResult::Passed)
+    });
+}
@@ -1,10 +0,0 @@
-use crate::reg::Reg;
-use crate::addrreg::AddrReg;
-
-#[derive(Debug, Clone)]
-pub enum DataLoc {
-    Reg(Reg),
-    AddrReg(AddrReg),
-    Addr(u16),
-    Value(u8),
-}
\ No newline at end of file
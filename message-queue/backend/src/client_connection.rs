@@ -77,6 +77,8 @@ impl<T: ToSocketAddrs + Clone + Debug + Send> ConnectedClient<T> {
                return Err(match e{
                    StreamIOError::Stream(_) => RequestError::CommunicationError,
                    StreamIOError::Codec(_) => RequestError::PayloadEncodeError,
+                   StreamIOError::FrameTooLarge { .. } => RequestError::PayloadEncodeError,
+                   StreamIOError::UnsupportedVersion(_) => RequestError::CommunicationError,
                })
         }
         self.pull_admin_response().await
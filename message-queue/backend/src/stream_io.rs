@@ -1,16 +1,26 @@
 use std::fmt::Debug;
+use std::future::poll_fn;
+use std::task::Poll;
 use crate::protocol::codec::{decode, encode, CodecError};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use crate::protocol::client_id::ClientID;
 use crate::protocol::request_error::RequestError;
 
-const BUFFER_SIZE: usize = 1024;
+/// Version of the wire format, sent at the start of every frame. Peers refuse frames of
+/// another version, so it must change whenever the framing changes.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Largest payload accepted in either direction unless changed with
+/// `StreamIO::set_max_frame_size`.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+/// A frame starts with the protocol version and the payload length as a big-endian `u32`.
+const HEADER_SIZE: usize = 5;
 
 pub struct StreamIO {
     stream: TcpStream,
+    max_frame_size: usize,
     last_read: Option<SystemTime>,
     last_write: Option<SystemTime>,
 }
@@ -21,6 +31,12 @@ pub enum StreamIOError {
     Stream(std::io::Error),
     /// For errors with serialisation of messages.
     Codec(postcard::Error),
+    /// The payload of a frame is larger than the maximum frame size. Oversized frames are
+    /// not sent, and received ones are skipped, so the connection remains usable.
+    FrameTooLarge { size: usize, max: usize },
+    /// The peer speaks another protocol version. Its frames can't be delimited, so the
+    /// connection should be closed.
+    UnsupportedVersion(u8),
 }
 
 impl From<CodecError> for StreamIOError {
@@ -35,6 +51,14 @@ impl From<StreamIOError> for String {
         match value {
             StreamIOError::Stream(e) => e.to_string(),
             StreamIOError::Codec(e) => e.to_string(),
+            StreamIOError::FrameTooLarge { size, max } => format!(
+                "Frame of {} bytes exceeds the maximum frame size of {} bytes",
+                size, max
+            ),
+            StreamIOError::UnsupportedVersion(version) => format!(
+                "Unsupported protocol version {}, expected {}",
+                version, PROTOCOL_VERSION
+            ),
         }
     }
 }
@@ -56,22 +80,72 @@ impl From<postcard::Error> for StreamIOError {
 /// typed encoded messages and exposes error handling for when packets are not received
 /// or incorrectly formatted. Connections will automatically be shutdown once the stream
 /// goes out of scope.
+///
+/// Every message is sent as one frame: the protocol version, the length of the payload
+/// and the payload itself, so messages of any size up to the maximum frame size arrive
+/// whole and separately.
 impl StreamIO {
     pub fn new(stream: TcpStream) -> Self {
         Self {
             stream,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
             last_read: None,
             last_write: None,
         }
     }
 
-    pub async fn write(&mut self, data: &Vec<u8>) -> Result<(), StreamIOError> {
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Limit the payload of frames sent and received. Both peers should use the same limit.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
 
-        let result = Ok(self.stream.write_all(data).await?);
+    fn check_frame_size(&self, size: usize) -> Result<(), StreamIOError> {
+        if size > self.max_frame_size || size > u32::MAX as usize {
+            return Err(StreamIOError::FrameTooLarge {
+                size,
+                max: self.max_frame_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write `data` to the stream as one frame.
+    pub async fn write(&mut self, data: &Vec<u8>) -> Result<(), StreamIOError> {
+        self.check_frame_size(data.len())?;
+        // Header and payload go out in a single write, so that Nagle's algorithm doesn't
+        // hold back the payload until the header is acknowledged.
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        frame.push(PROTOCOL_VERSION);
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(data);
+        let result = Ok(self.stream.write_all(&frame).await?);
         self.last_write = Some(SystemTime::now());
         result
     }
 
+    /// Read the payload of the next frame. Waits until the whole frame has arrived.
+    async fn read_frame(&mut self) -> Result<Vec<u8>, StreamIOError> {
+        let mut header = [0; HEADER_SIZE];
+        self.stream.read_exact(&mut header).await?;
+        let [version, len @ ..] = header;
+        if version != PROTOCOL_VERSION {
+            return Err(StreamIOError::UnsupportedVersion(version));
+        }
+        let size = u32::from_be_bytes(len) as usize;
+        if let Err(e) = self.check_frame_size(size) {
+            let mut payload = (&mut self.stream).take(size as u64);
+            tokio::io::copy(&mut payload, &mut tokio::io::sink()).await?;
+            return Err(e);
+        }
+        let mut payload = vec![0; size];
+        self.stream.read_exact(&mut payload).await?;
+        Ok(payload)
+    }
+
     /// Write a struct to the stream, after first encoding it. The struct must
     /// be serialisable and deserialisable by `serde`.
     pub async fn write_encode<T>(&mut self, message: &T) -> Result<(), StreamIOError>
@@ -88,24 +162,28 @@ impl StreamIO {
     where
         T: Serialize + for<'a> Deserialize<'a>,
     {
-        let mut buf = [0; BUFFER_SIZE];
-        self.stream.read(&mut buf).await?;
-        let result = Ok(decode(&buf.to_vec())?);
+        let payload = self.read_frame().await?;
+        let result = Ok(decode(&payload)?);
         self.last_read = Some(SystemTime::now());
         result
     }
 
     /// Try to read a struct from the stream, after first decoding it. The struct must
-    /// be serialisable and deserialisable by `serde`.
+    /// be serialisable and deserialisable by `serde`. Fails with `WouldBlock` if nothing
+    /// has arrived yet; once a frame has started arriving, waits for the rest of it.
     pub async fn try_read<T>(&mut self) -> Result<T, StreamIOError>
     where
         T: Serialize + for<'a> Deserialize<'a>,
     {
-        let mut buf = [0; BUFFER_SIZE];
-        self.stream.try_read(&mut buf)?;
-        let result = Ok(postcard::from_bytes(&buf)?);
-        self.last_read = Some(SystemTime::now());
-        result
+        let mut byte = [0; 1];
+        let mut buf = ReadBuf::new(&mut byte);
+        match poll_fn(|cx| Poll::Ready(self.stream.poll_peek(cx, &mut buf))).await {
+            Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock).into()),
+            Poll::Ready(peeked) => {
+                peeked?;
+                self.read().await
+            }
+        }
     }
 
     /// Read a `Result` containing the desired struct as `Ok` and a `crate::stream_io::StreamIOError` as `Err`
@@ -0,0 +1,142 @@
+use backend::protocol::message::{Message, MessagePayload, TTL};
+use backend::protocol::queue_id::QueueId;
+use backend::protocol::request::{Publish, SupportedRequest};
+use backend::protocol::routing_key::{DLXPreference, RoutingKey};
+use backend::stream_io::{StreamIO, StreamIOError, DEFAULT_MAX_FRAME_SIZE, PROTOCOL_VERSION};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Two ends of a loopback TCP connection.
+async fn connected_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    (client.unwrap(), server.unwrap().0)
+}
+
+async fn stream_io_pair() -> (StreamIO, StreamIO) {
+    let (client, server) = connected_pair().await;
+    (StreamIO::new(client), StreamIO::new(server))
+}
+
+fn publish_blob(size: usize) -> SupportedRequest {
+    let payload = (0..size).map(|i| (i % 251) as u8).collect();
+    SupportedRequest::Publish(Publish {
+        message: Message::new(
+            MessagePayload::Blob(payload),
+            RoutingKey::new(QueueId::Queue("large".to_string()), DLXPreference::Default),
+            TTL::Permanent,
+        ),
+    })
+}
+
+fn blob_of(request: SupportedRequest) -> Vec<u8> {
+    match request {
+        SupportedRequest::Publish(Publish {
+            message: Message {
+                payload: MessagePayload::Blob(blob),
+                ..
+            },
+        }) => blob,
+        other => panic!("Expected a blob, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn multi_megabyte_payload_arrives_whole() {
+    let (mut client, mut server) = stream_io_pair().await;
+    let size = 8 * 1024 * 1024;
+    let writer = tokio::spawn(async move {
+        client.write_encode(&publish_blob(size)).await.unwrap();
+        client
+    });
+    let received: SupportedRequest = server.read().await.unwrap();
+    writer.await.unwrap();
+    assert_eq!(blob_of(received), blob_of(publish_blob(size)));
+}
+
+#[tokio::test]
+async fn back_to_back_frames_are_not_merged() {
+    let (mut client, mut server) = stream_io_pair().await;
+    let sizes = [3 * 1024 * 1024, 10, 2 * 1024 * 1024 + 1];
+    let writer = tokio::spawn(async move {
+        for size in sizes {
+            client.write_encode(&publish_blob(size)).await.unwrap();
+        }
+        client
+    });
+    for size in sizes {
+        let received: SupportedRequest = server.read().await.unwrap();
+        assert_eq!(blob_of(received).len(), size);
+    }
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn oversized_frame_is_not_sent() {
+    let (mut client, _server) = stream_io_pair().await;
+    client.set_max_frame_size(1024 * 1024);
+    let result = client.write_encode(&publish_blob(2 * 1024 * 1024)).await;
+    assert!(matches!(
+        result,
+        Err(StreamIOError::FrameTooLarge { max: 1048576, .. })
+    ));
+}
+
+#[tokio::test]
+async fn oversized_frame_is_skipped_by_reader() {
+    let (mut client, mut server) = stream_io_pair().await;
+    assert_eq!(server.max_frame_size(), DEFAULT_MAX_FRAME_SIZE);
+    server.set_max_frame_size(1024 * 1024);
+    let writer = tokio::spawn(async move {
+        client.write_encode(&publish_blob(4 * 1024 * 1024)).await.unwrap();
+        client.write_encode(&publish_blob(100)).await.unwrap();
+        client
+    });
+    let result: Result<SupportedRequest, _> = server.read().await;
+    match result {
+        Err(StreamIOError::FrameTooLarge { size, max }) => {
+            assert!(size > 4 * 1024 * 1024);
+            assert_eq!(max, 1024 * 1024);
+        }
+        other => panic!("Expected FrameTooLarge, got {:?}", other),
+    }
+    let next: SupportedRequest = server.read().await.unwrap();
+    assert_eq!(blob_of(next).len(), 100);
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn other_protocol_version_is_refused() {
+    let (mut client, server) = connected_pair().await;
+    let mut server = StreamIO::new(server);
+    client
+        .write_all(&[PROTOCOL_VERSION + 1, 0, 0, 0, 1, 0])
+        .await
+        .unwrap();
+    let result: Result<SupportedRequest, _> = server.read().await;
+    assert!(matches!(
+        result,
+        Err(StreamIOError::UnsupportedVersion(v)) if v == PROTOCOL_VERSION + 1
+    ));
+}
+
+#[tokio::test]
+async fn try_read_without_data_would_block() {
+    let (mut client, mut server) = stream_io_pair().await;
+    let result: Result<SupportedRequest, _> = server.try_read().await;
+    assert!(matches!(
+        result,
+        Err(StreamIOError::Stream(e)) if e.kind() == std::io::ErrorKind::WouldBlock
+    ));
+    client.write_encode(&publish_blob(5000)).await.unwrap();
+    let received: SupportedRequest = loop {
+        match server.try_read().await {
+            Err(StreamIOError::Stream(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                tokio::task::yield_now().await
+            }
+            result => break result.unwrap(),
+        }
+    };
+    assert_eq!(blob_of(received).len(), 5000);
+}
@@ -21,6 +21,9 @@ impl RequestWorker {
     pub async fn run(mut self) -> tokio::io::Result<StreamIO> {
         loop {
             let request: Result<SupportedRequest, StreamIOError> = self.stream_io.read().await;
+            // Frames of another protocol version can't be delimited, so nothing after this
+            // request can be read.
+            let unsupported_version = matches!(request, Err(StreamIOError::UnsupportedVersion(_)));
             let request = request.map_err(|_| RequestError::DecodeError);
             let response = match request {
                 Ok(r) => self
@@ -38,10 +41,16 @@ impl RequestWorker {
                     StreamIOError::Codec(_) => {
                         error!("Failed to encode response.")
                     }
+                    StreamIOError::FrameTooLarge { .. } | StreamIOError::UnsupportedVersion(_) => {
+                        error!("Failed to frame response.")
+                    }
                 }
                 error!("Failed to send response to client: {:?}", e);
                 break;
             }
+            if unsupported_version {
+                break;
+            }
         }
         Ok(self.stream_io)
     }